@@ -0,0 +1,967 @@
+use std::{
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+};
+
+use super::{
+    DEGREE, Item,
+    codec::{Codec, KeyCodec},
+    error::{IndexError, Result},
+    events::{Event, Observer},
+    paging::{Link, Page, PageID, Pager, corrupt},
+};
+
+/// A B+tree kept in the same file format, pager and snapshots as
+/// [`Btree`](super::Btree).
+///
+/// Internal pages hold separator keys only; every item lives in a leaf, and
+/// leaves link to their neighbours so range scans descend once and then walk
+/// the leaf chain. A separator is the shortest key (see
+/// `KeyCodec::separator`) above the subtree to its left and no greater than
+/// the first key of the subtree to its right. It may stay behind after that
+/// key is deleted.
+///
+/// A published leaf that is written moves to a new page but keeps its
+/// serial, which the links of its neighbours name. Following a link checks
+/// that the page still holds that leaf; when it has moved, the neighbour is
+/// found again from the root. Files older than version 7 link leaves by page
+/// id alone, so there a moved leaf's neighbours are rewritten to point at
+/// it, which copies much of the leaf level on the first changes after a
+/// snapshot.
+#[derive(Debug)]
+pub struct BPlusTree<K = i32, V = String> {
+    pub(super) pager: Pager,
+    pub root: Option<PageID>,
+    degree: i32,
+    marker: PhantomData<(K, V)>,
+}
+
+/// The items of a leaf together with its serial and its previous and next
+/// leaf.
+struct Leaf<K, V> {
+    serial: u64,
+    items: Vec<Item<K, V>>,
+    prev: Link,
+    next: Link,
+}
+
+fn not_bplus(id: PageID) -> IndexError {
+    corrupt(id, "expected a B+tree node, found a B-tree page").into()
+}
+
+impl<K: KeyCodec, V: Codec> BPlusTree<K, V> {
+    pub fn new(filename: &str, page_size: usize) -> Result<Self> {
+        Self::with_degree(filename, page_size, DEGREE)
+    }
+
+    /// Creates a tree whose nodes hold between `degree - 1` and `2 * degree` keys.
+    pub fn with_degree(filename: &str, page_size: usize, degree: i32) -> Result<Self> {
+        if degree < 2 {
+            return Err(IndexError::InvalidInput(format!(
+                "Invalid degree {degree}: must be at least 2"
+            )));
+        }
+
+        Ok(BPlusTree {
+            pager: Pager::create(filename, page_size)?,
+            root: None,
+            degree,
+            marker: PhantomData,
+        })
+    }
+
+    /// Opens a snapshot written by a `BPlusTree`. Only the metadata and the
+    /// root page are read; B-tree snapshots are rejected.
+    pub fn load_snapshot(filename: &str, page_size: usize) -> Result<Self> {
+        let (pager, metadata) = Pager::open_snapshot(filename, page_size)?;
+        let root = metadata.root_page_id;
+        match pager.read_page::<K, V>(root)? {
+            Page::Separators { .. } | Page::LinkedLeaf { .. } => {}
+            _ => return Err(not_bplus(root)),
+        }
+
+        Ok(BPlusTree {
+            pager,
+            root: Some(root),
+            degree: metadata.degree as i32,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn is_valid_snapshot(filename: &str, page_size: usize) -> bool {
+        Pager::is_valid_snapshot(filename, page_size)
+    }
+
+    /// Format version of the snapshot file; see `Btree::format_version`.
+    pub fn format_version(&self) -> u32 {
+        self.pager.format_version
+    }
+
+    /// Attaches the observer that receives this tree's structural events;
+    /// `None` makes the tree quiet again.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.pager.set_observer(observer);
+    }
+
+    /// Publishes the current root as a new snapshot; see
+    /// `Pager::publish_snapshot`. Returns how many pages were flushed.
+    pub fn snapshot(&mut self) -> Result<usize> {
+        let Some(root) = self.root else {
+            return Err(IndexError::InvalidInput(
+                "Cannot snapshot empty tree".to_string(),
+            ));
+        };
+        Ok(self.pager.publish_snapshot(root, self.degree as u32)?)
+    }
+
+    fn min_items(&self) -> usize {
+        (self.degree - 1) as usize
+    }
+
+    fn max_items(&self) -> usize {
+        (self.degree * 2) as usize
+    }
+
+    pub fn search(&self, key: &K) -> Result<V> {
+        if let Some(leaf) = self.descend(|keys| keys.partition_point(|k| k <= key))? {
+            let items = self.read_leaf(leaf)?.items;
+            if let Ok(pos) = items.binary_search_by(|item| item.key.cmp(key)) {
+                return Ok(items[pos].val.clone());
+            }
+        }
+        Err(IndexError::NotFound("Key not found".to_string()))
+    }
+
+    /// Iterates over the items whose keys fall within `bounds`, in key order.
+    pub fn range<R: RangeBounds<K>>(&self, bounds: R) -> BPlusRange<'_, K, V> {
+        BPlusRange {
+            tree: self,
+            front: None,
+            back: None,
+            lower: bounds.start_bound().cloned(),
+            upper: bounds.end_bound().cloned(),
+            last_front: None,
+            last_back: None,
+            done: self.root.is_none(),
+        }
+    }
+
+    /// Inserts `item`, failing with `AlreadyExists` if its key is present.
+    pub fn insert(&mut self, item: Item<K, V>) -> Result<()> {
+        self.pager.check_writable()?;
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let id = self.pager.allocate_page()?;
+                let leaf = Leaf {
+                    serial: self.pager.new_serial(id),
+                    items: Vec::new(),
+                    prev: Link::NONE,
+                    next: Link::NONE,
+                };
+                self.write_leaf(id, leaf)?;
+                self.root = Some(id);
+                id
+            }
+        };
+
+        let result = match self.insert_into(root, item) {
+            Ok(Some((separator, right))) => {
+                let id = self.pager.allocate_page().map_err(IndexError::from);
+                id.and_then(|id| {
+                    self.root = Some(id);
+                    self.pager.emit(Event::RootSplit { root: id });
+                    self.write_branch(id, vec![separator], vec![root, right])
+                })
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        self.repoint_relocated()?;
+        result
+    }
+
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        self.pager.check_writable()?;
+        let Some(root) = self.root else {
+            return Err(IndexError::NotFound("Tree is empty".to_string()));
+        };
+
+        let result = self.delete_from(root, key);
+        if result.is_ok() {
+            self.pager.emit(Event::Delete { key });
+        }
+        if result.is_ok()
+            && let Page::Separators { keys, children, .. } = self.pager.read_page::<K, V>(root)?
+            && keys.is_empty()
+        {
+            self.root = Some(children[0]);
+            self.pager.free_page::<K, V>(root)?;
+        }
+        self.repoint_relocated()?;
+
+        result.map(|_| ())
+    }
+
+    /// Follows separators from the root, taking the child `choose` picks in
+    /// each internal node, and returns the leaf reached.
+    fn descend(&self, choose: impl Fn(&[K]) -> usize) -> Result<Option<PageID>> {
+        let Some(mut id) = self.root else {
+            return Ok(None);
+        };
+        loop {
+            match self.pager.read_page::<K, V>(id)? {
+                Page::Separators { keys, children, .. } => id = children[choose(&keys)],
+                Page::LinkedLeaf { .. } => return Ok(Some(id)),
+                _ => return Err(not_bplus(id)),
+            }
+        }
+    }
+
+    /// Returns the leaf after (`forward`) or before the one `key` leads to,
+    /// found by descending from the root.
+    fn neighbour(&self, key: &K, forward: bool) -> Result<Option<PageID>> {
+        let Some(mut id) = self.root else {
+            return Ok(None);
+        };
+        // The last subtree passed on the side the neighbour lies on.
+        let mut beside = None;
+        loop {
+            match self.pager.read_page::<K, V>(id)? {
+                Page::Separators { keys, children, .. } => {
+                    let pos = keys.partition_point(|k| k <= key);
+                    if forward && pos + 1 < children.len() {
+                        beside = Some(children[pos + 1]);
+                    } else if !forward && pos > 0 {
+                        beside = Some(children[pos - 1]);
+                    }
+                    id = children[pos];
+                }
+                Page::LinkedLeaf { .. } => break,
+                _ => return Err(not_bplus(id)),
+            }
+        }
+
+        let Some(mut id) = beside else {
+            return Ok(None);
+        };
+        loop {
+            match self.pager.read_page::<K, V>(id)? {
+                Page::Separators { children, .. } if forward => id = children[0],
+                Page::Separators { children, .. } => id = children[children.len() - 1],
+                Page::LinkedLeaf { .. } => return Ok(Some(id)),
+                _ => return Err(not_bplus(id)),
+            }
+        }
+    }
+
+    /// Returns the leaf `link` names, next to the leaf `key` leads to in the
+    /// direction of `forward`. A link to a leaf that has moved since it was
+    /// written no longer matches the page, and the leaf is found from the
+    /// root instead; that takes a key, which an empty leaf cannot supply.
+    fn follow(
+        &self,
+        link: Link,
+        key: Option<&K>,
+        forward: bool,
+    ) -> Result<Option<(PageID, Leaf<K, V>)>> {
+        if link.page == 0 {
+            return Ok(None);
+        }
+        let id = if self.pager.leaf_serial(link.page)? == Some(link.serial) {
+            link.page
+        } else {
+            let Some(key) = key else {
+                return Err(corrupt(link.page, "an empty leaf links to a leaf that moved").into());
+            };
+            match self.neighbour(key, forward)? {
+                Some(id) => id,
+                None => {
+                    return Err(corrupt(link.page, "leaf links past the end of the tree").into());
+                }
+            }
+        };
+        Ok(Some((id, self.read_leaf(id)?)))
+    }
+
+    fn read_leaf(&self, id: PageID) -> Result<Leaf<K, V>> {
+        match self.pager.read_page::<K, V>(id)? {
+            Page::LinkedLeaf {
+                serial,
+                items,
+                prev,
+                next,
+                ..
+            } => Ok(Leaf {
+                serial,
+                items,
+                prev,
+                next,
+            }),
+            Page::Separators { .. } => {
+                Err(corrupt(id, "expected a leaf, found an internal node").into())
+            }
+            _ => Err(not_bplus(id)),
+        }
+    }
+
+    fn write_leaf(&mut self, id: PageID, leaf: Leaf<K, V>) -> Result<()> {
+        Ok(self.pager.write_page(&Page::LinkedLeaf {
+            id,
+            serial: leaf.serial,
+            items: leaf.items,
+            prev: leaf.prev,
+            next: leaf.next,
+        })?)
+    }
+
+    /// A link to the leaf with `serial` that is, or will be once the current
+    /// operation is done, stored at `id`.
+    fn link_to(&self, id: PageID, serial: u64) -> Link {
+        let page = self.pager.location(id);
+        Link {
+            page,
+            serial: if self.pager.stable_leaves() {
+                serial
+            } else {
+                page as u64
+            },
+        }
+    }
+
+    fn write_branch(&mut self, id: PageID, keys: Vec<K>, children: Vec<PageID>) -> Result<()> {
+        Ok(self
+            .pager
+            .write_page::<K, V>(&Page::Separators { id, keys, children })?)
+    }
+
+    /// Points the `prev` link of leaf `id` at `prev`.
+    fn set_prev(&mut self, id: PageID, prev: Link) -> Result<()> {
+        let mut leaf = self.read_leaf(id)?;
+        if leaf.prev == prev {
+            return Ok(());
+        }
+        leaf.prev = prev;
+        self.write_leaf(id, leaf)
+    }
+
+    /// Points the `next` link of leaf `id` at `next`.
+    fn set_next(&mut self, id: PageID, next: Link) -> Result<()> {
+        let mut leaf = self.read_leaf(id)?;
+        if leaf.next == next {
+            return Ok(());
+        }
+        leaf.next = next;
+        self.write_leaf(id, leaf)
+    }
+
+    /// Inserts into the subtree at `id`. If the node splits, returns the
+    /// separator and the new right sibling for the parent to take in.
+    fn insert_into(&mut self, id: PageID, item: Item<K, V>) -> Result<Option<(K, PageID)>> {
+        match self.pager.read_page::<K, V>(id)? {
+            Page::LinkedLeaf {
+                serial,
+                mut items,
+                prev,
+                next,
+                ..
+            } => {
+                let pos = match items.binary_search_by(|probe| probe.key.cmp(&item.key)) {
+                    Ok(_) => {
+                        return Err(IndexError::Duplicate(format!(
+                            "Key {:?} already exists",
+                            item.key
+                        )));
+                    }
+                    Err(pos) => pos,
+                };
+                self.pager.emit(Event::Insert { key: &item.key });
+                items.insert(pos, item);
+                if items.len() <= self.max_items() {
+                    self.write_leaf(
+                        id,
+                        Leaf {
+                            serial,
+                            items,
+                            prev,
+                            next,
+                        },
+                    )?;
+                    return Ok(None);
+                }
+
+                let right_items = items.split_off(items.len() / 2);
+                let separator = K::separator(&items[items.len() - 1].key, &right_items[0].key);
+                let after =
+                    self.follow(next, Some(&right_items[right_items.len() - 1].key), true)?;
+                let right = self.pager.allocate_page()?;
+                self.pager.emit(Event::Split {
+                    page: id,
+                    sibling: right,
+                });
+                let right_serial = self.pager.new_serial(right);
+                let right_link = self.link_to(right, right_serial);
+                self.write_leaf(
+                    id,
+                    Leaf {
+                        serial,
+                        items,
+                        prev,
+                        next: right_link,
+                    },
+                )?;
+                let mut right_next = Link::NONE;
+                if let Some((next_id, mut leaf)) = after {
+                    leaf.prev = right_link;
+                    let next_serial = leaf.serial;
+                    self.write_leaf(next_id, leaf)?;
+                    right_next = self.link_to(next_id, next_serial);
+                }
+                let right_leaf = Leaf {
+                    serial: right_serial,
+                    items: right_items,
+                    prev: self.link_to(id, serial),
+                    next: right_next,
+                };
+                self.write_leaf(right, right_leaf)?;
+                Ok(Some((separator, right)))
+            }
+            Page::Separators {
+                mut keys,
+                mut children,
+                ..
+            } => {
+                let pos = keys.partition_point(|k| *k <= item.key);
+                let Some((separator, right)) = self.insert_into(children[pos], item)? else {
+                    return Ok(None);
+                };
+                keys.insert(pos, separator);
+                children.insert(pos + 1, right);
+                if keys.len() <= self.max_items() {
+                    self.write_branch(id, keys, children)?;
+                    return Ok(None);
+                }
+
+                // The middle key moves up instead of being copied.
+                let mid = keys.len() / 2;
+                let right_keys = keys.split_off(mid + 1);
+                let right_children = children.split_off(mid + 1);
+                let separator = keys.remove(mid);
+                let right = self.pager.allocate_page()?;
+                self.pager.emit(Event::Split {
+                    page: id,
+                    sibling: right,
+                });
+                self.write_branch(right, right_keys, right_children)?;
+                self.write_branch(id, keys, children)?;
+                Ok(Some((separator, right)))
+            }
+            _ => Err(not_bplus(id)),
+        }
+    }
+
+    /// Deletes `key` from the subtree at `id`, rebalancing children that fall
+    /// below the minimum. Returns how many entries the node has left.
+    fn delete_from(&mut self, id: PageID, key: &K) -> Result<usize> {
+        match self.pager.read_page::<K, V>(id)? {
+            Page::LinkedLeaf {
+                serial,
+                mut items,
+                prev,
+                next,
+                ..
+            } => {
+                let Ok(pos) = items.binary_search_by(|item| item.key.cmp(key)) else {
+                    return Err(IndexError::NotFound(format!("Key {key:?} not found")));
+                };
+                items.remove(pos);
+                let left = items.len();
+                self.write_leaf(
+                    id,
+                    Leaf {
+                        serial,
+                        items,
+                        prev,
+                        next,
+                    },
+                )?;
+                Ok(left)
+            }
+            Page::Separators {
+                mut keys,
+                mut children,
+                ..
+            } => {
+                let pos = keys.partition_point(|k| k <= key);
+                if self.delete_from(children[pos], key)? < self.min_items() {
+                    self.rebalance(&mut keys, &mut children, pos)?;
+                    let left = keys.len();
+                    self.write_branch(id, keys, children)?;
+                    return Ok(left);
+                }
+                Ok(keys.len())
+            }
+            _ => Err(not_bplus(id)),
+        }
+    }
+
+    /// Refills `children[pos]` from a sibling that can spare an entry, or
+    /// merges it with one. The caller writes the parent back.
+    fn rebalance(
+        &mut self,
+        keys: &mut Vec<K>,
+        children: &mut Vec<PageID>,
+        pos: usize,
+    ) -> Result<()> {
+        if pos > 0 && self.entries(children[pos - 1])? > self.min_items() {
+            self.rotate(keys, children, pos - 1, false)
+        } else if pos + 1 < children.len() && self.entries(children[pos + 1])? > self.min_items() {
+            self.rotate(keys, children, pos, true)
+        } else {
+            self.merge(keys, children, pos.saturating_sub(1))
+        }
+    }
+
+    fn entries(&self, id: PageID) -> Result<usize> {
+        match self.pager.read_page::<K, V>(id)? {
+            Page::Separators { keys, .. } => Ok(keys.len()),
+            Page::LinkedLeaf { items, .. } => Ok(items.len()),
+            _ => Err(not_bplus(id)),
+        }
+    }
+
+    /// Moves one entry between `children[at]` and `children[at + 1]`: from
+    /// the right node to the left one when `leftwards`, the other way
+    /// otherwise. The separator between them is updated to match.
+    fn rotate(
+        &mut self,
+        keys: &mut [K],
+        children: &[PageID],
+        at: usize,
+        leftwards: bool,
+    ) -> Result<()> {
+        let (left_id, right_id) = (children[at], children[at + 1]);
+        self.pager.emit(if leftwards {
+            Event::Borrow {
+                page: left_id,
+                from: right_id,
+            }
+        } else {
+            Event::Borrow {
+                page: right_id,
+                from: left_id,
+            }
+        });
+        match (
+            self.pager.read_page::<K, V>(left_id)?,
+            self.pager.read_page::<K, V>(right_id)?,
+        ) {
+            (
+                Page::LinkedLeaf {
+                    serial: left_serial,
+                    items: mut left,
+                    prev: left_prev,
+                    next: left_next,
+                    ..
+                },
+                Page::LinkedLeaf {
+                    serial: right_serial,
+                    items: mut right,
+                    prev: right_prev,
+                    next: right_next,
+                    ..
+                },
+            ) => {
+                if leftwards {
+                    left.push(right.remove(0));
+                } else if let Some(item) = left.pop() {
+                    right.insert(0, item);
+                }
+                keys[at] = K::separator(&left[left.len() - 1].key, &right[0].key);
+                self.write_leaf(
+                    left_id,
+                    Leaf {
+                        serial: left_serial,
+                        items: left,
+                        prev: left_prev,
+                        next: left_next,
+                    },
+                )?;
+                self.write_leaf(
+                    right_id,
+                    Leaf {
+                        serial: right_serial,
+                        items: right,
+                        prev: right_prev,
+                        next: right_next,
+                    },
+                )
+            }
+            (
+                Page::Separators {
+                    keys: mut left,
+                    children: mut left_children,
+                    ..
+                },
+                Page::Separators {
+                    keys: mut right,
+                    children: mut right_children,
+                    ..
+                },
+            ) => {
+                if leftwards {
+                    left.push(std::mem::replace(&mut keys[at], right.remove(0)));
+                    left_children.push(right_children.remove(0));
+                } else if let (Some(key), Some(child)) = (left.pop(), left_children.pop()) {
+                    right.insert(0, std::mem::replace(&mut keys[at], key));
+                    right_children.insert(0, child);
+                }
+                self.write_branch(left_id, left, left_children)?;
+                self.write_branch(right_id, right, right_children)
+            }
+            _ => Err(corrupt(right_id, "siblings are on different levels").into()),
+        }
+    }
+
+    /// Folds `children[at + 1]` into `children[at]` and frees its page.
+    fn merge(&mut self, keys: &mut Vec<K>, children: &mut Vec<PageID>, at: usize) -> Result<()> {
+        let (left_id, right_id) = (children[at], children[at + 1]);
+        match (
+            self.pager.read_page::<K, V>(left_id)?,
+            self.pager.read_page::<K, V>(right_id)?,
+        ) {
+            (
+                Page::LinkedLeaf {
+                    serial,
+                    items: mut left,
+                    prev,
+                    ..
+                },
+                Page::LinkedLeaf {
+                    items: right, next, ..
+                },
+            ) => {
+                // The right leaf may be empty; its separator still leads to it.
+                let after = self.follow(next, Some(&keys[at]), true)?;
+                left.extend(right);
+                let mut left_next = Link::NONE;
+                if let Some((next_id, leaf)) = &after {
+                    left_next = self.link_to(*next_id, leaf.serial);
+                }
+                let leaf = Leaf {
+                    serial,
+                    items: left,
+                    prev,
+                    next: left_next,
+                };
+                self.write_leaf(left_id, leaf)?;
+                if let Some((next_id, mut leaf)) = after {
+                    leaf.prev = self.link_to(left_id, serial);
+                    self.write_leaf(next_id, leaf)?;
+                }
+            }
+            (
+                Page::Separators {
+                    keys: mut left,
+                    children: mut left_children,
+                    ..
+                },
+                Page::Separators {
+                    keys: right,
+                    children: right_children,
+                    ..
+                },
+            ) => {
+                left.push(keys[at].clone());
+                left.extend(right);
+                left_children.extend(right_children);
+                self.write_branch(left_id, left, left_children)?;
+            }
+            _ => return Err(corrupt(right_id, "siblings are on different levels").into()),
+        }
+
+        keys.remove(at);
+        children.remove(at + 1);
+        self.pager.emit(Event::Merge {
+            page: left_id,
+            sibling: right_id,
+        });
+        Ok(self.pager.free_page::<K, V>(right_id)?)
+    }
+
+    /// Like `Btree::repoint_relocated`. In files older than version 7 a
+    /// relocated leaf also needs its neighbours' links pointed at the copy,
+    /// which may relocate them too; newer files find a moved leaf by its
+    /// serial instead.
+    fn repoint_relocated(&mut self) -> Result<()> {
+        while let Some((old, new)) = self.pager.next_unlinked() {
+            if !self.pager.stable_leaves()
+                && let Page::LinkedLeaf { prev, next, .. } = self.pager.read_page::<K, V>(new)?
+            {
+                let moved = self.link_to(new, 0);
+                if prev.page != 0 {
+                    self.set_next(prev.page, moved)?;
+                }
+                if next.page != 0 {
+                    self.set_prev(next.page, moved)?;
+                }
+            }
+
+            if self.root == Some(old) {
+                self.root = Some(new);
+                continue;
+            }
+
+            let (parent, keys, mut children) = self.find_parent(old)?;
+            for child in children.iter_mut().filter(|c| **c == old) {
+                *child = new;
+            }
+            self.write_branch(parent, keys, children)?;
+        }
+        Ok(self.pager.clear_relocations()?)
+    }
+
+    /// Finds the internal node whose children include `id`, following the
+    /// search path of the node's first key and falling back to a full walk.
+    fn find_parent(&self, id: PageID) -> Result<(PageID, Vec<K>, Vec<PageID>)> {
+        let Some(root) = self.root else {
+            return Err(corrupt(id, "page has no parent in an empty tree").into());
+        };
+
+        let first = match self.pager.read_page::<K, V>(id)? {
+            Page::Separators { keys, .. } => keys.first().cloned(),
+            Page::LinkedLeaf { items, .. } => items.first().map(|item| item.key.clone()),
+            _ => None,
+        };
+        if let Some(first) = first {
+            let mut current = root;
+            while let Page::Separators { keys, children, .. } =
+                self.pager.read_page::<K, V>(current)?
+            {
+                if children.contains(&id) {
+                    return Ok((current, keys, children));
+                }
+                current = children[keys.partition_point(|k| *k <= first)];
+            }
+        }
+
+        let mut stack = vec![root];
+        while let Some(page) = stack.pop() {
+            if let Page::Separators { keys, children, .. } = self.pager.read_page::<K, V>(page)? {
+                if children.contains(&id) {
+                    return Ok((page, keys, children));
+                }
+                stack.extend(&children);
+            }
+        }
+        Err(corrupt(id, "page is not reachable from the root").into())
+    }
+}
+
+/// Position of one end of a [`BPlusRange`] within a leaf. For the front end
+/// `pos` is the next item to yield, for the back end it is one past it.
+/// `key` is the last key this end has passed, which finds the next leaf
+/// from the root when an empty leaf's link is stale.
+struct Cursor<K, V> {
+    id: PageID,
+    items: Vec<Item<K, V>>,
+    pos: usize,
+    prev: Link,
+    next: Link,
+    key: Option<K>,
+}
+
+/// In-order iterator over the items of a [`BPlusTree`] whose keys fall
+/// inside a range.
+///
+/// Each end descends to its boundary leaf once and then follows the leaf
+/// links past any empty leaves, descending again only past a leaf that has
+/// moved. A read failure is yielded as an error and ends the iteration.
+/// The two ends stop once they meet.
+pub struct BPlusRange<'a, K, V> {
+    tree: &'a BPlusTree<K, V>,
+    front: Option<Cursor<K, V>>,
+    back: Option<Cursor<K, V>>,
+    lower: Bound<K>,
+    upper: Bound<K>,
+    last_front: Option<K>,
+    last_back: Option<K>,
+    done: bool,
+}
+
+impl<K: KeyCodec, V: Codec> BPlusRange<'_, K, V> {
+    fn seek(&self, back: bool) -> Result<Option<Cursor<K, V>>> {
+        let bound = if back { &self.upper } else { &self.lower };
+        let leaf = self.tree.descend(|keys| match bound {
+            Bound::Included(key) | Bound::Excluded(key) => keys.partition_point(|k| k <= key),
+            Bound::Unbounded if back => keys.len(),
+            Bound::Unbounded => 0,
+        })?;
+        let Some(leaf) = leaf else {
+            return Ok(None);
+        };
+
+        let Leaf {
+            items, prev, next, ..
+        } = self.tree.read_leaf(leaf)?;
+        let pos = if back {
+            items.partition_point(|item| self.below_upper(&item.key))
+        } else {
+            items.partition_point(|item| !self.above_lower(&item.key))
+        };
+        let key = match bound {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key.clone()),
+            Bound::Unbounded => None,
+        };
+        Ok(Some(Cursor {
+            id: leaf,
+            items,
+            pos,
+            prev,
+            next,
+            key,
+        }))
+    }
+
+    fn advance_front(&mut self) -> Result<Option<Item<K, V>>> {
+        if self.front.is_none() {
+            self.front = self.seek(false)?;
+        }
+        let Some(cursor) = self.front.as_mut() else {
+            return Ok(None);
+        };
+
+        while cursor.pos >= cursor.items.len() {
+            if let Some(last) = cursor.items.last() {
+                cursor.key = Some(last.key.clone());
+            }
+            let Some((id, leaf)) = self.tree.follow(cursor.next, cursor.key.as_ref(), true)? else {
+                return Ok(None);
+            };
+            if id == cursor.id {
+                return Err(corrupt(id, "leaf links lead back to the same leaf").into());
+            }
+            let Leaf {
+                items, prev, next, ..
+            } = leaf;
+            *cursor = Cursor {
+                id,
+                items,
+                pos: 0,
+                prev,
+                next,
+                key: cursor.key.take(),
+            };
+        }
+        cursor.pos += 1;
+        Ok(Some(cursor.items[cursor.pos - 1].clone()))
+    }
+
+    fn advance_back(&mut self) -> Result<Option<Item<K, V>>> {
+        if self.back.is_none() {
+            self.back = self.seek(true)?;
+        }
+        let Some(cursor) = self.back.as_mut() else {
+            return Ok(None);
+        };
+
+        while cursor.pos == 0 {
+            if let Some(first) = cursor.items.first() {
+                cursor.key = Some(first.key.clone());
+            }
+            let Some((id, leaf)) = self.tree.follow(cursor.prev, cursor.key.as_ref(), false)?
+            else {
+                return Ok(None);
+            };
+            if id == cursor.id {
+                return Err(corrupt(id, "leaf links lead back to the same leaf").into());
+            }
+            let Leaf {
+                items, prev, next, ..
+            } = leaf;
+            *cursor = Cursor {
+                id,
+                pos: items.len(),
+                items,
+                prev,
+                next,
+                key: cursor.key.take(),
+            };
+        }
+        cursor.pos -= 1;
+        Ok(Some(cursor.items[cursor.pos].clone()))
+    }
+
+    fn below_upper(&self, key: &K) -> bool {
+        match &self.upper {
+            Bound::Included(hi) => key <= hi,
+            Bound::Excluded(hi) => key < hi,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn above_lower(&self, key: &K) -> bool {
+        match &self.lower {
+            Bound::Included(lo) => key >= lo,
+            Bound::Excluded(lo) => key > lo,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<K: KeyCodec, V: Codec> Iterator for BPlusRange<'_, K, V> {
+    type Item = Result<Item<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.advance_front() {
+            Ok(Some(item))
+                if self.below_upper(&item.key)
+                    && self.last_back.as_ref().is_none_or(|back| item.key < *back) =>
+            {
+                self.last_front = Some(item.key.clone());
+                Some(Ok(item))
+            }
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<K: KeyCodec, V: Codec> DoubleEndedIterator for BPlusRange<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.advance_back() {
+            Ok(Some(item))
+                if self.above_lower(&item.key)
+                    && self
+                        .last_front
+                        .as_ref()
+                        .is_none_or(|front| item.key > *front) =>
+            {
+                self.last_back = Some(item.key.clone());
+                Some(Ok(item))
+            }
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, Result},
+    os::unix::fs::FileExt,
+};
+
+use super::{error::IndexError, paging::PageID};
+
+pub type FrameID = usize;
+
+pub const DEFAULT_POOL_CAPACITY: usize = 256;
+
+/// Decides which resident page to drop when the pool is full.
+///
+/// The pool reports every access to a frame and asks for a victim among the
+/// frames it has seen; pinned frames must never be chosen.
+pub trait EvictionPolicy: fmt::Debug + Send {
+    fn record_access(&mut self, frame: FrameID);
+    fn victim(&mut self, is_pinned: &dyn Fn(FrameID) -> bool) -> Option<FrameID>;
+}
+
+/// Evicts the least recently used unpinned frame.
+#[derive(Debug, Default)]
+pub struct Lru {
+    order: VecDeque<FrameID>,
+}
+
+impl EvictionPolicy for Lru {
+    fn record_access(&mut self, frame: FrameID) {
+        if let Some(pos) = self.order.iter().position(|&f| f == frame) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(frame);
+    }
+
+    fn victim(&mut self, is_pinned: &dyn Fn(FrameID) -> bool) -> Option<FrameID> {
+        let pos = self.order.iter().position(|&f| !is_pinned(f))?;
+        self.order.remove(pos)
+    }
+}
+
+/// Second-chance eviction: a clock hand sweeps the frames, clearing reference
+/// bits and evicting the first unpinned frame whose bit is already clear.
+#[derive(Debug, Default)]
+pub struct Clock {
+    referenced: Vec<Option<bool>>,
+    hand: usize,
+}
+
+impl EvictionPolicy for Clock {
+    fn record_access(&mut self, frame: FrameID) {
+        if frame >= self.referenced.len() {
+            self.referenced.resize(frame + 1, None);
+        }
+        self.referenced[frame] = Some(true);
+    }
+
+    fn victim(&mut self, is_pinned: &dyn Fn(FrameID) -> bool) -> Option<FrameID> {
+        let len = self.referenced.len();
+        for _ in 0..2 * len {
+            let frame = self.hand;
+            self.hand = (self.hand + 1) % len;
+
+            match self.referenced[frame] {
+                Some(_) if is_pinned(frame) => {}
+                Some(true) => self.referenced[frame] = Some(false),
+                Some(false) => {
+                    self.referenced[frame] = None;
+                    return Some(frame);
+                }
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hits={} misses={} evictions={} writebacks={}",
+            self.hits, self.misses, self.evictions, self.writebacks
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    page_id: PageID,
+    data: Vec<u8>,
+    dirty: bool,
+    pin_count: u32,
+}
+
+/// Fixed number of in-memory page frames sitting between the pager and the
+/// file. Dirty frames are written back when evicted or flushed.
+///
+/// Pages are read and written at their offset without moving the file
+/// cursor, so read views on other threads can share the tree's file.
+#[derive(Debug)]
+pub struct BufferPool {
+    capacity: usize,
+    frames: Vec<Frame>,
+    free_frames: Vec<FrameID>,
+    page_table: HashMap<PageID, FrameID>,
+    policy: Box<dyn EvictionPolicy>,
+    stats: PoolStats,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize, policy: Box<dyn EvictionPolicy>) -> Result<Self> {
+        if capacity == 0 {
+            return Err(IndexError::InvalidInput(
+                "Buffer pool capacity must be at least 1".to_string(),
+            )
+            .into());
+        }
+
+        Ok(BufferPool {
+            capacity,
+            frames: Vec::new(),
+            free_frames: Vec::new(),
+            page_table: HashMap::new(),
+            policy,
+            stats: PoolStats::default(),
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Brings `page_id` into the pool and pins it. Pages read from disk are
+    /// passed through `verify` before they become visible.
+    pub fn fetch(
+        &mut self,
+        file: &File,
+        page_size: usize,
+        page_id: PageID,
+        verify: &dyn Fn(&[u8]) -> Result<()>,
+    ) -> Result<FrameID> {
+        if let Some(&frame) = self.page_table.get(&page_id) {
+            self.stats.hits += 1;
+            self.pin(frame);
+            return Ok(frame);
+        }
+
+        self.stats.misses += 1;
+        let frame = self.claim_frame(file, page_size)?;
+
+        let loaded = self
+            .read_into(file, page_size, page_id, frame)
+            .and_then(|()| verify(&self.frames[frame].data));
+        if let Err(e) = loaded {
+            self.free_frames.push(frame);
+            return Err(e);
+        }
+
+        self.install(frame, page_id, false);
+        self.pin(frame);
+        Ok(frame)
+    }
+
+    /// Replaces the contents of `page_id` and marks it dirty, without reading
+    /// the old contents from disk.
+    pub fn put(
+        &mut self,
+        file: &File,
+        page_size: usize,
+        page_id: PageID,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let frame = match self.page_table.get(&page_id) {
+            Some(&frame) => frame,
+            None => {
+                let frame = self.claim_frame(file, page_size)?;
+                self.install(frame, page_id, true);
+                frame
+            }
+        };
+
+        self.frames[frame].data = data;
+        self.frames[frame].dirty = true;
+        self.policy.record_access(frame);
+        Ok(())
+    }
+
+    pub fn data(&self, frame: FrameID) -> &[u8] {
+        &self.frames[frame].data
+    }
+
+    pub fn pin(&mut self, frame: FrameID) {
+        self.frames[frame].pin_count += 1;
+        self.policy.record_access(frame);
+    }
+
+    pub fn unpin(&mut self, frame: FrameID) {
+        let pin_count = &mut self.frames[frame].pin_count;
+        *pin_count = pin_count.saturating_sub(1);
+    }
+
+    /// Writes every dirty frame back to the file and returns how many there were.
+    pub fn flush(&mut self, file: &File, page_size: usize) -> Result<usize> {
+        let mut flushed = 0;
+        for frame in 0..self.frames.len() {
+            if self.frames[frame].dirty {
+                self.write_back(file, page_size, frame)?;
+                flushed += 1;
+            }
+        }
+        Ok(flushed)
+    }
+
+    fn read_into(
+        &mut self,
+        file: &File,
+        page_size: usize,
+        page_id: PageID,
+        frame: FrameID,
+    ) -> Result<()> {
+        let data = &mut self.frames[frame].data;
+        data.resize(page_size, 0);
+        file.read_exact_at(data, page_id as u64 * page_size as u64)
+    }
+
+    fn install(&mut self, frame: FrameID, page_id: PageID, dirty: bool) {
+        let f = &mut self.frames[frame];
+        f.page_id = page_id;
+        f.dirty = dirty;
+        f.pin_count = 0;
+        self.page_table.insert(page_id, frame);
+    }
+
+    /// Returns an unused frame, evicting (and writing back) a victim if the
+    /// pool is at capacity.
+    fn claim_frame(&mut self, file: &File, page_size: usize) -> Result<FrameID> {
+        if let Some(frame) = self.free_frames.pop() {
+            return Ok(frame);
+        }
+
+        if self.frames.len() < self.capacity {
+            self.frames.push(Frame {
+                page_id: 0,
+                data: vec![0u8; page_size],
+                dirty: false,
+                pin_count: 0,
+            });
+            return Ok(self.frames.len() - 1);
+        }
+
+        let frames = &self.frames;
+        let victim = self
+            .policy
+            .victim(&|frame| frames[frame].pin_count > 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    "Buffer pool exhausted: every frame is pinned",
+                )
+            })?;
+
+        if self.frames[victim].dirty {
+            self.write_back(file, page_size, victim)?;
+        }
+        self.page_table.remove(&self.frames[victim].page_id);
+        self.stats.evictions += 1;
+        Ok(victim)
+    }
+
+    fn write_back(&mut self, file: &File, page_size: usize, frame: FrameID) -> Result<()> {
+        let f = &mut self.frames[frame];
+        file.write_all_at(&f.data, f.page_id as u64 * page_size as u64)?;
+        f.dirty = false;
+        self.stats.writebacks += 1;
+        Ok(())
+    }
+}
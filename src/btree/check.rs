@@ -0,0 +1,229 @@
+use std::{collections::HashSet, fmt, marker::PhantomData};
+
+use super::{
+    Btree, Item,
+    codec::{Codec, KeyCodec},
+    error::{IndexError, Result},
+    metadata::BtreeMetadata,
+    node::{Node, cmp_item},
+    paging::{Page, PageID, Pager},
+};
+
+/// A broken structural invariant, reported by `Btree::check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub page: PageID,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Page {}: {}", self.page, self.message)
+    }
+}
+
+/// State shared by the whole walk.
+struct Walk {
+    visited: HashSet<PageID>,
+    leaf_depth: Option<usize>,
+    violations: Vec<Violation>,
+}
+
+impl Walk {
+    fn report(&mut self, page: PageID, message: impl Into<String>) {
+        self.violations.push(Violation {
+            page,
+            message: message.into(),
+        });
+    }
+}
+
+impl<K: KeyCodec, V: Codec> Btree<K, V> {
+    /// Walks the whole tree and returns every broken invariant: item order
+    /// within and across nodes, item and child counts, recorded subtree
+    /// sizes, occupancy, leaf depth and child page ids. Pages that cannot be
+    /// read are reported too.
+    pub fn check(&self) -> Vec<Violation> {
+        let mut walk = Walk {
+            visited: HashSet::new(),
+            leaf_depth: None,
+            violations: Vec::new(),
+        };
+        if let Some(root) = self.root {
+            self.check_subtree(root, 0, None, None, &mut walk);
+        }
+        walk.violations
+    }
+
+    /// Whether `a` must sort strictly before `b`: by key, then by value in
+    /// trees that allow duplicate keys.
+    fn in_order(&self, a: &Item<K, V>, b: &Item<K, V>) -> bool {
+        cmp_item(a, &b.key, Some(&b.val), self.values).is_lt()
+    }
+
+    /// Checks the subtree at `id` and returns how many items it holds.
+    fn check_subtree(
+        &self,
+        id: PageID,
+        depth: usize,
+        lower: Option<&Item<K, V>>,
+        upper: Option<&Item<K, V>>,
+        walk: &mut Walk,
+    ) -> u64 {
+        if !walk.visited.insert(id) {
+            walk.report(id, "page is referenced more than once");
+            return 0;
+        }
+        if self.pager.is_free(id) {
+            walk.report(id, "page is referenced by the tree but marked free");
+        }
+        let node: Node<K, V> = match self.read_node(id) {
+            Ok(node) => node,
+            Err(e) => {
+                walk.report(id, format!("unreadable: {e}"));
+                return 0;
+            }
+        };
+        let is_root = Some(id) == self.root;
+
+        let len = node.items.len() as i32;
+        if len > self.max_items() {
+            walk.report(
+                id,
+                format!(
+                    "holds {len} items, more than the maximum {}",
+                    self.max_items()
+                ),
+            );
+        }
+        if !is_root && len < self.min_items() {
+            walk.report(
+                id,
+                format!(
+                    "holds {len} items, fewer than the minimum {}",
+                    self.min_items()
+                ),
+            );
+        }
+
+        for pair in node.items.windows(2) {
+            if !self.in_order(&pair[0], &pair[1]) {
+                walk.report(
+                    id,
+                    format!(
+                        "key {:?} is not ordered before {:?}",
+                        pair[0].key, pair[1].key
+                    ),
+                );
+            }
+        }
+        if let (Some(lower), Some(first)) = (lower, node.items.first())
+            && !self.in_order(lower, first)
+        {
+            walk.report(
+                id,
+                format!(
+                    "key {:?} is not above the separator {:?}",
+                    first.key, lower.key
+                ),
+            );
+        }
+        if let (Some(upper), Some(last)) = (upper, node.items.last())
+            && !self.in_order(last, upper)
+        {
+            walk.report(
+                id,
+                format!(
+                    "key {:?} is not below the separator {:?}",
+                    last.key, upper.key
+                ),
+            );
+        }
+
+        if node.children.is_empty() {
+            match walk.leaf_depth {
+                None => walk.leaf_depth = Some(depth),
+                Some(expected) if expected != depth => walk.report(
+                    id,
+                    format!("leaf at depth {depth}, other leaves are at depth {expected}"),
+                ),
+                Some(_) => {}
+            }
+            return node.items.len() as u64;
+        }
+
+        if node.children.len() != node.items.len() + 1 {
+            walk.report(
+                id,
+                format!(
+                    "internal node with {} items has {} children",
+                    node.items.len(),
+                    node.children.len()
+                ),
+            );
+            return node.items.len() as u64;
+        }
+        let mut size = node.items.len() as u64;
+        for (i, &child) in node.children.iter().enumerate() {
+            if child == 0 || child > self.pager.num_pages {
+                walk.report(
+                    id,
+                    format!(
+                        "child {child} is outside the file's pages 1..={}",
+                        self.pager.num_pages
+                    ),
+                );
+                continue;
+            }
+            let lower = if i == 0 {
+                lower
+            } else {
+                Some(&node.items[i - 1])
+            };
+            let upper = node.items.get(i).or(upper);
+            let held = self.check_subtree(child, depth + 1, lower, upper, walk);
+            if self.pager.counts_subtrees() && node.counts[i] != held {
+                walk.report(
+                    id,
+                    format!(
+                        "child {child} is recorded with {} items but holds {held}",
+                        node.counts[i]
+                    ),
+                );
+            }
+            size += held;
+        }
+        size
+    }
+}
+
+impl<K: KeyCodec, V: Codec + Ord> Btree<K, V> {
+    /// Runs `check` against the newest snapshot published in `filename`
+    /// without opening it as a tree, so a snapshot the loader rejects can
+    /// still be inspected. Trees with duplicate keys are checked in value
+    /// order as well; B+tree files are refused.
+    pub fn check_file(filename: &str, page_size: usize) -> Result<Vec<Violation>> {
+        let (pager, metadata) = Pager::open_snapshot(filename, page_size)?;
+        let root = match metadata.root_page_id {
+            0 => 1,
+            id => id,
+        };
+        if let Ok(Page::Separators { .. } | Page::LinkedLeaf { .. }) = pager.read_page::<K, V>(root)
+        {
+            return Err(IndexError::InvalidInput(format!(
+                "{filename} holds a B+tree; only B-tree snapshots can be checked"
+            )));
+        }
+
+        let multimap = metadata.flags & BtreeMetadata::MULTIMAP != 0;
+        let btree: Btree<K, V> = Btree {
+            pager,
+            root: Some(root),
+            degree: metadata.degree as i32,
+            wal: None,
+            values: multimap.then_some(V::cmp),
+            marker: PhantomData,
+        };
+        Ok(btree.check())
+    }
+}
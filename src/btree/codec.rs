@@ -0,0 +1,189 @@
+use std::{
+    fmt::Debug,
+    io::{self, Result},
+};
+
+/// On-page encoding for anything stored in a B-tree page.
+///
+/// `encode` appends the bytes to `buf`; `decode` reads one value from the
+/// front of `buf` and returns it together with the number of bytes consumed.
+pub trait Codec: Clone + Debug + Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+    fn decode(buf: &[u8]) -> Result<(Self, usize)>;
+}
+
+/// Keys need a total order on top of their encoding.
+///
+/// Node pages can store each key as the bytes from `key_bytes` minus the
+/// prefix it shares with the key before it, and do so when that makes the
+/// page smaller, so those bytes should start with whatever neighbouring keys
+/// tend to have in common.
+pub trait KeyCodec: Codec + Ord {
+    /// Appends the bytes prefix compression works on.
+    fn key_bytes(&self, buf: &mut Vec<u8>) {
+        self.encode(buf);
+    }
+
+    /// Rebuilds a key from exactly the bytes `key_bytes` wrote.
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self> {
+        match Self::decode(bytes)? {
+            (key, used) if used == bytes.len() => Ok(key),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Trailing bytes after key in page",
+            )),
+        }
+    }
+
+    /// Returns the shortest key `s` with `left < s <= right`, which routes
+    /// lookups between two B+tree nodes as well as `right` does. `left`
+    /// must be below `right`.
+    fn separator(_left: &Self, right: &Self) -> Self {
+        right.clone()
+    }
+}
+
+/// Length of the shortest prefix of `right` that sorts above `left`, given
+/// `left < right`.
+fn shortest_above(left: &[u8], right: &[u8]) -> usize {
+    let common = left.iter().zip(right).take_while(|(a, b)| a == b).count();
+    (common + 1).min(right.len())
+}
+
+/// Appends `n` as a varint: seven bits per byte, low bits first, with the
+/// top bit set on every byte but the last.
+pub fn put_varint(mut n: usize, buf: &mut Vec<u8>) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Reads a varint of up to 32 bits written by `put_varint`.
+pub fn get_varint(buf: &[u8]) -> Result<(usize, usize)> {
+    let mut n = 0;
+    for (i, &byte) in buf.iter().take(5).enumerate() {
+        n |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((n, i + 1));
+        }
+    }
+    Err(truncated("varint"))
+}
+
+fn truncated(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Truncated {what} in page"),
+    )
+}
+
+fn take<const N: usize>(buf: &[u8], what: &str) -> Result<[u8; N]> {
+    buf.get(..N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| truncated(what))
+}
+
+macro_rules! int_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                    let bytes = take::<{ size_of::<$t>() }>(buf, stringify!($t))?;
+                    Ok((<$t>::from_le_bytes(bytes), size_of::<$t>()))
+                }
+            }
+
+            // Big-endian, so nearby keys share their leading bytes.
+            impl KeyCodec for $t {
+                fn key_bytes(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn from_key_bytes(bytes: &[u8]) -> Result<Self> {
+                    let bytes = bytes.try_into().map_err(|_| truncated(stringify!($t)))?;
+                    Ok(<$t>::from_be_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+int_codec!(u8, i32, u32, i64, u64);
+
+impl Codec for Vec<u8> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self);
+    }
+
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let len = u32::from_le_bytes(take::<4>(buf, "length")?) as usize;
+        let bytes = buf.get(4..4 + len).ok_or_else(|| truncated("bytes"))?;
+        Ok((bytes.to_vec(), 4 + len))
+    }
+}
+
+impl KeyCodec for Vec<u8> {
+    fn key_bytes(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(bytes.to_vec())
+    }
+
+    fn separator(left: &Self, right: &Self) -> Self {
+        right[..shortest_above(left, right)].to_vec()
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let (bytes, used) = Vec::<u8>::decode(buf)?;
+        Ok((String::from_utf8_lossy(&bytes).to_string(), used))
+    }
+}
+
+impl KeyCodec for String {
+    fn key_bytes(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(String::from_utf8_lossy(bytes).to_string())
+    }
+
+    fn separator(left: &Self, right: &Self) -> Self {
+        let mut len = shortest_above(left.as_bytes(), right.as_bytes());
+        while !right.is_char_boundary(len) {
+            len += 1;
+        }
+        right[..len].to_string()
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let (a, used_a) = A::decode(buf)?;
+        let (b, used_b) = B::decode(&buf[used_a..])?;
+        Ok(((a, b), used_a + used_b))
+    }
+}
+
+impl<A: KeyCodec, B: KeyCodec> KeyCodec for (A, B) {}
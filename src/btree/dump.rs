@@ -0,0 +1,439 @@
+use std::{
+    fmt::{self, Write as _},
+    io::{BufRead, Write},
+    str::FromStr,
+};
+
+use super::{
+    Btree, Item,
+    codec::{Codec, KeyCodec},
+    error::{IndexError, Result},
+    node::cmp_item,
+};
+
+/// Text formats for `Btree::export` and `Btree::import`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// One `{"key": .., "value": ..}` object per line.
+    JsonLines,
+    /// A `key,value` header followed by one record per item, quoted as in
+    /// RFC 4180 where needed.
+    Csv,
+}
+
+impl FromStr for DumpFormat {
+    type Err = IndexError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" => Ok(DumpFormat::JsonLines),
+            "csv" => Ok(DumpFormat::Csv),
+            other => Err(IndexError::InvalidInput(format!(
+                "Unknown dump format {other}: expected json or csv"
+            ))),
+        }
+    }
+}
+
+/// Keys and values that can be dumped: written with `Display` and read
+/// back with `FromStr`. Numbers appear bare in JSON, everything else as a
+/// JSON string.
+pub trait DumpField: fmt::Display + FromStr {
+    const NUMERIC: bool = false;
+}
+
+impl DumpField for String {}
+
+macro_rules! numeric_field {
+    ($($t:ty),*) => {
+        $(
+            impl DumpField for $t {
+                const NUMERIC: bool = true;
+            }
+        )*
+    };
+}
+
+numeric_field!(u8, i32, u32, i64, u64);
+
+impl<K: KeyCodec + DumpField, V: Codec + DumpField> Btree<K, V> {
+    /// Writes every item to `out` in key order and returns how many were
+    /// written. Items are streamed, so the tree is never held in memory.
+    pub fn export<W: Write>(&self, mut out: W, format: DumpFormat) -> Result<u64> {
+        if format == DumpFormat::Csv {
+            writeln!(out, "key,value")?;
+        }
+
+        let mut line = String::new();
+        let mut count = 0;
+        for item in self.range(..) {
+            let item = item?;
+            line.clear();
+            match format {
+                DumpFormat::JsonLines => {
+                    line.push_str("{\"key\":");
+                    json_field(&item.key, &mut line);
+                    line.push_str(",\"value\":");
+                    json_field(&item.val, &mut line);
+                    line.push('}');
+                }
+                DumpFormat::Csv => {
+                    csv_field(&item.key.to_string(), &mut line);
+                    line.push(',');
+                    csv_field(&item.val.to_string(), &mut line);
+                }
+            }
+            writeln!(out, "{line}")?;
+            count += 1;
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// Inserts every item of a dump written by `export` and returns how many
+    /// were inserted. Trees that allow duplicate keys take them from the dump
+    /// too.
+    ///
+    /// A failed import changes nothing. The whole dump is parsed and checked
+    /// before anything is inserted: it must not repeat a key, or a pair in a
+    /// tree with duplicate keys, nor hold one already in the tree. If an
+    /// insert still fails, for instance because the item does not fit in a
+    /// page, the items inserted before it are deleted again.
+    pub fn import<R: BufRead>(&mut self, input: R, format: DumpFormat) -> Result<u64> {
+        let items = match format {
+            DumpFormat::JsonLines => read_json_lines(input)?,
+            DumpFormat::Csv => read_csv(input)?,
+        };
+        self.check_new(&items)?;
+
+        for (count, item) in items.iter().enumerate() {
+            if let Err(e) = self.insert(item.clone()) {
+                for item in items[..count].iter().rev() {
+                    match self.values {
+                        Some(_) => self.delete_pair(&item.key, &item.val)?,
+                        None => self.delete(&item.key)?,
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(items.len() as u64)
+    }
+
+    /// Fails with `Duplicate` if `items` repeat a key (a pair, when keys may
+    /// repeat) or hold one already in the tree.
+    fn check_new(&self, items: &[Item<K, V>]) -> Result<()> {
+        let values = self.values;
+        let entry = |item: &Item<K, V>| match values {
+            Some(_) => format!("Pair ({:?}, {:?})", item.key, item.val),
+            None => format!("Key {:?}", item.key),
+        };
+
+        let mut sorted: Vec<&Item<K, V>> = items.iter().collect();
+        sorted.sort_by(|a, b| cmp_item(a, &b.key, Some(&b.val), values));
+        if let Some(pair) = sorted
+            .windows(2)
+            .find(|pair| cmp_item(pair[0], &pair[1].key, Some(&pair[1].val), values).is_eq())
+        {
+            return Err(IndexError::Duplicate(format!(
+                "{} appears more than once in the dump",
+                entry(pair[0])
+            )));
+        }
+
+        for run in sorted.chunk_by(|a, b| a.key == b.key) {
+            let present = match values {
+                Some(cmp) => {
+                    let stored = self.search_all(&run[0].key)?;
+                    run.iter()
+                        .find(|item| stored.iter().any(|val| cmp(val, &item.val).is_eq()))
+                }
+                None => match self.search(&run[0].key) {
+                    Ok(_) => Some(&run[0]),
+                    Err(IndexError::NotFound(_)) => None,
+                    Err(e) => return Err(e),
+                },
+            };
+            if let Some(item) = present {
+                return Err(IndexError::Duplicate(format!(
+                    "{} already exists",
+                    entry(item)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_field<T: DumpField>(field: &T, out: &mut String) {
+    if T::NUMERIC {
+        let _ = write!(out, "{field}");
+        return;
+    }
+
+    out.push('"');
+    for c in field.to_string().chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn csv_field(field: &str, out: &mut String) {
+    if field.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+fn parse_field<T: FromStr>(text: &str, what: &str, line: usize) -> Result<T> {
+    text.parse()
+        .map_err(|_| malformed(line, format!("invalid {what} {text:?}")))
+}
+
+fn malformed(line: usize, reason: impl fmt::Display) -> IndexError {
+    IndexError::InvalidInput(format!("Line {line} of the dump: {reason}"))
+}
+
+fn read_json_lines<K: FromStr, V: FromStr>(input: impl BufRead) -> Result<Vec<Item<K, V>>> {
+    let mut items = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, val) = parse_json_item(&line).map_err(|reason| malformed(i + 1, reason))?;
+        items.push(Item {
+            key: parse_field(&key, "key", i + 1)?,
+            val: parse_field(&val, "value", i + 1)?,
+        });
+    }
+    Ok(items)
+}
+
+fn read_csv<K: FromStr, V: FromStr>(mut input: impl BufRead) -> Result<Vec<Item<K, V>>> {
+    let mut items = Vec::new();
+    let mut record = String::new();
+    let (mut line, mut start) = (0, 0);
+    let mut header = false;
+    loop {
+        if record.is_empty() {
+            start = line + 1;
+        }
+        // Read with the line break, which belongs to the value when it
+        // falls inside a quoted field.
+        if input.read_line(&mut record)? == 0 {
+            break;
+        }
+        line += 1;
+        if record == "\n" || record == "\r\n" {
+            record.clear();
+            continue;
+        }
+
+        let Some(fields) = split_csv(&record) else {
+            continue;
+        };
+        record.clear();
+        match fields.as_slice() {
+            [key, val] if !header => {
+                if key != "key" || val != "value" {
+                    return Err(malformed(start, "expected the header \"key,value\""));
+                }
+                header = true;
+            }
+            [key, val] => items.push(Item {
+                key: parse_field(key, "key", start)?,
+                val: parse_field(val, "value", start)?,
+            }),
+            _ => {
+                return Err(malformed(
+                    start,
+                    format!("expected 2 fields, found {}", fields.len()),
+                ));
+            }
+        }
+    }
+
+    if !record.is_empty() {
+        return Err(malformed(start, "quoted field is never closed"));
+    }
+    if !header {
+        return Err(malformed(1, "expected the header \"key,value\""));
+    }
+    Ok(items)
+}
+
+/// Splits one CSV record, read with its line break, into its fields, or
+/// returns `None` if it ends inside a quoted field.
+fn split_csv(record: &str) -> Option<Vec<String>> {
+    let record = record
+        .strip_suffix('\n')
+        .map_or(record, |rest| rest.strip_suffix('\r').unwrap_or(rest));
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// Parses a `{"key": .., "value": ..}` object. String fields are unescaped;
+/// numbers and other bare tokens are returned as written.
+fn parse_json_item(line: &str) -> std::result::Result<(String, String), String> {
+    let mut json = JsonCursor { rest: line };
+    json.expect('{')?;
+    let (mut key, mut val) = (None, None);
+    loop {
+        let name = json.string()?;
+        json.expect(':')?;
+        let field = json.value()?;
+        match name.as_str() {
+            "key" => key = Some(field),
+            "value" => val = Some(field),
+            other => return Err(format!("unexpected field {other:?}")),
+        }
+        if !json.eat(',') {
+            break;
+        }
+    }
+    json.expect('}')?;
+    if !json.rest.trim().is_empty() {
+        return Err("unexpected text after the object".to_string());
+    }
+
+    match (key, val) {
+        (Some(key), Some(val)) => Ok((key, val)),
+        _ => Err("expected both \"key\" and \"value\"".to_string()),
+    }
+}
+
+struct JsonCursor<'a> {
+    rest: &'a str,
+}
+
+impl JsonCursor<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> std::result::Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{c}'"))
+        }
+    }
+
+    fn value(&mut self) -> std::result::Result<String, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') {
+            return self.string();
+        }
+        let end = self
+            .rest
+            .find(|c: char| c == ',' || c == '}' || c.is_whitespace())
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err("expected a value".to_string());
+        }
+        let (token, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Ok(token.to_string())
+    }
+
+    fn string(&mut self) -> std::result::Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = hex4(&mut chars)?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                let (Some((_, '\\')), Some((_, 'u'))) =
+                                    (chars.next(), chars.next())
+                                else {
+                                    return Err("unpaired surrogate in string".to_string());
+                                };
+                                let low = hex4(&mut chars)?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err("unpaired surrogate in string".to_string());
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or("invalid \\u escape in string")?
+                        }
+                        _ => return Err("invalid escape in string".to_string()),
+                    };
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+        Err("string is never closed".to_string())
+    }
+}
+
+fn hex4(chars: &mut std::str::CharIndices<'_>) -> std::result::Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars
+            .next()
+            .and_then(|(_, c)| c.to_digit(16))
+            .ok_or("invalid \\u escape in string")?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
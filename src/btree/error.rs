@@ -0,0 +1,106 @@
+use std::{error::Error, fmt, io};
+
+use super::paging::PageID;
+
+/// Error returned by the public methods of the trees.
+///
+/// The pager, nodes and WAL work with `io::Error`; the typed variants below
+/// travel through them as the error's payload and are recovered when the
+/// error reaches the tree. Any other `io::Error` becomes `Io`.
+#[derive(Debug)]
+pub enum IndexError {
+    /// The key, or key-value pair, is not in the tree.
+    NotFound(String),
+    /// The key, or key-value pair, is already in the tree.
+    Duplicate(String),
+    /// A page failed its integrity checks. Page 0 is the metadata page.
+    Corrupt {
+        page: PageID,
+        reason: String,
+    },
+    /// A node does not fit in its page even with its values moved to
+    /// overflow pages.
+    PageFull {
+        page: PageID,
+        needed: usize,
+        page_size: usize,
+    },
+    /// The snapshot was written in a format newer than this build reads.
+    VersionMismatch {
+        found: u32,
+        supported: u32,
+    },
+    /// An argument or the state of the tree rules out the operation.
+    InvalidInput(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, IndexError>;
+
+impl IndexError {
+    /// The `io::ErrorKind` this error maps to, and is carried under when it
+    /// passes through the pager.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            IndexError::NotFound(_) => io::ErrorKind::NotFound,
+            IndexError::Duplicate(_) => io::ErrorKind::AlreadyExists,
+            IndexError::Corrupt { .. } | IndexError::VersionMismatch { .. } => {
+                io::ErrorKind::InvalidData
+            }
+            IndexError::PageFull { .. } | IndexError::InvalidInput(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            IndexError::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::NotFound(msg)
+            | IndexError::Duplicate(msg)
+            | IndexError::InvalidInput(msg) => {
+                write!(f, "{msg}")
+            }
+            IndexError::Corrupt { page, reason } => write!(f, "Page {page} is corrupt: {reason}"),
+            IndexError::PageFull {
+                page,
+                needed,
+                page_size,
+            } => write!(
+                f,
+                "Page {page} needs {needed} bytes but page size is {page_size}"
+            ),
+            IndexError::VersionMismatch { found, supported } => write!(
+                f,
+                "Snapshot format version {found} is newer than supported version {supported}"
+            ),
+            IndexError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for IndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IndexError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(err: io::Error) -> Self {
+        err.downcast::<IndexError>().unwrap_or_else(IndexError::Io)
+    }
+}
+
+impl From<IndexError> for io::Error {
+    fn from(err: IndexError) -> Self {
+        match err {
+            IndexError::Io(e) => e,
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
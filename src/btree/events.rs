@@ -0,0 +1,107 @@
+use std::fmt;
+
+use super::paging::PageID;
+
+/// A structural change reported to the tree's `Observer`.
+#[derive(Debug)]
+pub enum Event<'a> {
+    Insert {
+        key: &'a dyn fmt::Debug,
+    },
+    Delete {
+        key: &'a dyn fmt::Debug,
+    },
+    /// `page` overflowed and moved its upper half to the new page `sibling`.
+    Split {
+        page: PageID,
+        sibling: PageID,
+    },
+    /// The root split and `root` is the new root above both halves.
+    RootSplit {
+        root: PageID,
+    },
+    /// `page` took an entry from its neighbour `from`.
+    Borrow {
+        page: PageID,
+        from: PageID,
+    },
+    /// `sibling` was folded into `page` and freed.
+    Merge {
+        page: PageID,
+        sibling: PageID,
+    },
+    PageAlloc {
+        page: PageID,
+    },
+    PageFree {
+        page: PageID,
+    },
+}
+
+impl Event<'_> {
+    /// The lowest verbosity at which a `Logger` reports this event.
+    pub fn verbosity(&self) -> Verbosity {
+        match self {
+            Event::Split { .. }
+            | Event::RootSplit { .. }
+            | Event::Borrow { .. }
+            | Event::Merge { .. } => Verbosity::Structure,
+            Event::Insert { .. }
+            | Event::Delete { .. }
+            | Event::PageAlloc { .. }
+            | Event::PageFree { .. } => Verbosity::Detail,
+        }
+    }
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Insert { key } => write!(f, "insert {key:?}"),
+            Event::Delete { key } => write!(f, "delete {key:?}"),
+            Event::Split { page, sibling } => {
+                write!(f, "split page {page} into {page} and {sibling}")
+            }
+            Event::RootSplit { root } => write!(f, "root split, new root {root}"),
+            Event::Borrow { page, from } => write!(f, "page {page} borrowed from page {from}"),
+            Event::Merge { page, sibling } => write!(f, "merged page {sibling} into page {page}"),
+            Event::PageAlloc { page } => write!(f, "allocated page {page}"),
+            Event::PageFree { page } => write!(f, "freed page {page}"),
+        }
+    }
+}
+
+/// Receives every `Event` of the tree it is attached to. Trees are quiet
+/// until one is attached. Observers are `Send` so trees can move between
+/// threads.
+pub trait Observer: fmt::Debug + Send {
+    fn on_event(&mut self, event: &Event<'_>);
+}
+
+/// How much a `Logger` reports: `Structure` covers splits, borrows and
+/// merges, `Detail` adds every insert, delete and page allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Structure,
+    Detail,
+}
+
+/// Writes the events up to its verbosity to stderr.
+#[derive(Debug)]
+pub struct Logger {
+    pub verbosity: Verbosity,
+}
+
+impl Logger {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Logger { verbosity }
+    }
+}
+
+impl Observer for Logger {
+    fn on_event(&mut self, event: &Event<'_>) {
+        if event.verbosity() <= self.verbosity {
+            eprintln!("[btree] {event}");
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! An experiment with latch crabbing on an in-memory B-tree that many
+//! threads can read and write at once.
+//!
+//! It is kept apart from the storage engine: nothing here is paged,
+//! snapshotted or logged. To share the persistent [`Btree`](super::Btree)
+//! between threads, wrap it in a [`SharedBtree`](super::shared::SharedBtree).
+//!
+//! Every node sits behind its own `RwLock` (its latch). Operations descend
+//! with latch crabbing: a thread latches a child before releasing its
+//! parent, and releases the parent as soon as the child is safe, meaning no
+//! change below it can reach back up. Writers make children safe on the way
+//! down instead of fixing nodes up afterwards: an insert splits a full child
+//! before entering it, and a delete tops up a child holding the minimum
+//! number of items by borrowing from or merging with a sibling. A writer
+//! therefore never holds more than a parent, a child and one sibling, and
+//! threads working in different subtrees only share the latches above them.
+//!
+//! The root node never moves: when it splits or its last item is merged
+//! away, its contents move instead, so no extra latch is needed for the
+//! root pointer. Latches are always taken top-down and left to right under
+//! a parent that is held exclusively, which rules out deadlock.
+
+use std::{
+    cmp::Ordering,
+    fmt, mem,
+    ops::{Bound, RangeBounds},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    DEGREE, Item,
+    error::{IndexError, Result},
+};
+
+type Latch<K, V> = Arc<RwLock<Node<K, V>>>;
+
+struct Node<K, V> {
+    items: Vec<Item<K, V>>,
+    children: Vec<Latch<K, V>>,
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn search(&self, key: &K) -> std::result::Result<usize, usize> {
+        self.items.binary_search_by(|item| item.key.cmp(key))
+    }
+}
+
+// No operation panics while holding a latch, so a poisoned one still guards
+// a consistent node.
+fn read<K, V>(latch: &RwLock<Node<K, V>>) -> RwLockReadGuard<'_, Node<K, V>> {
+    latch.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<K, V>(latch: &RwLock<Node<K, V>>) -> RwLockWriteGuard<'_, Node<K, V>> {
+    latch.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn latch<K, V>(node: Node<K, V>) -> Latch<K, V> {
+    Arc::new(RwLock::new(node))
+}
+
+/// A B-tree shared between threads through `&self`, for trying out latch
+/// crabbing. Unlike `Btree` it lives only in memory: there are no pages,
+/// snapshots or write-ahead log.
+pub struct InMemoryLatchedBtree<K = i32, V = String> {
+    root: RwLock<Node<K, V>>,
+    degree: usize,
+}
+
+impl<K, V> fmt::Debug for InMemoryLatchedBtree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryLatchedBtree")
+            .field("degree", &self.degree)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Ord + Clone + fmt::Debug, V: Clone> Default for InMemoryLatchedBtree<K, V> {
+    fn default() -> Self {
+        InMemoryLatchedBtree {
+            root: RwLock::new(Node {
+                items: Vec::new(),
+                children: Vec::new(),
+            }),
+            degree: DEGREE as usize,
+        }
+    }
+}
+
+impl<K: Ord + Clone + fmt::Debug, V: Clone> InMemoryLatchedBtree<K, V> {
+    /// Creates a tree whose nodes hold between `degree - 1` and `2 * degree` items.
+    pub fn with_degree(degree: i32) -> Result<Self> {
+        if degree < 2 {
+            return Err(IndexError::InvalidInput(format!(
+                "Invalid degree {degree}: must be at least 2"
+            )));
+        }
+        Ok(InMemoryLatchedBtree {
+            degree: degree as usize,
+            ..Self::default()
+        })
+    }
+
+    fn min_items(&self) -> usize {
+        self.degree - 1
+    }
+
+    fn max_items(&self) -> usize {
+        2 * self.degree
+    }
+
+    /// Returns the value stored under `key`. Readers only take shared
+    /// latches, one parent and child pair at a time.
+    pub fn search(&self, key: &K) -> Result<V> {
+        Self::search_in(read(&self.root), key)
+    }
+
+    fn search_in(node: RwLockReadGuard<'_, Node<K, V>>, key: &K) -> Result<V> {
+        let pos = match node.search(key) {
+            Ok(pos) => return Ok(node.items[pos].val.clone()),
+            Err(_) if node.is_leaf() => {
+                return Err(IndexError::NotFound("Key not found".to_string()));
+            }
+            Err(pos) => pos,
+        };
+        let child = Arc::clone(&node.children[pos]);
+        let child_node = read(&child);
+        drop(node);
+        Self::search_in(child_node, key)
+    }
+
+    /// Adds `item`, failing with `Duplicate` if its key is already present.
+    pub fn insert(&self, item: Item<K, V>) -> Result<()> {
+        let mut root = write(&self.root);
+        if root.items.len() == self.max_items() {
+            let (median, right) = Self::split(&mut root);
+            let left = Node {
+                items: mem::take(&mut root.items),
+                children: mem::take(&mut root.children),
+            };
+            root.items.push(median);
+            root.children = vec![latch(left), right];
+        }
+        self.insert_into(root, item)
+    }
+
+    /// Inserts below `node`, which is latched exclusively and not full.
+    fn insert_into(
+        &self,
+        mut node: RwLockWriteGuard<'_, Node<K, V>>,
+        item: Item<K, V>,
+    ) -> Result<()> {
+        let pos = match node.search(&item.key) {
+            Ok(_) => return Err(duplicate(&item.key)),
+            Err(pos) => pos,
+        };
+        if node.is_leaf() {
+            node.items.insert(pos, item);
+            return Ok(());
+        }
+
+        let child = Arc::clone(&node.children[pos]);
+        let mut child_node = write(&child);
+        if child_node.items.len() < self.max_items() {
+            drop(node);
+            return self.insert_into(child_node, item);
+        }
+
+        // The child is full: split it while the parent is still latched,
+        // then carry on into whichever half the key belongs to.
+        let (median, right) = Self::split(&mut child_node);
+        let ordering = item.key.cmp(&median.key);
+        node.items.insert(pos, median);
+        node.children.insert(pos + 1, Arc::clone(&right));
+        match ordering {
+            Ordering::Less => {
+                drop(node);
+                self.insert_into(child_node, item)
+            }
+            Ordering::Greater => {
+                drop(child_node);
+                let right_node = write(&right);
+                drop(node);
+                self.insert_into(right_node, item)
+            }
+            Ordering::Equal => Err(duplicate(&item.key)),
+        }
+    }
+
+    /// Moves the upper half of a full `node` into a new sibling, returning
+    /// the median item that separates them.
+    fn split(node: &mut Node<K, V>) -> (Item<K, V>, Latch<K, V>) {
+        let mid = node.items.len() / 2;
+        let items = node.items.split_off(mid + 1);
+        let children = if node.is_leaf() {
+            Vec::new()
+        } else {
+            node.children.split_off(mid + 1)
+        };
+        let median = node.items.remove(mid);
+        (median, latch(Node { items, children }))
+    }
+
+    /// Removes `key`, failing with `NotFound` if it is absent.
+    pub fn delete(&self, key: &K) -> Result<()> {
+        let mut root = write(&self.root);
+        if root.items.len() == 1 && !root.is_leaf() {
+            let min = self.min_items();
+            if root
+                .children
+                .iter()
+                .all(|child| read(child).items.len() == min)
+            {
+                // Both children are at the minimum: fold them into the
+                // root, which lowers the tree by one level.
+                Self::merge(&mut root, 0);
+                let only = root.children.remove(0);
+                let mut only = write(&only);
+                root.items = mem::take(&mut only.items);
+                root.children = mem::take(&mut only.children);
+            }
+        }
+        self.delete_from(root, key)
+    }
+
+    /// Deletes below `node`, which is latched exclusively and, unless it is
+    /// the root, holds more than the minimum number of items.
+    fn delete_from(&self, mut node: RwLockWriteGuard<'_, Node<K, V>>, key: &K) -> Result<()> {
+        let pos = match node.search(key) {
+            Ok(pos) if node.is_leaf() => {
+                node.items.remove(pos);
+                return Ok(());
+            }
+            Ok(pos) => {
+                // Replace the item with its predecessor or successor if
+                // either side can spare one, else merge the two sides.
+                let min = self.min_items();
+                for (child, last) in [(pos, true), (pos + 1, false)] {
+                    if read(&node.children[child]).items.len() > min {
+                        let child = Arc::clone(&node.children[child]);
+                        node.items[pos] = self.remove_edge(write(&child), last);
+                        return Ok(());
+                    }
+                }
+                Self::merge(&mut node, pos);
+                pos
+            }
+            Err(_) if node.is_leaf() => {
+                return Err(IndexError::NotFound(format!("Key {key:?} not found")));
+            }
+            Err(pos) => self.fill_child(&mut node, pos),
+        };
+
+        let child = Arc::clone(&node.children[pos]);
+        let child_node = write(&child);
+        drop(node);
+        self.delete_from(child_node, key)
+    }
+
+    /// Removes and returns the last (or first) item below `node`.
+    fn remove_edge(&self, mut node: RwLockWriteGuard<'_, Node<K, V>>, last: bool) -> Item<K, V> {
+        if node.is_leaf() {
+            let pos = if last { node.items.len() - 1 } else { 0 };
+            return node.items.remove(pos);
+        }
+        let pos = if last { node.children.len() - 1 } else { 0 };
+        let pos = self.fill_child(&mut node, pos);
+        let child = Arc::clone(&node.children[pos]);
+        let child_node = write(&child);
+        drop(node);
+        self.remove_edge(child_node, last)
+    }
+
+    /// Makes sure child `pos` of `node` holds more than the minimum number
+    /// of items, so a delete below it cannot reach `node`. Returns the
+    /// position of the child to descend into, which moves left when the
+    /// child is merged into its left sibling.
+    fn fill_child(&self, node: &mut Node<K, V>, pos: usize) -> usize {
+        let min = self.min_items();
+        let child = Arc::clone(&node.children[pos]);
+        let mut child_node = write(&child);
+        if child_node.items.len() > min {
+            return pos;
+        }
+
+        if pos > 0 {
+            let left = Arc::clone(&node.children[pos - 1]);
+            let mut left_node = write(&left);
+            if left_node.items.len() > min
+                && let Some(moved) = left_node.items.pop()
+            {
+                let separator = mem::replace(&mut node.items[pos - 1], moved);
+                child_node.items.insert(0, separator);
+                if let Some(grandchild) = left_node.children.pop() {
+                    child_node.children.insert(0, grandchild);
+                }
+                return pos;
+            }
+        }
+        if pos + 1 < node.children.len() {
+            let right = Arc::clone(&node.children[pos + 1]);
+            let mut right_node = write(&right);
+            if right_node.items.len() > min {
+                let moved = right_node.items.remove(0);
+                let separator = mem::replace(&mut node.items[pos], moved);
+                child_node.items.push(separator);
+                if !right_node.is_leaf() {
+                    child_node.children.push(right_node.children.remove(0));
+                }
+                return pos;
+            }
+        }
+
+        drop(child_node);
+        if pos + 1 < node.children.len() {
+            Self::merge(node, pos);
+            pos
+        } else {
+            Self::merge(node, pos - 1);
+            pos - 1
+        }
+    }
+
+    /// Merges child `pos + 1` and the item between them into child `pos`.
+    fn merge(node: &mut Node<K, V>, pos: usize) {
+        let separator = node.items.remove(pos);
+        let right = node.children.remove(pos + 1);
+        let left = Arc::clone(&node.children[pos]);
+        let mut left_node = write(&left);
+        let mut right_node = write(&right);
+        left_node.items.push(separator);
+        left_node.items.append(&mut right_node.items);
+        left_node.children.append(&mut right_node.children);
+    }
+
+    /// Returns the items whose keys fall in `range`, in key order.
+    ///
+    /// The scan moves a leaf at a time. Each step descends hand over hand
+    /// under shared latches to the leaf holding the keys after the last one
+    /// returned, copies them and releases the leaf, so writers can run
+    /// between steps. Every key present for the whole scan is returned; one
+    /// written while the scan runs may or may not be.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<Item<K, V>> {
+        let above = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        };
+
+        let mut items = Vec::new();
+        let mut after = range.start_bound().cloned();
+        loop {
+            let step = Self::following(read(&self.root), after.as_ref(), None);
+            let Some(last) = step.last() else {
+                return items;
+            };
+            after = Bound::Excluded(last.key.clone());
+            for item in step {
+                if above(&item.key) {
+                    return items;
+                }
+                items.push(item);
+            }
+        }
+    }
+
+    /// Descends from `node` to the leaf where the keys after `after` start
+    /// and returns the leaf's items from there. If the leaf has none, the
+    /// next key is `next`, the nearest item above the leaf that follows it.
+    fn following(
+        node: RwLockReadGuard<'_, Node<K, V>>,
+        after: Bound<&K>,
+        mut next: Option<Item<K, V>>,
+    ) -> Vec<Item<K, V>> {
+        let pos = node.items.partition_point(|item| match after {
+            Bound::Included(start) => item.key < *start,
+            Bound::Excluded(start) => item.key <= *start,
+            Bound::Unbounded => false,
+        });
+        if node.is_leaf() {
+            return match &node.items[pos..] {
+                [] => next.into_iter().collect(),
+                items => items.to_vec(),
+            };
+        }
+
+        if let Some(item) = node.items.get(pos) {
+            next = Some(item.clone());
+        }
+        let child = Arc::clone(&node.children[pos]);
+        let child_node = read(&child);
+        drop(node);
+        Self::following(child_node, after, next)
+    }
+
+    /// Walks the whole tree under shared latches and describes every broken
+    /// invariant: key order, item and child counts, and leaf depth.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut leaf_depth = None;
+        self.check_node(
+            &read(&self.root),
+            "root".to_string(),
+            0,
+            (None, None),
+            &mut leaf_depth,
+            &mut problems,
+        );
+        problems
+    }
+
+    fn check_node(
+        &self,
+        node: &Node<K, V>,
+        path: String,
+        depth: usize,
+        (low, high): (Option<&K>, Option<&K>),
+        leaf_depth: &mut Option<usize>,
+        problems: &mut Vec<String>,
+    ) {
+        let len = node.items.len();
+        if len > self.max_items() || (depth > 0 && len < self.min_items()) {
+            problems.push(format!("{path}: holds {len} items"));
+        }
+        let keys = || node.items.iter().map(|item| &item.key);
+        if keys().zip(keys().skip(1)).any(|(a, b)| a >= b) {
+            problems.push(format!("{path}: keys are out of order"));
+        }
+        if keys()
+            .any(|key| low.is_some_and(|low| key <= low) || high.is_some_and(|high| key >= high))
+        {
+            problems.push(format!("{path}: keys fall outside {low:?}..{high:?}"));
+        }
+
+        if node.is_leaf() {
+            match *leaf_depth {
+                None => *leaf_depth = Some(depth),
+                Some(expected) if expected != depth => {
+                    problems.push(format!(
+                        "{path}: leaf at depth {depth}, expected {expected}"
+                    ));
+                }
+                Some(_) => {}
+            }
+            return;
+        }
+        if node.children.len() != len + 1 {
+            problems.push(format!(
+                "{path}: {} children for {len} items",
+                node.children.len()
+            ));
+            return;
+        }
+        for (pos, child) in node.children.iter().enumerate() {
+            let low = if pos == 0 {
+                low
+            } else {
+                Some(&node.items[pos - 1].key)
+            };
+            let high = node.items.get(pos).map(|item| &item.key).or(high);
+            self.check_node(
+                &read(child),
+                format!("{path}/{pos}"),
+                depth + 1,
+                (low, high),
+                leaf_depth,
+                problems,
+            );
+        }
+    }
+}
+
+fn duplicate<K: fmt::Debug>(key: &K) -> IndexError {
+    IndexError::Duplicate(format!("Key {key:?} already exists"))
+}
@@ -1,20 +1,48 @@
-use std::io::{self, Result};
+use super::{DEGREE, error::IndexError, paging::corrupt};
+use std::io::Result;
 
 #[derive(Debug, Clone)]
 pub struct BtreeMetadata {
-    magic: [u8; 4],        // Magic bytes "BTRE" for validation
-    version: u32,          // Snapshot format version
-    pub root_page_id: u32, // Which page contains the root
-    pub page_size: u32,    // Page size used
-    pub num_pages: u32,    // Total number of pages
-    created_at: u64,       // Timestamp for validation
+    magic: [u8; 4],          // Magic bytes "BTRE" for validation
+    pub version: u32,        // Snapshot format version
+    pub root_page_id: u32,   // Which page contains the root
+    pub page_size: u32,      // Page size used
+    pub num_pages: u32,      // Total number of pages
+    created_at: u64,         // Timestamp for validation
+    pub degree: u32,         // B-tree order the tree was built with
+    pub free_list_head: u32, // First page of the free list, 0 when empty
+    pub free_pages: u32,     // Number of pages on the free list
+    pub sequence: u64,       // Bumped by every snapshot; picks the newest slot
+    pub flags: u32,          // Tree options, see `MULTIMAP`
 }
 
 impl BtreeMetadata {
     const MAGIC: [u8; 4] = [b'B', b'T', b'R', b'E'];
-    const VERSION: u32 = 1;
+    /// Version 2 added page headers with checksums and a checksummed
+    /// metadata page. Version 3 keeps two metadata slots in page 0 and
+    /// stores the free list in its own pages. Version 4 adds the flags
+    /// field. Version 5 stores subtree item counts in internal pages and
+    /// version 6 prefix-compresses the keys of node pages. Version 7 gives
+    /// B+tree leaves serial numbers that their sibling links name, and only
+    /// prefix-compresses the pages where that saves space. Older files are
+    /// still readable.
+    pub const VERSION: u32 = 7;
+    /// Flag set on trees that allow duplicate keys.
+    pub const MULTIMAP: u32 = 1;
+    /// Each slot sits in its own disk sector so a torn write hits only one.
+    pub const SLOT_SIZE: usize = 512;
+    const CHECKSUM_OFFSET: usize = 32;
+
+    /// End of the fields covered by the checksum in `version`.
+    fn fields_end(version: u32) -> usize {
+        match version {
+            4.. => 56,
+            3 => 52,
+            _ => 44,
+        }
+    }
 
-    pub fn new(root_page_id: u32, page_size: u32, num_pages: u32) -> Self {
+    pub fn new(root_page_id: u32, page_size: u32, num_pages: u32, degree: u32) -> Self {
         BtreeMetadata {
             magic: Self::MAGIC,
             version: Self::VERSION,
@@ -23,8 +51,12 @@ impl BtreeMetadata {
             num_pages,
             created_at: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            degree,
+            free_list_head: 0,
+            free_pages: 0,
+            sequence: 0,
+            flags: 0,
         }
     }
 
@@ -43,8 +75,35 @@ impl BtreeMetadata {
 
         buf.extend_from_slice(&self.created_at.to_le_bytes()); // Timestamp (8 bytes)
 
-        while buf.len() < 4096 {
-            buf.push(0); // Padding to page size
+        buf.extend_from_slice(&self.degree.to_le_bytes()); // Degree (4 bytes)
+
+        buf.extend_from_slice(&[0u8; 4]); // Checksum, filled in below (4 bytes)
+
+        buf.extend_from_slice(&self.free_list_head.to_le_bytes()); // Free list head (4 bytes)
+
+        buf.extend_from_slice(&self.free_pages.to_le_bytes()); // Free page count (4 bytes)
+
+        if self.version >= 3 {
+            buf.extend_from_slice(&self.sequence.to_le_bytes()); // Sequence number (8 bytes)
+        }
+
+        if self.version >= 4 {
+            buf.extend_from_slice(&self.flags.to_le_bytes()); // Flags (4 bytes)
+        }
+
+        if self.version >= 2 {
+            let checksum = Self::checksum(&buf, self.version);
+            buf[Self::CHECKSUM_OFFSET..Self::CHECKSUM_OFFSET + 4]
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+
+        let len = if self.version >= 3 {
+            Self::SLOT_SIZE
+        } else {
+            4096
+        };
+        while buf.len() < len {
+            buf.push(0); // Padding to slot or page size
         }
 
         buf
@@ -52,25 +111,62 @@ impl BtreeMetadata {
 
     pub fn deserialize(data: &[u8]) -> Result<Self> {
         if data.len() < 28 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Metadata too small",
-            ));
+            return Err(corrupt(0, "metadata is too small"));
         }
 
         let magic = [data[0], data[1], data[2], data[3]];
         if magic != Self::MAGIC {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid magic bytes",
-            ));
+            return Err(corrupt(0, "invalid magic bytes"));
         }
 
+        // Newer formats may lay out and checksum their fields differently, so
+        // nothing past the version is trusted to mean the same thing.
         let version = read_u32_le(data, 4);
+        if version > Self::VERSION {
+            return Err(IndexError::VersionMismatch {
+                found: version,
+                supported: Self::VERSION,
+            }
+            .into());
+        }
+        if version >= 2 {
+            if data.len() < Self::fields_end(version) {
+                return Err(corrupt(0, "metadata checksum missing"));
+            }
+            let stored = read_u32_le(data, Self::CHECKSUM_OFFSET);
+            let actual = Self::checksum(data, version);
+            if stored != actual {
+                return Err(corrupt(
+                    0,
+                    format!(
+                        "metadata checksum mismatch (stored {stored:#010x}, computed {actual:#010x})"
+                    ),
+                ));
+            }
+        }
         let root_page_id = read_u32_le(data, 8);
         let page_size = read_u32_le(data, 12);
         let num_pages = read_u32_le(data, 16);
         let created_at = read_u64_le(data, 20);
+        // Snapshots written before the order was configurable leave this zeroed.
+        let degree = match data.get(28..32).map(|_| read_u32_le(data, 28)) {
+            None | Some(0) => DEGREE as u32,
+            Some(degree) => degree,
+        };
+
+        // Absent (zero) in snapshots written before pages were reused.
+        let free_list_head = data.get(36..40).map_or(0, |_| read_u32_le(data, 36));
+        let free_pages = data.get(40..44).map_or(0, |_| read_u32_le(data, 40));
+        let sequence = if version >= 3 {
+            read_u64_le(data, 44)
+        } else {
+            0
+        };
+        let flags = if version >= 4 {
+            read_u32_le(data, 52)
+        } else {
+            0
+        };
 
         Ok(BtreeMetadata {
             magic,
@@ -79,8 +175,21 @@ impl BtreeMetadata {
             page_size,
             num_pages,
             created_at,
+            degree,
+            free_list_head,
+            free_pages,
+            sequence,
+            flags,
         })
     }
+
+    /// CRC32 of the metadata fields, skipping the checksum field itself.
+    fn checksum(data: &[u8], version: u32) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&data[..Self::CHECKSUM_OFFSET]);
+        hasher.update(&data[Self::CHECKSUM_OFFSET + 4..Self::fields_end(version)]);
+        hasher.finalize()
+    }
 }
 
 fn read_u32_le(data: &[u8], offset: usize) -> u32 {
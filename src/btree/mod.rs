@@ -1,117 +1,381 @@
+use buffer_pool::{EvictionPolicy, PoolStats};
+use codec::{Codec, KeyCodec};
 use core::fmt;
+use error::{IndexError, Result};
+use events::{Event, Observer};
 use metadata::BtreeMetadata;
-use node::Node;
-use paging::{Page, Pager};
-use std::io::{self, Result};
+use node::{Node, ValueOrder, cmp_item};
+use paging::{PageID, Pager, corrupt};
+use range::Range;
+use std::{
+    cmp::Ordering,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    path::Path,
+};
+use wal::{Replay, SyncPolicy, Wal, WalRecord};
+pub mod bplus;
+pub mod buffer_pool;
+pub mod check;
+pub mod codec;
+pub mod dump;
+pub mod error;
+pub mod events;
+pub mod latching;
 mod metadata;
 mod node;
 mod paging;
+pub mod range;
+pub mod shared;
+pub mod upgrade;
 pub mod utils;
+pub mod view;
+pub mod wal;
 
 pub const DEGREE: i32 = 2;
-pub const MIN_ITEMS: i32 = DEGREE - 1;
-pub const MAX_ITEMS: i32 = DEGREE * 2;
+/// Snapshot format version this build writes. Older snapshots are still
+/// read; see `upgrade::upgrade_file` to rewrite them in this format.
+pub const FORMAT_VERSION: u32 = BtreeMetadata::VERSION;
 
 #[derive(Clone, Debug)]
-pub struct Item {
-    pub key: i32,
-    pub val: String,
+pub struct Item<K = i32, V = String> {
+    pub key: K,
+    pub val: V,
 }
-impl fmt::Display for Item {
+impl<K: fmt::Display, V: fmt::Display> fmt::Display for Item<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}-{}", self.key, self.val)
     }
 }
 
 #[derive(Debug)]
-pub struct Btree {
+pub struct Btree<K = i32, V = String> {
     pager: Pager,
-    pub root: Option<Box<Node>>,
+    pub root: Option<PageID>,
+    degree: i32,
+    wal: Option<Wal>,
+    /// Set on trees that allow duplicate keys, which keep equal keys sorted
+    /// by value.
+    values: ValueOrder<V>,
+    marker: PhantomData<(K, V)>,
 }
 
-impl fmt::Display for Btree {
+impl<K, V> fmt::Display for Btree<K, V>
+where
+    K: KeyCodec + fmt::Display,
+    V: Codec + fmt::Display,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.root {
-            Some(node) => write!(f, "{node}"),
+        match self.root {
+            Some(id) => self.fmt_subtree(f, id, 0),
             None => write!(f, "<empty tree>"),
         }
     }
 }
-impl Btree {
+
+impl<K, V> Btree<K, V>
+where
+    K: KeyCodec + fmt::Display,
+    V: Codec + fmt::Display,
+{
+    fn fmt_subtree(&self, f: &mut fmt::Formatter<'_>, id: PageID, indent: usize) -> fmt::Result {
+        let node = self.read_node(id).map_err(|_| fmt::Error)?;
+        node.fmt_with_indent(f, indent)?;
+        writeln!(f)?;
+        for &child in &node.children {
+            self.fmt_subtree(f, child, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+impl<K: KeyCodec, V: Codec> Btree<K, V> {
     pub fn new(filename: &str, page_size: usize) -> Result<Self> {
-        let file_exists = std::path::Path::new(filename).exists();
+        Self::with_degree(filename, page_size, DEGREE)
+    }
 
-        let file = if file_exists {
-            std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(filename)?
-        } else {
-            std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(false)
-                .open(filename)?
-        };
+    /// Creates a tree whose nodes hold between `degree - 1` and `2 * degree` items.
+    pub fn with_degree(filename: &str, page_size: usize, degree: i32) -> Result<Self> {
+        if degree < 2 {
+            return Err(IndexError::InvalidInput(format!(
+                "Invalid degree {degree}: must be at least 2"
+            )));
+        }
 
         Ok(Btree {
-            pager: Pager {
-                file,
-                page_size,
-                num_pages: 0,
-            },
+            pager: Pager::create(filename, page_size)?,
             root: None,
+            degree,
+            wal: None,
+            values: None,
+            marker: PhantomData,
         })
     }
 
-    pub fn insert(&mut self, item: Item) {
-        println!("Inserting {:?}", item.key);
-        if self.root.is_none() {
-            let id = self.pager.allocate_page().unwrap();
-            self.root = Some(Box::new(Node::new(id)));
+    /// Replaces the buffer pool with one of `capacity` frames using `policy`.
+    /// Dirty pages in the old pool are written back first.
+    pub fn configure_buffer_pool(
+        &mut self,
+        capacity: usize,
+        policy: Box<dyn EvictionPolicy>,
+    ) -> Result<()> {
+        Ok(self.pager.configure_pool(capacity, policy)?)
+    }
+
+    pub fn buffer_pool_stats(&self) -> PoolStats {
+        self.pager.pool_stats()
+    }
+
+    /// Number of released pages waiting to be reused.
+    pub fn free_pages(&self) -> usize {
+        self.pager.free_pages()
+    }
+
+    /// Format version of the snapshot file, which older files keep until
+    /// they are upgraded.
+    pub fn format_version(&self) -> u32 {
+        self.pager.format_version
+    }
+
+    fn min_items(&self) -> i32 {
+        self.degree - 1
+    }
+
+    fn max_items(&self) -> i32 {
+        self.degree * 2
+    }
+
+    /// Reads the node stored at `id`.
+    pub fn read_node(&self, id: PageID) -> Result<Node<K, V>> {
+        Ok(Node::from_page(&self.pager.read_page(id)?)?)
+    }
+
+    fn write_node(&mut self, node: &Node<K, V>) -> Result<()> {
+        Ok(self.pager.write_page(&node.to_page())?)
+    }
+
+    /// Inserts `item`, failing with `AlreadyExists` if its key is present.
+    pub fn insert(&mut self, item: Item<K, V>) -> Result<()> {
+        self.pager.check_writable()?;
+        if self.wal.is_none() {
+            return self.insert_item(item);
         }
-        let root_is_full = if let Some(root_node) = self.root.as_ref() {
-            root_node.num_items >= MAX_ITEMS
-        } else {
-            false
+        self.insert_item(item.clone())?;
+        self.log(|wal| wal.log_insert(&item))
+    }
+
+    /// Stores `item`, replacing the value if its key is present. Returns the
+    /// value it replaced.
+    pub fn upsert(&mut self, item: Item<K, V>) -> Result<Option<V>> {
+        self.pager.check_writable()?;
+        let old = match self.replace_value(&item.key, item.val.clone())? {
+            Some(old) => Some(old),
+            None => {
+                self.insert_item(item.clone())?;
+                None
+            }
+        };
+        self.log(|wal| wal.log_upsert(&item))?;
+        Ok(old)
+    }
+
+    /// Replaces the value stored under `item.key` and returns the old one,
+    /// failing with `NotFound` if the key is absent.
+    pub fn update(&mut self, item: Item<K, V>) -> Result<V> {
+        self.pager.check_writable()?;
+        let old = self
+            .replace_value(&item.key, item.val.clone())?
+            .ok_or_else(|| IndexError::NotFound(format!("Key {:?} not found", item.key)))?;
+        self.log(|wal| wal.log_update(&item))?;
+        Ok(old)
+    }
+
+    /// Appends a record to the write-ahead log, if one is attached. Records
+    /// are only written once their operation has succeeded, so replaying the
+    /// log never meets one that the tree rejected.
+    fn log(&mut self, record: impl FnOnce(&mut Wal) -> std::io::Result<()>) -> Result<()> {
+        match &mut self.wal {
+            Some(wal) => Ok(record(wal)?),
+            None => Ok(()),
+        }
+    }
+
+    fn insert_item(&mut self, item: Item<K, V>) -> Result<()> {
+        let root_id = match self.root {
+            Some(id) => id,
+            None => {
+                let id = self.pager.allocate_page()?;
+                self.write_node(&Node::new(id))?;
+                self.root = Some(id);
+                id
+            }
+        };
+
+        let mut root_node = self.read_node(root_id)?;
+        if root_node.num_items >= self.max_items() {
+            root_node = self.split_root(root_node)?;
+        }
+
+        let max_items = self.max_items();
+        let result = root_node.insert(item, &mut self.pager, max_items, self.values);
+        self.repoint_relocated()?;
+        Ok(result?)
+    }
+
+    /// Swaps the value stored under `key` for `val` and returns the old one,
+    /// or `None` (writing nothing) if the key is absent.
+    fn replace_value(&mut self, key: &K, val: V) -> Result<Option<V>> {
+        if self.values.is_some() {
+            return Err(IndexError::InvalidInput("Values of a tree with duplicate keys cannot be replaced; delete the pair and insert the new one".to_string()));
+        }
+
+        let mut current_id = self.root;
+        while let Some(id) = current_id {
+            let mut node = self.read_node(id)?;
+            let (pos, found) = node.search(key);
+            if found {
+                let old = std::mem::replace(&mut node.items[pos as usize].val, val);
+                let result = self.write_node(&node);
+                self.repoint_relocated()?;
+                return result.map(|()| Some(old));
+            }
+            current_id = node.children.get(pos as usize).copied();
+        }
+        Ok(None)
+    }
+
+    /// Builds the tree bottom-up from `items`, which must be sorted by key
+    /// with no duplicates. Nodes are packed to `fill_factor` of their capacity
+    /// (between 0 and 1) and written level by level, leaves first. The tree
+    /// must be empty; nothing is written if the input is rejected. Returns the
+    /// number of items loaded. Items are not logged to the write-ahead log, so
+    /// take a snapshot to make them durable.
+    pub fn bulk_load<I>(&mut self, items: I, fill_factor: f64) -> Result<usize>
+    where
+        I: IntoIterator<Item = Item<K, V>>,
+    {
+        self.pager.check_writable()?;
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(IndexError::InvalidInput(format!(
+                "Invalid fill factor {fill_factor}: must be in (0, 1]"
+            )));
+        }
+
+        let old_root = match self.root {
+            Some(id) => {
+                let root = self.read_node(id)?;
+                if root.num_items > 0 {
+                    return Err(IndexError::InvalidInput(
+                        "Bulk load requires an empty tree".to_string(),
+                    ));
+                }
+                Some(id)
+            }
+            None => None,
         };
 
-        if root_is_full {
-            println!("Root is full {:?}", self.root.clone().unwrap().num_items);
-            self.split_root();
+        let items: Vec<Item<K, V>> = items.into_iter().collect();
+        if let Some(pair) = items.windows(2).find(|pair| pair[0].key >= pair[1].key) {
+            return Err(IndexError::InvalidInput(format!(
+                "Bulk load input is not sorted: key {:?} follows {:?}",
+                pair[1].key, pair[0].key
+            )));
+        }
+        if items.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(id) = old_root {
+            self.pager.free_page::<K, V>(id)?;
         }
 
-        if let Some(root_node) = self.root.as_mut() {
-            root_node.insert(item, &mut self.pager);
+        let loaded = items.len();
+        let target = ((self.max_items() as f64 * fill_factor).round() as i32)
+            .clamp(self.min_items().max(1), self.max_items());
+
+        let mut level = items;
+        let mut children: Option<Vec<(PageID, u64)>> = None;
+        loop {
+            let sizes = self.plan_level(level.len(), target);
+            let mut nodes = Vec::with_capacity(sizes.len());
+            let mut separators = Vec::with_capacity(sizes.len() - 1);
+            let mut items = level.into_iter();
+            let mut child_ids = children.map(|c| c.into_iter());
+
+            for (i, &size) in sizes.iter().enumerate() {
+                let mut node = Node::new(self.pager.allocate_page()?);
+                node.items = items.by_ref().take(size).collect();
+                node.num_items = size as i32;
+                if let Some(child_ids) = child_ids.as_mut() {
+                    (node.children, node.counts) = child_ids.by_ref().take(size + 1).unzip();
+                    node.num_children = size as i32 + 1;
+                }
+                self.write_node(&node)?;
+                nodes.push((node.id, node.size()));
+
+                if i + 1 < sizes.len() {
+                    separators.extend(items.next());
+                }
+            }
+
+            if nodes.len() == 1 {
+                self.root = Some(nodes[0].0);
+                return Ok(loaded);
+            }
+            level = separators;
+            children = Some(nodes);
         }
     }
 
-    fn split_root(&mut self) {
-        let mut old_root = self
-            .root
-            .take()
-            .expect("Called split_root on an empty tree.");
+    /// Splits `n` items into nodes of about `target` items each, leaving one
+    /// item between neighbours to become their separator one level up. Every
+    /// node stays within the item bounds unless the level fits in one node.
+    fn plan_level(&self, n: usize, target: i32) -> Vec<usize> {
+        let (min, max) = (self.min_items() as usize, self.max_items() as usize);
+        if n <= max {
+            return vec![n];
+        }
+
+        let fewest = (n + 1).div_ceil(max + 1);
+        let most = ((n + 1) / (min + 1)).max(fewest);
+        let nodes = (n + 1).div_ceil(target as usize + 1).clamp(fewest, most);
+
+        let in_nodes = n - (nodes - 1);
+        (0..nodes)
+            .map(|i| in_nodes / nodes + usize::from(i < in_nodes % nodes))
+            .collect()
+    }
 
-        let (mid_item, new_node) = old_root.split(&mut self.pager).unwrap();
-        let new_root_id = self.pager.allocate_page().unwrap();
+    fn split_root(&mut self, mut old_root: Node<K, V>) -> Result<Node<K, V>> {
+        let (mid_item, new_node) = old_root.split(&mut self.pager)?;
+        let new_root_id = self.pager.allocate_page()?;
         let mut new_root = Node::new(new_root_id);
 
         new_root.insert_item_at(0, mid_item);
-        new_root.insert_child_at(0, *old_root);
-        new_root.insert_child_at(1, new_node);
-        self.root = Some(Box::new(new_root));
-
-        println!(
-            "Root split. New num_items {:?}",
-            self.root.clone().unwrap().num_items
-        );
+        new_root.insert_child_at(0, old_root.id, old_root.size());
+        new_root.insert_child_at(1, new_node.id, new_node.size());
+
+        self.write_node(&old_root)?;
+        self.write_node(&new_node)?;
+        self.write_node(&new_root)?;
+        self.root = Some(new_root_id);
+        self.pager.emit(Event::RootSplit { root: new_root_id });
+        Ok(new_root)
     }
 
-    pub fn search(&self, key: i32) -> Result<String> {
-        let mut current_node_opt = self.root.as_deref();
+    /// Returns the value stored under `key`; with duplicate keys, the
+    /// smallest one.
+    pub fn search(&self, key: &K) -> Result<V> {
+        if self.values.is_some() {
+            return match self.range(key..=key).next() {
+                Some(item) => Ok(item?.val),
+                None => Err(IndexError::NotFound("Key not found".to_string())),
+            };
+        }
+
+        let mut current_id = self.root;
 
-        while let Some(current_node) = current_node_opt {
+        while let Some(id) = current_id {
+            let current_node = self.read_node(id)?;
             let (pos, found) = current_node.search(key);
 
             if found {
@@ -119,261 +383,507 @@ impl Btree {
                 return Ok(val.clone());
             }
 
-            current_node_opt = current_node
-                .children
-                .get(pos as usize)
-                .map(|boxed_node| &**boxed_node);
+            current_id = current_node.children.get(pos as usize).copied();
         }
 
-        Err(io::Error::new(io::ErrorKind::NotFound, "Key not found"))
+        Err(IndexError::NotFound("Key not found".to_string()))
     }
 
-    pub fn delete(&mut self, key: i32) -> Result<()> {
-        if self.root.is_none() {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Tree is empty"));
-        }
-
-        let mut root = self.root.take().unwrap();
-        let result = self.delete_recursive(&mut root, key);
+    /// Every value stored under `key`, in value order when the tree allows
+    /// duplicate keys.
+    pub fn search_all(&self, key: &K) -> Result<Vec<V>> {
+        self.range(key..=key)
+            .map(|item| item.map(|item| item.val))
+            .collect()
+    }
 
-        if root.num_items == 0 && !root.is_leaf() {
-            self.root = Some(root.children.remove(0));
-        } else {
-            self.root = Some(root);
-        }
+    /// Iterates over the items whose keys fall within `bounds`, in key order.
+    pub fn range<R: RangeBounds<K>>(&self, bounds: R) -> Range<'_, K, V> {
+        Range::new(&self.pager, self.root, bounds, self.values)
+    }
 
-        result
+    /// Returns how many items have a key below `key`: the position of its
+    /// first item in key order, or where it would go if absent.
+    pub fn rank(&self, key: &K) -> Result<u64> {
+        self.count_below(key, false)
     }
 
-    fn delete_recursive(&mut self, node: &mut Node, key: i32) -> Result<()> {
-        let (pos, found) = node.search(key);
+    /// Returns the item at position `k` (from 0) in key order; with
+    /// duplicate keys, equal keys are ordered by value.
+    pub fn select(&self, k: u64) -> Result<Item<K, V>> {
+        let mut remaining = k;
+        let mut current_id = self.root;
+        'descend: while let Some(id) = current_id {
+            let node = self.read_node(id)?;
+            if node.is_leaf() {
+                if let Some(item) = node.items.get(remaining as usize) {
+                    return Ok(item.clone());
+                }
+                break;
+            }
 
-        if node.is_leaf() {
-            if found {
-                self.delete_from_leaf(node, pos)
-            } else {
-                Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Key {key} not found"),
-                ))
+            for (i, &child) in node.children.iter().enumerate() {
+                let size = self.child_size(&node, i)?;
+                if remaining < size {
+                    current_id = Some(child);
+                    continue 'descend;
+                }
+                remaining -= size;
+                if let Some(item) = node.items.get(i) {
+                    if remaining == 0 {
+                        return Ok(item.clone());
+                    }
+                    remaining -= 1;
+                }
             }
-        } else if found {
-            self.delete_from_internal(node, pos)
-        } else {
-            self.delete_from_subtree(node, pos, key)
+            break;
         }
+        Err(IndexError::NotFound(format!("No item at position {k}")))
     }
-    pub fn snapshot(&mut self) -> Result<()> {
-        if self.root.is_none() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Cannot snapshot empty tree",
-            ));
-        }
 
-        let root_page_id = self.root.as_ref().unwrap().id;
-        let metadata = BtreeMetadata::new(
-            root_page_id,
-            self.pager.page_size as u32,
-            self.pager.num_pages,
-        );
-        self.pager.write_metadata(&metadata)?;
+    /// Counts the items whose keys fall within `bounds`.
+    pub fn count<R: RangeBounds<K>>(&self, bounds: R) -> Result<u64> {
+        let start = match bounds.start_bound() {
+            Bound::Included(key) => self.count_below(key, false)?,
+            Bound::Excluded(key) => self.count_below(key, true)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match bounds.end_bound() {
+            Bound::Included(key) => self.count_below(key, true)?,
+            Bound::Excluded(key) => self.count_below(key, false)?,
+            Bound::Unbounded => match self.root {
+                Some(root) if self.pager.counts_subtrees() => self.read_node(root)?.size(),
+                Some(root) => self.walk_size(root)?,
+                None => 0,
+            },
+        };
+        Ok(end.saturating_sub(start))
+    }
 
-        if let Some(root) = self.root.clone() {
-            self.snapshot_node(&root)?;
+    /// Counts the items with a key below `key`, or also equal to it when
+    /// `inclusive`, adding up the subtrees left of the search path.
+    fn count_below(&self, key: &K, inclusive: bool) -> Result<u64> {
+        let mut below = 0;
+        let mut current_id = self.root;
+        while let Some(id) = current_id {
+            let node = self.read_node(id)?;
+            let (pos, _) = node.search_by(|item| match item.key.cmp(key) {
+                Ordering::Equal if inclusive => Ordering::Less,
+                Ordering::Equal => Ordering::Greater,
+                order => order,
+            });
+            below += pos as u64;
+            if !node.is_leaf() {
+                for i in 0..pos as usize {
+                    below += self.child_size(&node, i)?;
+                }
+            }
+            current_id = node.children.get(pos as usize).copied();
         }
+        Ok(below)
+    }
 
-        self.pager.file.sync_all()?;
+    /// Number of items under `node.children[i]`. Files older than format
+    /// version 5 do not record it, so there the subtree is walked instead.
+    fn child_size(&self, node: &Node<K, V>, i: usize) -> Result<u64> {
+        if self.pager.counts_subtrees() {
+            return Ok(node.counts[i]);
+        }
+        self.walk_size(node.children[i])
+    }
 
-        Ok(())
+    fn walk_size(&self, id: PageID) -> Result<u64> {
+        let mut size = 0;
+        let mut stack = vec![id];
+        while let Some(page) = stack.pop() {
+            let node = self.read_node(page)?;
+            size += node.num_items as u64;
+            stack.extend(&node.children);
+        }
+        Ok(size)
     }
 
-    fn snapshot_node(&mut self, node: &Node) -> std::io::Result<()> {
-        if !node.is_leaf() {
-            for child in &node.children {
-                self.snapshot_node(child)?;
+    /// Removes `key`; in a tree with duplicate keys, every pair with it.
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        self.pager.check_writable()?;
+        self.delete_item(key, None)?;
+        if self.values.is_some() {
+            loop {
+                match self.delete_item(key, None) {
+                    Err(IndexError::NotFound(_)) => break,
+                    result => result?,
+                }
             }
         }
-        let page = node.to_page();
-        self.pager.write_page(&page)?;
-
-        Ok(())
+        self.log(|wal| wal.log_delete(key))
     }
 
-    pub fn load_snapshot(filename: &str, page_size: usize) -> Result<Self> {
-        let file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(filename)?;
-
-        let metadata = file.metadata()?;
-        if metadata.len() == 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Cannot load snapshot from empty file",
+    /// Removes the single pair `(key, val)` from a tree with duplicate keys.
+    pub fn delete_pair(&mut self, key: &K, val: &V) -> Result<()> {
+        if self.values.is_none() {
+            return Err(IndexError::InvalidInput(
+                "Tree does not allow duplicate keys; delete by key instead".to_string(),
             ));
         }
+        self.pager.check_writable()?;
+        self.delete_item(key, Some(val))?;
+        self.log(|wal| wal.log_delete_pair(key, val))
+    }
 
-        let mut pager = Pager {
-            file,
-            page_size,
-            num_pages: 0,
+    /// Removes an item with `key`, or with duplicate keys the pair
+    /// `(key, val)` when `val` is given.
+    fn delete_item(&mut self, key: &K, val: Option<&V>) -> Result<()> {
+        let Some(root_id) = self.root else {
+            return Err(IndexError::NotFound("Tree is empty".to_string()));
         };
-        let metadata = pager.read_metadata()?;
-
-        if metadata.page_size as usize != page_size {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!(
-                    "Page size mismatch: expected {}, got {}",
-                    page_size, metadata.page_size
-                ),
-            ));
+
+        let mut root = self.read_node(root_id)?;
+        let result = self.delete_recursive(&mut root, key, val);
+        if result.is_ok() {
+            self.pager.emit(Event::Delete { key });
         }
-        pager.num_pages = metadata.num_pages;
 
-        let root_page = if metadata.root_page_id == 0 {
-            pager.read_page(1)?
-        } else {
-            pager.read_page(metadata.root_page_id)?
-        };
-        let root_node = Self::load_node(&mut pager, &root_page)?;
-
-        if root_node.num_items < 0 || root_node.num_items > MAX_ITEMS {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid root node: invalid item count {}",
-                    root_node.num_items
-                ),
-            ));
+        if root.num_items == 0 && !root.is_leaf() {
+            self.root = Some(root.children[0]);
+            self.pager.free_page::<K, V>(root.id)?;
         }
+        self.repoint_relocated()?;
 
-        Ok(Btree {
-            pager,
-            root: Some(Box::new(root_node)),
-        })
+        result
     }
 
-    fn load_node(pager: &mut Pager, page: &Page) -> std::io::Result<Node> {
-        let mut node = Node::from_page(page);
+    /// Writes never touch pages of the published snapshot; such pages are
+    /// copied elsewhere instead. Once an operation is done this points each
+    /// parent (or the root) at the copies, which may relocate the parents in
+    /// turn.
+    fn repoint_relocated(&mut self) -> Result<()> {
+        while let Some((old, new)) = self.pager.next_unlinked() {
+            if self.root == Some(old) {
+                self.root = Some(new);
+                continue;
+            }
 
-        if let Page::Internal { children, .. } = page {
-            let mut loaded_children = Vec::new();
-            for &child_page_id in children {
-                if child_page_id == 0 || child_page_id > pager.num_pages {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("Invalid child page ID: {child_page_id}"),
-                    ));
-                }
-                let child_page = pager.read_page(child_page_id)?;
-                let child_node = Self::load_node(pager, &child_page)?;
-                loaded_children.push(Box::new(child_node));
+            let mut parent = self.find_parent(old)?;
+            for child in parent.children.iter_mut().filter(|c| **c == old) {
+                *child = new;
             }
-            node.children = loaded_children;
+            self.write_node(&parent)?;
         }
-
-        Ok(node)
+        Ok(self.pager.clear_relocations()?)
     }
 
-    pub fn is_valid_snapshot(filename: &str, page_size: usize) -> bool {
-        let file = match std::fs::OpenOptions::new().read(true).open(filename) {
-            Ok(file) => file,
-            Err(_) => return false,
+    /// Finds the node whose children include `id`, following the search path
+    /// of the node's first key and falling back to a full walk.
+    fn find_parent(&self, id: PageID) -> Result<Node<K, V>> {
+        let Some(root) = self.root else {
+            return Err(corrupt(id, "page has no parent in an empty tree").into());
         };
 
-        let metadata = match file.metadata() {
-            Ok(meta) => meta,
-            Err(_) => return false,
-        };
+        if let Some(first) = self.read_node(id)?.items.first() {
+            let mut current = self.read_node(root)?;
+            while !current.is_leaf() {
+                if current.children.contains(&id) {
+                    return Ok(current);
+                }
+                let (pos, _) = current
+                    .search_by(|item| cmp_item(item, &first.key, Some(&first.val), self.values));
+                current = self.read_node(current.children[pos as usize])?;
+            }
+        }
 
-        if metadata.len() < page_size as u64 {
-            return false;
+        let mut stack = vec![root];
+        while let Some(page) = stack.pop() {
+            let current = self.read_node(page)?;
+            if current.children.contains(&id) {
+                return Ok(current);
+            }
+            stack.extend(&current.children);
         }
+        Err(corrupt(id, "page is not reachable from the root").into())
+    }
 
-        let mut pager = Pager {
-            file,
-            page_size,
-            num_pages: 0,
-        };
+    fn delete_recursive(&mut self, node: &mut Node<K, V>, key: &K, val: Option<&V>) -> Result<()> {
+        let (pos, found) = node.search_by(|item| cmp_item(item, key, val, self.values));
 
-        match pager.read_metadata() {
-            Ok(metadata) => metadata.page_size as usize == page_size,
-            Err(_) => false,
+        if node.is_leaf() {
+            if found {
+                self.delete_from_leaf(node, pos)
+            } else {
+                Err(IndexError::NotFound(format!("Key {key:?} not found")))
+            }
+        } else if found {
+            self.delete_from_internal(node, pos)
+        } else {
+            self.delete_from_subtree(node, pos, key, val)
         }
     }
-}
 
-impl Btree {
-    fn delete_from_leaf(&mut self, node: &mut Node, pos: i32) -> Result<()> {
-        let key = node.items[pos as usize].key;
-        let mut indices_to_remove = Vec::new();
+    /// Publishes the current root as a new snapshot (see
+    /// `Pager::publish_snapshot`) and empties the write-ahead log, which the
+    /// snapshot now covers. Returns how many pages were flushed.
+    pub fn snapshot(&mut self) -> Result<usize> {
+        let Some(root_page_id) = self.root else {
+            return Err(IndexError::InvalidInput(
+                "Cannot snapshot empty tree".to_string(),
+            ));
+        };
+
+        let flushed = self
+            .pager
+            .publish_snapshot(root_page_id, self.degree as u32)?;
 
-        for (i, item) in node.items.iter().enumerate() {
-            if item.key == key {
-                indices_to_remove.push(i);
+        if let Some(wal) = &mut self.wal {
+            wal.truncate()?;
+        }
+        Ok(flushed)
+    }
+
+    /// Replays the write-ahead log at `path` on top of the current tree and
+    /// logs every later change to it.
+    ///
+    /// Records the tree rejects (a duplicate insert, a missing key, an item
+    /// too large for a page, an operation the tree does not support) are
+    /// skipped and counted; they were written by builds that logged before
+    /// validating. A corrupt page or an I/O error stops the replay with the
+    /// log left unattached and untouched on disk.
+    pub fn attach_wal(&mut self, path: &Path, policy: SyncPolicy) -> Result<Replay> {
+        let (wal, records) = Wal::open::<K, V>(path, policy)?;
+        if !records.is_empty() {
+            self.pager.check_writable()?;
+        }
+        self.wal = None;
+
+        let mut replay = Replay::default();
+        for record in records {
+            let result = match record {
+                WalRecord::Insert(item) => self.insert(item),
+                WalRecord::Upsert(item) => self.upsert(item).map(drop),
+                WalRecord::Update(item) => self.update(item).map(drop),
+                WalRecord::Delete(key) => self.delete(&key),
+                WalRecord::DeletePair(item) => self.delete_pair(&item.key, &item.val),
+            };
+            match result {
+                Ok(()) => replay.applied += 1,
+                Err(
+                    IndexError::Duplicate(_)
+                    | IndexError::NotFound(_)
+                    | IndexError::PageFull { .. }
+                    | IndexError::InvalidInput(_),
+                ) => replay.skipped += 1,
+                Err(e) => return Err(e),
             }
         }
 
-        for &index in indices_to_remove.iter().rev() {
-            node.items.remove(index);
-            node.num_items -= 1;
+        self.wal = Some(wal);
+        Ok(replay)
+    }
+
+    /// Attaches the observer that receives this tree's structural events;
+    /// `None` makes the tree quiet again.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.pager.set_observer(observer);
+    }
+
+    pub fn set_wal_sync_policy(&mut self, policy: SyncPolicy) -> Result<()> {
+        match &mut self.wal {
+            Some(wal) => Ok(wal.set_policy(policy)?),
+            None => Err(IndexError::NotFound(
+                "No write-ahead log attached".to_string(),
+            )),
         }
+    }
 
-        Ok(())
+    /// Opens a snapshot without reading the tree: only the metadata and the
+    /// root page are touched, the rest is paged in as it is traversed.
+    pub fn load_snapshot(filename: &str, page_size: usize) -> Result<Self> {
+        Self::open(filename, page_size, None)
     }
 
-    fn delete_from_internal(&mut self, node: &mut Node, pos: i32) -> Result<()> {
-        let key = node.items[pos as usize].key;
+    /// Opens a snapshot of a tree with unique keys (`values` unset) or one
+    /// with duplicate keys.
+    fn open(filename: &str, page_size: usize, values: ValueOrder<V>) -> Result<Self> {
+        let (pager, metadata) = Pager::open_snapshot(filename, page_size)?;
+        let degree = metadata.degree as i32;
+        let multimap = metadata.flags & BtreeMetadata::MULTIMAP != 0;
+        if multimap != values.is_some() {
+            return Err(IndexError::InvalidInput(
+                if multimap {
+                    "Snapshot allows duplicate keys; open it with load_multimap"
+                } else {
+                    "Snapshot does not allow duplicate keys; open it with load_snapshot"
+                }
+                .to_string(),
+            ));
+        }
 
-        if node.children[pos as usize].num_items > MIN_ITEMS {
-            let predecessor = node.get_predecessor(pos);
-            node.items[pos as usize] = predecessor.clone();
-            self.delete_recursive(&mut node.children[pos as usize], predecessor.key)
-        } else if node.children[pos as usize + 1].num_items > MIN_ITEMS {
-            let successor = node.get_successor(pos);
-            node.items[pos as usize] = successor.clone();
-            self.delete_recursive(&mut node.children[pos as usize + 1], successor.key)
+        let root_page_id = if metadata.root_page_id == 0 {
+            1
         } else {
-            node.merge_children(pos);
-            self.delete_recursive(&mut node.children[pos as usize], key)
+            metadata.root_page_id
+        };
+        let btree = Btree {
+            pager,
+            root: Some(root_page_id),
+            degree,
+            wal: None,
+            values,
+            marker: PhantomData,
+        };
+
+        let root_node = btree.read_node(root_page_id)?;
+        if root_node.num_items < 0 || root_node.num_items > degree * 2 {
+            return Err(corrupt(
+                root_page_id,
+                format!("invalid root item count {}", root_node.num_items),
+            )
+            .into());
         }
+
+        Ok(btree)
     }
 
-    fn delete_from_subtree(&mut self, node: &mut Node, pos: i32, key: i32) -> Result<()> {
-        let child_has_min = node.children[pos as usize].num_items == MIN_ITEMS;
+    pub fn is_valid_snapshot(filename: &str, page_size: usize) -> bool {
+        Pager::is_valid_snapshot(filename, page_size)
+    }
+}
 
-        if child_has_min {
-            self.fill_child(node, pos)?;
+impl<K: KeyCodec, V: Codec + Ord> Btree<K, V> {
+    /// Creates a tree that allows duplicate keys. Equal keys are kept in
+    /// value order and only an identical key-value pair is rejected.
+    pub fn new_multimap(filename: &str, page_size: usize) -> Result<Self> {
+        let mut btree = Self::new(filename, page_size)?;
+        btree.values = Some(V::cmp);
+        btree.pager.flags |= BtreeMetadata::MULTIMAP;
+        Ok(btree)
+    }
+
+    /// Opens a snapshot written by a tree made with `new_multimap`.
+    pub fn load_multimap(filename: &str, page_size: usize) -> Result<Self> {
+        Self::open(filename, page_size, Some(V::cmp))
+    }
+}
+
+impl<K: KeyCodec, V: Codec> Btree<K, V> {
+    fn delete_from_leaf(&mut self, node: &mut Node<K, V>, pos: i32) -> Result<()> {
+        node.items.remove(pos as usize);
+        node.num_items -= 1;
+        self.write_node(node)
+    }
+
+    // The item moved into a child is deleted from there by key and value, so
+    // that with duplicate keys it is that very item that goes.
+    fn delete_from_internal(&mut self, node: &mut Node<K, V>, pos: i32) -> Result<()> {
+        let item = node.items[pos as usize].clone();
+
+        let mut left = self.read_node(node.children[pos as usize])?;
+        if left.num_items > self.min_items() {
+            let predecessor = node.get_predecessor(pos, &self.pager)?;
+            node.items[pos as usize] = predecessor.clone();
+            self.delete_recursive(&mut left, &predecessor.key, Some(&predecessor.val))?;
+            node.update_count(&left);
+            return self.write_node(node);
         }
 
-        if pos >= node.num_children {
-            self.delete_recursive(&mut node.children[pos as usize - 1], key)
+        let mut right = self.read_node(node.children[pos as usize + 1])?;
+        if right.num_items > self.min_items() {
+            let successor = node.get_successor(pos, &self.pager)?;
+            node.items[pos as usize] = successor.clone();
+            self.delete_recursive(&mut right, &successor.key, Some(&successor.val))?;
+            node.update_count(&right);
         } else {
-            let child = &node.children[pos as usize];
-            if child.num_items == 0 {
-                self.delete_recursive(&mut node.children[pos as usize + 1], key)
-            } else {
-                self.delete_recursive(&mut node.children[pos as usize], key)
-            }
+            let right_id = right.id;
+            node.merge_children(pos, &mut left, right);
+            self.write_node(node)?;
+            self.write_node(&left)?;
+            self.pager.free_page::<K, V>(right_id)?;
+            self.delete_recursive(&mut left, &item.key, Some(&item.val))?;
+            node.update_count(&left);
         }
+        self.write_node(node)
     }
 
-    fn fill_child(&mut self, node: &mut Node, pos: i32) -> Result<()> {
-        if pos > 0 && node.children[pos as usize - 1].num_items > MIN_ITEMS {
-            node.borrow_from_prev(pos)
-        } else if pos < node.num_children - 1
-            && node.children[pos as usize + 1].num_items > MIN_ITEMS
-        {
-            node.borrow_from_next(pos)
-        } else if pos > 0 {
-            node.merge_children(pos - 1)
+    fn delete_from_subtree(
+        &mut self,
+        node: &mut Node<K, V>,
+        pos: i32,
+        key: &K,
+        val: Option<&V>,
+    ) -> Result<()> {
+        let child = self.read_node(node.children[pos as usize])?;
+
+        let mut child = if child.num_items == self.min_items() {
+            self.fill_child(node, pos, child)?
         } else {
-            node.merge_children(pos)
+            child
+        };
+
+        self.delete_recursive(&mut child, key, val)?;
+        node.update_count(&child);
+        self.write_node(node)
+    }
+
+    /// Tops up the child at `pos` by borrowing from or merging with a sibling
+    /// and returns the node that now covers its key range.
+    fn fill_child(
+        &mut self,
+        node: &mut Node<K, V>,
+        pos: i32,
+        mut child: Node<K, V>,
+    ) -> Result<Node<K, V>> {
+        if pos > 0 {
+            let mut prev = self.read_node(node.children[pos as usize - 1])?;
+            if prev.num_items > self.min_items() {
+                node.borrow_from_prev(pos, &mut prev, &mut child);
+                self.pager.emit(Event::Borrow {
+                    page: child.id,
+                    from: prev.id,
+                });
+                self.write_node(&prev)?;
+                self.write_node(&child)?;
+                self.write_node(node)?;
+                return Ok(child);
+            }
         }
 
-        Ok(())
+        if pos < node.num_children - 1 {
+            let mut next = self.read_node(node.children[pos as usize + 1])?;
+            if next.num_items > self.min_items() {
+                node.borrow_from_next(pos, &mut child, &mut next);
+                self.pager.emit(Event::Borrow {
+                    page: child.id,
+                    from: next.id,
+                });
+                self.write_node(&next)?;
+                self.write_node(&child)?;
+                self.write_node(node)?;
+                return Ok(child);
+            }
+
+            if pos == 0 {
+                let next_id = next.id;
+                node.merge_children(pos, &mut child, next);
+                self.pager.emit(Event::Merge {
+                    page: child.id,
+                    sibling: next_id,
+                });
+                self.write_node(&child)?;
+                self.write_node(node)?;
+                self.pager.free_page::<K, V>(next_id)?;
+                return Ok(child);
+            }
+        }
+
+        let mut prev = self.read_node(node.children[pos as usize - 1])?;
+        let child_id = child.id;
+        node.merge_children(pos - 1, &mut prev, child);
+        self.pager.emit(Event::Merge {
+            page: prev.id,
+            sibling: child_id,
+        });
+        self.write_node(&prev)?;
+        self.write_node(node)?;
+        self.pager.free_page::<K, V>(child_id)?;
+        Ok(prev)
     }
 }
 #[cfg(test)]
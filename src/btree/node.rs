@@ -1,38 +1,90 @@
-use std::{fmt, io::Result};
+use std::{
+    cmp::Ordering,
+    fmt,
+    io::{self, Result},
+};
 
 use super::{
-    Item, MAX_ITEMS,
-    paging::{Page, PageID, Pager},
+    Item,
+    codec::{Codec, KeyCodec},
+    error::IndexError,
+    events::Event,
+    paging::{Page, PageID, Pager, corrupt},
 };
 
+/// Orders the values of equal keys in trees that allow duplicate keys;
+/// `None` when keys are unique.
+pub type ValueOrder<V> = Option<fn(&V, &V) -> Ordering>;
+
+/// Orders `item` against the target `key` and, when duplicates are ordered
+/// by `values` and a target value is given, against `val`.
+pub fn cmp_item<K: Ord, V>(
+    item: &Item<K, V>,
+    key: &K,
+    val: Option<&V>,
+    values: ValueOrder<V>,
+) -> Ordering {
+    item.key.cmp(key).then_with(|| match (values, val) {
+        (Some(cmp), Some(val)) => cmp(&item.val, val),
+        _ => Ordering::Equal,
+    })
+}
+
+fn conflict<K: fmt::Debug, V: fmt::Debug>(item: &Item<K, V>, values: ValueOrder<V>) -> io::Error {
+    IndexError::Duplicate(match values {
+        Some(_) => format!("Pair ({:?}, {:?}) already exists", item.key, item.val),
+        None => format!("Key {:?} already exists", item.key),
+    })
+    .into()
+}
+
 #[derive(Clone, Debug)]
-pub struct Node {
+pub struct Node<K = i32, V = String> {
     pub id: PageID,
-    pub items: Vec<Item>,
-    #[allow(clippy::vec_box)]
-    pub children: Vec<Box<Node>>,
+    pub items: Vec<Item<K, V>>,
+    pub children: Vec<PageID>,
+    /// Number of items under each child, in step with `children`.
+    pub counts: Vec<u64>,
     pub num_items: i32,
     pub num_children: i32,
 }
 
-impl fmt::Display for Node {
+impl<K: fmt::Display, V: fmt::Display> fmt::Display for Node<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.fmt_with_indent(f, 0)
     }
 }
 
-impl Node {
+impl<K, V> Node<K, V> {
     pub fn new(id: PageID) -> Self {
         Node {
             id,
             items: Vec::new(),
             children: Vec::new(),
+            counts: Vec::new(),
             num_items: 0,
             num_children: 0,
         }
     }
 
-    pub fn fmt_with_indent(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+    /// Number of items in the subtree rooted at this node.
+    pub fn size(&self) -> u64 {
+        self.num_items as u64 + self.counts.iter().sum::<u64>()
+    }
+
+    /// Records the current size of `child`, which must be one of this
+    /// node's children.
+    pub fn update_count(&mut self, child: &Node<K, V>) {
+        if let Some(pos) = self.children.iter().position(|&id| id == child.id) {
+            self.counts[pos] = child.size();
+        }
+    }
+
+    pub fn fmt_with_indent(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result
+    where
+        K: fmt::Display,
+        V: fmt::Display,
+    {
         for _ in 0..indent {
             write!(f, "  ")?;
         }
@@ -45,33 +97,39 @@ impl Node {
         }
         write!(f, "]")?;
 
-        for child in &self.children {
-            child.fmt_with_indent(f, indent + 1)?;
+        if !self.children.is_empty() {
+            write!(f, " -> {:?}", self.children)?;
         }
 
         Ok(())
     }
+}
+
+impl<K: KeyCodec, V: Codec> Node<K, V> {
     pub fn is_leaf(&self) -> bool {
         self.num_children == 0
     }
-    pub fn search(&self, key: i32) -> (i32, bool) {
+    pub fn search(&self, key: &K) -> (i32, bool) {
+        self.search_by(|item| item.key.cmp(key))
+    }
+
+    /// Binary search with `probe`, which orders an item against the target.
+    pub fn search_by(&self, probe: impl Fn(&Item<K, V>) -> Ordering) -> (i32, bool) {
         let mut low: i32 = 0;
         let mut high: i32 = self.num_items;
         let mut mid;
         while low < high {
             mid = low + (high - low) / 2;
-            if self.items[mid as usize].key == key {
-                return (mid, true);
-            } else if self.items[mid as usize].key < key {
-                low = mid + 1;
-            } else {
-                high = mid;
+            match probe(&self.items[mid as usize]) {
+                Ordering::Equal => return (mid, true),
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
             }
         }
         (low, false)
     }
 
-    pub fn insert_item_at(&mut self, pos: i32, item: Item) {
+    pub fn insert_item_at(&mut self, pos: i32, item: Item<K, V>) {
         if pos > self.num_items || pos < 0 {
             return;
         }
@@ -86,16 +144,21 @@ impl Node {
         self.num_items += 1;
     }
 
-    pub fn insert_child_at(&mut self, pos: i32, node: Node) {
+    pub fn insert_child_at(&mut self, pos: i32, child: PageID, count: u64) {
         if pos > self.num_items || pos < 0 {
             return;
         }
-        self.children.insert(pos as usize, Box::new(node));
+        self.children.insert(pos as usize, child);
+        self.counts.insert(pos as usize, count);
         self.num_children += 1;
     }
 
-    pub fn split(&mut self, pager: &mut Pager) -> Result<(Item, Node)> {
+    pub fn split(&mut self, pager: &mut Pager) -> Result<(Item<K, V>, Node<K, V>)> {
         let new_id = pager.allocate_page()?;
+        pager.emit(Event::Split {
+            page: self.id,
+            sibling: new_id,
+        });
         let mut new_node = Node::new(new_id);
 
         let mid = self.num_items / 2;
@@ -106,6 +169,7 @@ impl Node {
 
         if !self.is_leaf() {
             new_node.children = self.children[mid as usize + 1..].to_vec();
+            new_node.counts = self.counts[mid as usize + 1..].to_vec();
             new_node.num_children = new_node.num_items + 1;
         }
 
@@ -114,22 +178,34 @@ impl Node {
 
         if !self.is_leaf() {
             self.children.truncate(mid as usize + 1);
+            self.counts.truncate(mid as usize + 1);
             self.num_children = self.num_items + 1;
         }
 
         Ok((mid_item, new_node))
     }
 
-    pub fn insert(&mut self, item: Item, pager: &mut Pager) {
-        let (mut pos, found) = self.search(item.key);
+    /// Inserts into the subtree rooted at this node, splitting full children
+    /// on the way down. Every node that changes is written back to its page.
+    /// With `values` set, equal keys are allowed and only an identical
+    /// key-value pair is a conflict.
+    pub fn insert(
+        &mut self,
+        item: Item<K, V>,
+        pager: &mut Pager,
+        max_items: i32,
+        values: ValueOrder<V>,
+    ) -> Result<()> {
+        let (pos, found) =
+            self.search_by(|probe| cmp_item(probe, &item.key, Some(&item.val), values));
         if found {
-            println!("Key already exist");
-            return;
+            return Err(conflict(&item, values));
         }
 
         if self.is_leaf() {
+            pager.emit(Event::Insert { key: &item.key });
             self.insert_item_at(pos, item);
-            return;
+            return pager.write_page(&self.to_page());
         }
 
         let child_pos = if pos >= self.num_items {
@@ -138,49 +214,55 @@ impl Node {
             pos as usize
         };
 
-        if self.children[child_pos].num_items >= MAX_ITEMS {
-            let (mid_item, new_node) = self.children[child_pos].split(pager).unwrap();
-            let mid_key = mid_item.key;
+        let mut child = Node::from_page(&pager.read_page(self.children[child_pos])?)?;
+        if child.num_items >= max_items {
+            let (mid_item, new_node) = child.split(pager)?;
+            let order = cmp_item(&mid_item, &item.key, Some(&item.val), values);
             self.insert_item_at(child_pos as i32, mid_item);
-            self.insert_child_at(child_pos as i32 + 1, new_node);
-
-            if item.key > mid_key {
-                pos = child_pos as i32 + 1;
-            } else {
-                pos = child_pos as i32;
+            self.insert_child_at(child_pos as i32 + 1, new_node.id, new_node.size());
+            self.counts[child_pos] = child.size();
+
+            pager.write_page(&child.to_page())?;
+            pager.write_page(&new_node.to_page())?;
+            pager.write_page(&self.to_page())?;
+
+            match order {
+                // The split lifted the conflicting item into this node.
+                Ordering::Equal => return Err(conflict(&item, values)),
+                Ordering::Less => child = new_node,
+                Ordering::Greater => {}
             }
         }
 
-        let child_pos = if pos >= self.num_items {
-            (self.num_items) as usize
-        } else {
-            pos as usize
-        };
-        self.children[child_pos].insert(item, pager);
+        child.insert(item, pager, max_items, values)?;
+        self.update_count(&child);
+        pager.write_page(&self.to_page())
     }
 }
 
-impl Node {
-    pub fn get_predecessor(&self, pos: i32) -> Item {
-        let mut current = &self.children[pos as usize];
+impl<K: KeyCodec, V: Codec> Node<K, V> {
+    pub fn get_predecessor(&self, pos: i32, pager: &Pager) -> Result<Item<K, V>> {
+        let mut current = Node::from_page(&pager.read_page(self.children[pos as usize])?)?;
         while !current.is_leaf() {
-            current = &current.children[current.num_children as usize - 1];
+            let last = current.children[current.num_children as usize - 1];
+            current = Node::from_page(&pager.read_page(last)?)?;
         }
-        current.items[current.num_items as usize - 1].clone()
+        Ok(current.items[current.num_items as usize - 1].clone())
     }
 
-    pub fn get_successor(&self, pos: i32) -> Item {
-        let mut current = &self.children[pos as usize + 1];
+    pub fn get_successor(&self, pos: i32, pager: &Pager) -> Result<Item<K, V>> {
+        let mut current = Node::from_page(&pager.read_page(self.children[pos as usize + 1])?)?;
         while !current.is_leaf() {
-            current = &current.children[0];
+            current = Node::from_page(&pager.read_page(current.children[0])?)?;
         }
-        current.items[0].clone()
+        Ok(current.items[0].clone())
     }
 
-    pub fn merge_children(&mut self, pos: i32) {
-        let next_child = self.children.remove(pos as usize + 1);
-
-        let child = &mut self.children[pos as usize];
+    /// Folds `next_child` and the separator at `pos` into `child`, which must
+    /// be the node at `children[pos]`. `next_child`'s page is no longer referenced.
+    pub fn merge_children(&mut self, pos: i32, child: &mut Node<K, V>, next_child: Node<K, V>) {
+        self.children.remove(pos as usize + 1);
+        self.counts.remove(pos as usize + 1);
 
         let separator = self.items.remove(pos as usize);
 
@@ -192,21 +274,22 @@ impl Node {
         all_items.extend(original_items);
         all_items.push(separator);
         all_items.extend(next_child.items);
-        all_items.sort_by_key(|item| item.key);
+        all_items.sort_by(|a, b| a.key.cmp(&b.key));
         child.items = all_items;
 
         child.children.extend(next_child.children);
+        child.counts.extend(next_child.counts);
 
         child.num_items = child.items.len() as i32;
         child.num_children = child.children.len() as i32;
         self.num_items -= 1;
         self.num_children -= 1;
+        self.counts[pos as usize] = child.size();
     }
 
-    pub fn borrow_from_prev(&mut self, pos: i32) {
-        let (left, right) = self.children.split_at_mut(pos as usize);
-        let sibling = &mut left[left.len() - 1];
-        let child = &mut right[0];
+    /// Rotates the last item of `sibling` (at `children[pos - 1]`) through the
+    /// parent into `child` (at `children[pos]`).
+    pub fn borrow_from_prev(&mut self, pos: i32, sibling: &mut Node<K, V>, child: &mut Node<K, V>) {
         let sibling_item = sibling.items.pop().unwrap();
 
         let parent_item = self.items[pos as usize - 1].clone();
@@ -215,10 +298,11 @@ impl Node {
 
         child.items.insert(0, parent_item);
 
-        if !sibling.is_leaf() {
-            if let Some(last_child) = sibling.children.pop() {
-                child.children.insert(0, last_child);
-            }
+        if !sibling.is_leaf()
+            && let Some(last_child) = sibling.children.pop()
+        {
+            child.children.insert(0, last_child);
+            child.counts.insert(0, sibling.counts.pop().unwrap_or(0));
         }
 
         sibling.num_items -= 1;
@@ -228,13 +312,13 @@ impl Node {
             sibling.num_children = sibling.children.len() as i32;
             child.num_children = child.children.len() as i32;
         }
+        self.counts[pos as usize - 1] = sibling.size();
+        self.counts[pos as usize] = child.size();
     }
 
-    pub fn borrow_from_next(&mut self, pos: i32) {
-        let (left, right) = self.children.split_at_mut(pos as usize + 1);
-        let child = &mut left[left.len() - 1];
-        let sibling = &mut right[0];
-
+    /// Rotates the first item of `sibling` (at `children[pos + 1]`) through
+    /// the parent into `child` (at `children[pos]`).
+    pub fn borrow_from_next(&mut self, pos: i32, child: &mut Node<K, V>, sibling: &mut Node<K, V>) {
         let sibling_item = sibling.items.remove(0);
 
         let parent_item = self.items[pos as usize].clone();
@@ -243,11 +327,12 @@ impl Node {
 
         child.items.push(parent_item);
 
-        if !sibling.is_leaf() {
-            if let Some(first_child) = sibling.children.first().cloned() {
-                child.children.push(Box::new(*first_child));
-                sibling.children.remove(0);
-            }
+        if !sibling.is_leaf()
+            && let Some(&first_child) = sibling.children.first()
+        {
+            child.children.push(first_child);
+            sibling.children.remove(0);
+            child.counts.push(sibling.counts.remove(0));
         }
 
         sibling.num_items -= 1;
@@ -257,11 +342,13 @@ impl Node {
             sibling.num_children = sibling.children.len() as i32;
             child.num_children = child.children.len() as i32;
         }
+        self.counts[pos as usize] = child.size();
+        self.counts[pos as usize + 1] = sibling.size();
     }
 }
 
-impl Node {
-    pub fn to_page(&self) -> Page {
+impl<K: KeyCodec, V: Codec> Node<K, V> {
+    pub fn to_page(&self) -> Page<K, V> {
         if self.is_leaf() {
             Page::Leaf {
                 id: self.id,
@@ -271,19 +358,22 @@ impl Node {
             Page::Internal {
                 id: self.id,
                 items: self.items.clone(),
-                children: self.children.iter().map(|c| c.id).collect(),
+                children: self.children.clone(),
+                counts: self.counts.clone(),
             }
         }
     }
 
-    pub fn from_page(page: &Page) -> Self {
-        match page {
+    /// Fails on B+tree pages, which a B-tree cannot interpret.
+    pub fn from_page(page: &Page<K, V>) -> Result<Self> {
+        Ok(match page {
             Page::Leaf { id, items } => {
                 let num_items = items.len() as i32;
                 Node {
                     id: *id,
                     items: items.clone(),
                     children: vec![],
+                    counts: vec![],
                     num_items,
                     num_children: 0,
                 }
@@ -292,6 +382,7 @@ impl Node {
                 id,
                 items,
                 children,
+                counts,
             } => {
                 let num_items = items.len() as i32;
                 let num_children = children.len() as i32;
@@ -299,11 +390,15 @@ impl Node {
                 Node {
                     id: *id,
                     items: items.clone(),
-                    children: Vec::new(),
+                    children: children.clone(),
+                    counts: counts.clone(),
                     num_items,
                     num_children,
                 }
             }
-        }
+            Page::Separators { id, .. } | Page::LinkedLeaf { id, .. } => {
+                return Err(corrupt(*id, "expected a B-tree node, found a B+tree page"));
+            }
+        })
     }
 }
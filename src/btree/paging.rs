@@ -1,22 +1,103 @@
-use super::{Item, metadata::BtreeMetadata};
+use super::{
+    Item,
+    buffer_pool::{BufferPool, DEFAULT_POOL_CAPACITY, EvictionPolicy, Lru, PoolStats},
+    codec::{Codec, KeyCodec, get_varint, put_varint},
+    error::IndexError,
+    events::{Event, Observer},
+    metadata::BtreeMetadata,
+};
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
-    io::{Read, Result, Seek, Write},
-    vec,
+    io::{self, Read, Result},
+    os::unix::fs::FileExt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
 };
 pub type PageID = u32;
 
+/// Reports that a page failed its integrity checks, as an `io::Error`
+/// carrying `IndexError::Corrupt`.
+pub fn corrupt(page: PageID, reason: impl Into<String>) -> io::Error {
+    IndexError::Corrupt {
+        page,
+        reason: reason.into(),
+    }
+    .into()
+}
+
 #[derive(Debug)]
-pub enum Page {
+pub enum Page<K, V> {
+    /// `counts[i]` is the number of items under `children[i]`. Pages of
+    /// files older than version 5 do not store them and read as zeros.
     Internal {
         id: PageID,
-        items: Vec<Item>,
+        items: Vec<Item<K, V>>,
         children: Vec<PageID>,
+        counts: Vec<u64>,
     },
     Leaf {
         id: PageID,
-        items: Vec<Item>,
+        items: Vec<Item<K, V>>,
     },
+    /// B+tree internal node: separator keys only, values live in the leaves.
+    Separators {
+        id: PageID,
+        keys: Vec<K>,
+        children: Vec<PageID>,
+    },
+    /// B+tree leaf, chained to its neighbours in key order. `serial` stays
+    /// with the leaf when it is copied to another page.
+    LinkedLeaf {
+        id: PageID,
+        serial: u64,
+        items: Vec<Item<K, V>>,
+        prev: Link,
+        next: Link,
+    },
+}
+
+/// A B+tree leaf's link to a neighbour: the page the neighbour was on when
+/// the link was written and its serial. `page` 0 means no neighbour.
+///
+/// Files before version 7 have no serials; a leaf there reads with its page
+/// id as serial, so a link is only valid while the neighbour stays put.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Link {
+    pub page: PageID,
+    pub serial: u64,
+}
+
+impl Link {
+    pub const NONE: Link = Link { page: 0, serial: 0 };
+}
+
+/// Open read views, counted by the epoch they were opened in. Views may be
+/// dropped on another thread than the tree's.
+type ViewRegistry = Arc<Mutex<BTreeMap<u64, usize>>>;
+
+// Nothing panics while holding the registry or the buffer pool, so a
+// poisoned lock still guards consistent state.
+fn locked<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keeps the pages of a read view from being reused; see `Pager::open_view`.
+#[derive(Debug)]
+pub struct ViewPin {
+    epoch: u64,
+    views: ViewRegistry,
+}
+
+impl Drop for ViewPin {
+    fn drop(&mut self) {
+        let mut views = locked(&self.views);
+        if let Some(open) = views.get_mut(&self.epoch) {
+            *open -= 1;
+            if *open == 0 {
+                views.remove(&self.epoch);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -24,185 +105,1384 @@ pub struct Pager {
     pub file: File,
     pub page_size: usize,
     pub num_pages: PageID,
+    /// Snapshot format the file uses; decides whether pages carry a header.
+    pub format_version: u32,
+    /// Sequence number of the last published snapshot.
+    pub sequence: u64,
+    /// Tree options recorded in the metadata, see `BtreeMetadata::flags`.
+    pub flags: u32,
+    /// Set when the file was opened in a format older than version 3, whose
+    /// free list is not rewritten on snapshot: pages freed while it is open
+    /// would leak, so it is only read until it is upgraded.
+    read_only: bool,
+    /// Pages `allocate_page` hands out before extending the file.
+    free: Vec<PageID>,
+    /// Every page on `free`, `pending` or `retired`, for quick lookups.
+    released: HashSet<PageID>,
+    /// Leaf serials handed out since the last snapshot was published.
+    serials: u32,
+    /// Pages the published snapshot still uses but the current tree does
+    /// not. They become free once the next snapshot is published.
+    pending: Vec<PageID>,
+    /// Pages allocated since the last snapshot was published or read view
+    /// opened. These are the only pages that may be overwritten in place.
+    fresh: HashSet<PageID>,
+    /// Pages written since the last snapshot that a read view may still be
+    /// reading; they are shadowed like published pages.
+    sealed: HashSet<PageID>,
+    /// Released pages that open read views may still be reading, tagged
+    /// with the view epoch they were released in.
+    retired: Vec<(u64, PageID)>,
+    /// Number of read views opened so far; the latest view's epoch.
+    epoch: u64,
+    /// Open read views, counted by epoch. Views deregister when dropped.
+    views: ViewRegistry,
+    /// Published pages whose new version was written elsewhere, by old id.
+    relocated: HashMap<PageID, PageID>,
+    /// Relocated pages whose parent may still point at the old id.
+    unlinked: Vec<PageID>,
+    /// Pages holding the published snapshot's free list, and the ones
+    /// written for the snapshot being published.
+    free_list_pages: Vec<PageID>,
+    staged_free_list: Vec<PageID>,
+    /// Locked for each page read, so threads sharing `&Pager` can read at
+    /// once.
+    pool: Mutex<BufferPool>,
+    /// Only used through `&mut self`; the mutex keeps the pager `Sync`
+    /// without requiring observers to be.
+    observer: Mutex<Option<Box<dyn Observer>>>,
 }
 
 impl Pager {
+    pub fn new(file: File, page_size: usize) -> Self {
+        let pool = BufferPool::new(DEFAULT_POOL_CAPACITY, Box::new(Lru::default()))
+            .expect("default pool capacity is non-zero");
+        Pager {
+            file,
+            page_size,
+            num_pages: 0,
+            format_version: BtreeMetadata::VERSION,
+            sequence: 0,
+            flags: 0,
+            read_only: false,
+            free: Vec::new(),
+            released: HashSet::new(),
+            serials: 0,
+            pending: Vec::new(),
+            fresh: HashSet::new(),
+            sealed: HashSet::new(),
+            retired: Vec::new(),
+            epoch: 0,
+            views: ViewRegistry::default(),
+            relocated: HashMap::new(),
+            unlinked: Vec::new(),
+            free_list_pages: Vec::new(),
+            staged_free_list: Vec::new(),
+            pool: Mutex::new(pool),
+            observer: Mutex::new(None),
+        }
+    }
+
+    /// Opens `filename` for a new tree. A file that was never published (its
+    /// metadata page is still blank) holds nothing durable and is emptied;
+    /// a file with metadata is refused, whether or not it can be read, so a
+    /// damaged or newer snapshot is never overwritten.
+    pub fn create(filename: &str, page_size: usize) -> Result<Self> {
+        check_page_size(page_size)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(filename)?;
+
+        let mut first_page = Vec::new();
+        (&file)
+            .take(page_size as u64)
+            .read_to_end(&mut first_page)?;
+        if first_page.iter().any(|&byte| byte != 0) {
+            return Err(IndexError::InvalidInput(format!(
+                "{filename} already holds snapshot metadata; refusing to overwrite it"
+            ))
+            .into());
+        }
+        file.set_len(0)?;
+        Ok(Pager::new(file, page_size))
+    }
+
+    /// Opens an existing snapshot and returns the pager together with the
+    /// metadata of the newest published snapshot.
+    pub fn open_snapshot(filename: &str, page_size: usize) -> Result<(Self, BtreeMetadata)> {
+        check_page_size(page_size)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(filename)?;
+
+        let metadata = file.metadata()?;
+        if metadata.len() == 0 {
+            return Err(IndexError::InvalidInput(
+                "Cannot load snapshot from empty file".to_string(),
+            )
+            .into());
+        }
+
+        let mut pager = Pager::new(file, page_size);
+        let metadata = pager.read_metadata()?;
+
+        if metadata.page_size as usize != page_size {
+            return Err(IndexError::InvalidInput(format!(
+                "Page size mismatch: expected {}, got {}",
+                page_size, metadata.page_size
+            ))
+            .into());
+        }
+        if metadata.version > BtreeMetadata::VERSION {
+            return Err(IndexError::VersionMismatch {
+                found: metadata.version,
+                supported: BtreeMetadata::VERSION,
+            }
+            .into());
+        }
+        if metadata.degree < 2 {
+            return Err(corrupt(
+                0,
+                format!("invalid degree {} in metadata", metadata.degree),
+            ));
+        }
+        pager.format_version = metadata.version;
+        pager.num_pages = metadata.num_pages;
+        pager.sequence = metadata.sequence;
+        pager.flags = metadata.flags;
+        pager.read_only = metadata.version < 3;
+        pager.load_free_list(&metadata)?;
+        Ok((pager, metadata))
+    }
+
+    /// Whether `filename` holds a snapshot with a readable metadata page
+    /// written with `page_size`.
+    pub fn is_valid_snapshot(filename: &str, page_size: usize) -> bool {
+        if check_page_size(page_size).is_err() {
+            return false;
+        }
+        let file = match std::fs::OpenOptions::new().read(true).open(filename) {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+
+        let metadata = match file.metadata() {
+            Ok(meta) => meta,
+            Err(_) => return false,
+        };
+
+        if metadata.len() < page_size as u64 {
+            return false;
+        }
+
+        let mut pager = Pager::new(file, page_size);
+
+        match pager.read_metadata() {
+            Ok(metadata) => metadata.page_size as usize == page_size,
+            Err(_) => false,
+        }
+    }
+
+    /// Writes dirty pages back and publishes `root` in the metadata slot the
+    /// previous snapshot is not using. Until that write completes the file
+    /// still opens as the previous snapshot.
+    ///
+    /// Only pages changed since the last snapshot are written; returns how
+    /// many were flushed, which is zero when nothing changed.
+    pub fn publish_snapshot(&mut self, root: PageID, degree: u32) -> Result<usize> {
+        self.check_writable()?;
+        if self.sequence > 0 && !self.has_changes() {
+            return Ok(0);
+        }
+
+        let mut metadata = BtreeMetadata::new(root, self.page_size as u32, self.num_pages, degree);
+        metadata.version = self.format_version;
+        metadata.sequence = self.sequence + 1;
+        metadata.flags = self.flags;
+        if metadata.version >= 3 {
+            (metadata.free_list_head, metadata.free_pages) = self.write_free_list()?;
+            metadata.num_pages = self.num_pages;
+        }
+
+        // The new pages must be durable before the metadata points at them.
+        let flushed = self.flush()?;
+        self.file.sync_all()?;
+
+        self.write_metadata(&metadata)?;
+        self.file.sync_all()?;
+        self.publish(metadata.sequence)?;
+        Ok(flushed)
+    }
+
+    /// Fails if the file is too old to be written; see `read_only`.
+    pub fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(IndexError::InvalidInput(format!(
+                "Snapshot format version {} cannot record freed pages; upgrade the file before writing to it",
+                self.format_version
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Flushes the current pool and replaces it with an empty one.
+    pub fn configure_pool(
+        &mut self,
+        capacity: usize,
+        policy: Box<dyn EvictionPolicy>,
+    ) -> Result<()> {
+        let pool = BufferPool::new(capacity, policy)?;
+        self.flush()?;
+        self.pool = Mutex::new(pool);
+        Ok(())
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        locked(&self.pool).stats()
+    }
+
+    /// Writes all dirty pages back to the file and returns how many there were.
+    pub fn flush(&mut self) -> Result<usize> {
+        let pool = self.pool.get_mut().unwrap_or_else(PoisonError::into_inner);
+        pool.flush(&self.file, self.page_size)
+    }
+
+    /// Attaches the observer that receives the tree's events, or detaches
+    /// it with `None`.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        *self
+            .observer
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = observer;
+    }
+
+    pub fn emit(&mut self, event: Event<'_>) {
+        let observer = self.observer.get_mut();
+        if let Some(observer) = observer.unwrap_or_else(PoisonError::into_inner) {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Number of released pages, including those that only become reusable
+    /// after the next snapshot or once the read views using them close.
+    pub fn free_pages(&self) -> usize {
+        self.free.len() + self.pending.len() + self.retired.len()
+    }
+
+    /// Whether `page_id` was released, now or pending the next snapshot.
+    pub fn is_free(&self, page_id: PageID) -> bool {
+        self.released.contains(&page_id)
+    }
+
+    /// Returns the serial of the B+tree leaf at `page_id`, or `None` if the
+    /// page is not a leaf the current tree uses.
+    pub fn leaf_serial(&self, page_id: PageID) -> Result<Option<u64>> {
+        let in_use = self.relocated.contains_key(&page_id)
+            || (page_id != 0 && page_id <= self.num_pages && !self.is_free(page_id));
+        if !in_use {
+            return Ok(None);
+        }
+
+        let buf = self.read_raw(page_id)?;
+        Ok(match buf[0] {
+            LINKED_LEAF_PAGE => Some(page_id as u64),
+            STABLE_LEAF_PAGE => {
+                let offset = self.header_size() + 4;
+                Some(u64::decode(tail(&buf, offset, page_id)?)?.0)
+            }
+            _ => None,
+        })
+    }
+
+    /// Where `page_id` is stored once the current operation is done.
+    pub fn location(&self, page_id: PageID) -> PageID {
+        self.relocated.get(&page_id).copied().unwrap_or(page_id)
+    }
+
+    /// Returns the serial for a new B+tree leaf on `page_id`: one no other
+    /// leaf in the file has, made of the sequence number of the next
+    /// snapshot and a counter. Files without serials use the page id.
+    pub fn new_serial(&mut self, page_id: PageID) -> u64 {
+        if !self.stable_leaves() {
+            return page_id as u64;
+        }
+        self.serials += 1;
+        ((self.sequence + 1) << 32) | self.serials as u64
+    }
+
+    /// Whether B+tree leaves of this file carry serials, so links to a leaf
+    /// stay valid when it moves.
+    pub fn stable_leaves(&self) -> bool {
+        self.format_version >= 7
+    }
+
     pub fn allocate_page(&mut self) -> std::io::Result<PageID> {
+        self.reclaim()?;
+        if let Some(page_id) = self.free.pop() {
+            self.released.remove(&page_id);
+            self.write_free(page_id)?;
+            self.sealed.remove(&page_id);
+            self.fresh.insert(page_id);
+            self.emit(Event::PageAlloc { page: page_id });
+            return Ok(page_id);
+        }
+
+        self.extend()
+    }
+
+    /// Allocates a page past the end of the file.
+    fn extend(&mut self) -> std::io::Result<PageID> {
         let new_id = if self.num_pages == 0 {
             1
         } else {
             self.num_pages + 1
         };
 
-        if self.page_size == 0 || self.page_size > 1024 * 1024 {
-            // Max 1MB
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Invalid page size",
-            ));
+        self.num_pages = new_id;
+        self.write_free(new_id)?;
+        self.fresh.insert(new_id);
+        self.emit(Event::PageAlloc { page: new_id });
+        Ok(new_id)
+    }
+
+    /// Whether anything was allocated or released since the last snapshot.
+    pub fn has_changes(&self) -> bool {
+        !self.fresh.is_empty() || !self.pending.is_empty() || !self.sealed.is_empty()
+    }
+
+    /// Freezes the pages the tree uses for a read view. Later writes shadow
+    /// them as they do published pages, and pages released from now on are
+    /// only reused once every view that might read them is dropped.
+    ///
+    /// Returns a pager that reads the frozen pages through its own cache, as
+    /// large as this pager's, and a clone of the file handle, together with
+    /// the view's registration.
+    /// The clone shares the file cursor, which is safe because all page I/O
+    /// is positioned.
+    pub fn open_view(&mut self) -> Result<(Pager, ViewPin)> {
+        // The view reads from the file, so it must hold every page written
+        // so far.
+        self.flush()?;
+        let mut pager = Pager::new(self.file.try_clone()?, self.page_size);
+        let capacity = locked(&self.pool).capacity();
+        pager.configure_pool(capacity, Box::new(Lru::default()))?;
+        pager.num_pages = self.num_pages;
+        pager.format_version = self.format_version;
+        pager.sequence = self.sequence;
+        pager.flags = self.flags;
+
+        self.sealed.extend(self.fresh.drain());
+        self.epoch += 1;
+        *locked(&self.views).entry(self.epoch).or_default() += 1;
+        let pin = ViewPin {
+            epoch: self.epoch,
+            views: Arc::clone(&self.views),
+        };
+        Ok((pager, pin))
+    }
+
+    /// Frees the retired pages no open view can reach: those released
+    /// before the oldest open view was opened.
+    fn reclaim(&mut self) -> Result<()> {
+        if self.retired.is_empty() {
+            return Ok(());
+        }
+        let oldest = locked(&self.views).keys().next().copied();
+        let (free, kept) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|&(epoch, _)| oldest.is_none_or(|oldest| epoch < oldest));
+        self.retired = kept;
+        for (_, page_id) in free {
+            self.mark_free(page_id)?;
         }
+        Ok(())
+    }
 
-        let offset = (new_id as u64) * (self.page_size as u64);
-        self.file.seek(std::io::SeekFrom::Start(offset))?;
+    /// Frees a page the current tree no longer uses, or keeps it until the
+    /// read views open now have closed.
+    fn retire(&mut self, page_id: PageID) -> Result<()> {
+        if locked(&self.views).is_empty() {
+            self.mark_free(page_id)
+        } else {
+            self.released.insert(page_id);
+            self.retired.push((self.epoch, page_id));
+            Ok(())
+        }
+    }
 
-        let write_result = self.file.write_all(&vec![0u8; self.page_size]);
+    /// Returns a relocated page whose parent has not been repointed yet,
+    /// together with its new id.
+    pub fn next_unlinked(&mut self) -> Option<(PageID, PageID)> {
+        let old = self.unlinked.pop()?;
+        Some((old, self.relocated[&old]))
+    }
 
-        if write_result.is_err() {
-            write_result?;
+    /// Forgets the old ids of relocated pages once nothing refers to them,
+    /// and only then releases the pages they name.
+    pub fn clear_relocations(&mut self) -> Result<()> {
+        debug_assert!(self.unlinked.is_empty());
+        for (old, _) in std::mem::take(&mut self.relocated) {
+            self.release(old)?;
         }
+        Ok(())
+    }
 
-        self.num_pages = new_id;
-        Ok(new_id)
+    /// Where writes to `page_id` go. A page of the published snapshot is
+    /// never overwritten: its first write moves it to a freshly allocated
+    /// page and later reads and writes of the old id follow it there. The
+    /// old page stays allocated until `clear_relocations`, so the operation
+    /// cannot hand it out again while its id still leads to the copy.
+    fn shadow(&mut self, page_id: PageID) -> Result<PageID> {
+        if let Some(&moved) = self.relocated.get(&page_id) {
+            return Ok(moved);
+        }
+        if self.fresh.contains(&page_id) {
+            return Ok(page_id);
+        }
+
+        let moved = self.allocate_page()?;
+        self.relocated.insert(page_id, moved);
+        self.unlinked.push(page_id);
+        Ok(moved)
+    }
+
+    fn release(&mut self, page_id: PageID) -> Result<()> {
+        if self.fresh.contains(&page_id) {
+            self.mark_free(page_id)
+        } else if self.sealed.remove(&page_id) {
+            // Never published, so only read views can still need it.
+            self.retire(page_id)
+        } else {
+            self.released.insert(page_id);
+            self.pending.push(page_id);
+            Ok(())
+        }
     }
 }
 
 impl Pager {
+    /// Writes the free list of the snapshot about to be published to newly
+    /// allocated pages and returns the first of them with the entry count.
+    /// Pages the published snapshot uses are listed but left untouched.
+    pub fn write_free_list(&mut self) -> Result<(PageID, u32)> {
+        let per_page = (self.page_size - self.header_size() - FREE_LIST_LINK_SIZE) / 4;
+        self.reclaim()?;
+        let mut pages = Vec::new();
+        loop {
+            let total = self.free.len()
+                + self.pending.len()
+                + self.free_list_pages.len()
+                + self.retired.len();
+            if pages.len() >= total.div_ceil(per_page) {
+                break;
+            }
+            // A free page taken for the list is no longer listed. When that
+            // would leave the list needing fewer pages than it has, the page
+            // comes from the end of the file instead, so that every list page
+            // holds entries and is found again when the list is loaded.
+            let page = if (total - 1).div_ceil(per_page) > pages.len() {
+                self.allocate_page()?
+            } else {
+                self.extend()?
+            };
+            pages.push(page);
+        }
+
+        let entries: Vec<PageID> = self
+            .free
+            .iter()
+            .chain(&self.pending)
+            .chain(&self.free_list_pages)
+            .copied()
+            .chain(self.retired.iter().map(|&(_, id)| id))
+            .collect();
+        for (i, chunk) in entries.chunks(per_page).enumerate() {
+            let next = pages.get(i + 1).copied().unwrap_or(0);
+            let mut buf = self.new_page_buf(FREE_LIST_PAGE);
+            buf.extend_from_slice(&next.to_le_bytes());
+            buf.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            for id in chunk {
+                buf.extend_from_slice(&id.to_le_bytes());
+            }
+            buf.resize(self.page_size, 0);
+            self.write_raw(pages[i], buf)?;
+        }
+
+        let first = pages.first().copied().unwrap_or(0);
+        self.staged_free_list = pages;
+        Ok((first, entries.len() as u32))
+    }
+
+    /// Called once the snapshot numbered `sequence` is durable. Pages only
+    /// the previous snapshot used can be handed out again, and everything
+    /// written so far is now part of the published image.
+    pub fn publish(&mut self, sequence: u64) -> Result<()> {
+        let released = std::mem::take(&mut self.pending);
+        let old_free_list = std::mem::replace(
+            &mut self.free_list_pages,
+            std::mem::take(&mut self.staged_free_list),
+        );
+        self.fresh.clear();
+        self.sealed.clear();
+        self.sequence = sequence;
+        self.serials = 0;
+
+        for page_id in released {
+            self.retire(page_id)?;
+        }
+        for page_id in old_free_list {
+            self.mark_free(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the free list of the snapshot `metadata` describes.
+    pub fn load_free_list(&mut self, metadata: &BtreeMetadata) -> Result<()> {
+        let mut next = metadata.free_list_head;
+        let mut seen = HashSet::new();
+        while next != 0 && self.free.len() < metadata.free_pages as usize {
+            if !seen.insert(next) {
+                return Err(corrupt(next, "free list loops back on itself"));
+            }
+
+            if metadata.version < 3 {
+                // Older snapshots chain the free pages themselves together.
+                self.released.insert(next);
+                self.free.push(next);
+                next = self.next_free(next)?;
+                continue;
+            }
+
+            let buf = self.read_raw(next)?;
+            if buf[0] != FREE_LIST_PAGE {
+                return Err(corrupt(
+                    next,
+                    format!("expected a free list page, found type {}", buf[0]),
+                ));
+            }
+            self.free_list_pages.push(next);
+
+            let header = self.header_size();
+            let (link, _) = PageID::decode(tail(&buf, header, next)?)?;
+            let (count, _) = u32::decode(tail(&buf, header + 4, next)?)?;
+            for i in 0..count as usize {
+                let (id, _) =
+                    PageID::decode(tail(&buf, header + FREE_LIST_LINK_SIZE + i * 4, next)?)?;
+                if id == 0 || id > self.num_pages {
+                    return Err(corrupt(next, format!("free list names page {id}")));
+                }
+                self.released.insert(id);
+                self.free.push(id);
+            }
+            next = link;
+        }
+        Ok(())
+    }
+}
+
+impl Pager {
+    /// Writes `metadata` into the slot its sequence number selects, leaving
+    /// the other slot (the previous snapshot) intact. Formats before version
+    /// 3 have a single slot filling page 0.
     pub fn write_metadata(&mut self, metadata: &BtreeMetadata) -> Result<()> {
-        let data = metadata.serialize();
+        let mut data = metadata.serialize();
+        let offset = if metadata.version >= 3 {
+            if self.page_size < 2 * BtreeMetadata::SLOT_SIZE {
+                return Err(IndexError::InvalidInput(format!(
+                    "Page size {} is too small for metadata",
+                    self.page_size
+                ))
+                .into());
+            }
+            (metadata.sequence % 2) as usize * BtreeMetadata::SLOT_SIZE
+        } else {
+            data.resize(self.page_size, 0);
+            0
+        };
 
-        self.file.seek(std::io::SeekFrom::Start(0))?;
-        self.file.write_all(&data)?;
+        self.file.write_all_at(&data, offset as u64)?;
 
         Ok(())
     }
 
+    /// Returns the newest intact metadata slot. A slot written in a newer
+    /// format than this build reads fails the whole file: the other slot is
+    /// an older snapshot that must not be opened, and written over, instead.
     pub fn read_metadata(&mut self) -> Result<BtreeMetadata> {
         let mut buf = vec![0u8; self.page_size];
 
-        self.file.seek(std::io::SeekFrom::Start(0))?;
-        self.file.read_exact(&mut buf)?;
+        self.file.read_exact_at(&mut buf, 0)?;
+
+        let first = BtreeMetadata::deserialize(&buf).map_err(IndexError::from);
+        if let Ok(metadata) = &first
+            && metadata.version < 3
+        {
+            return Ok(first?);
+        }
+
+        let second = match buf.get(BtreeMetadata::SLOT_SIZE..) {
+            Some(slot) => BtreeMetadata::deserialize(slot).map_err(IndexError::from),
+            None => return Ok(first?),
+        };
+        match (first, second) {
+            (Err(e @ IndexError::VersionMismatch { .. }), _)
+            | (_, Err(e @ IndexError::VersionMismatch { .. })) => Err(e.into()),
+            (Ok(a), Ok(b)) => Ok(if b.sequence > a.sequence { b } else { a }),
+            (Ok(a), Err(_)) => Ok(a),
+            (Err(_), Ok(b)) => Ok(b),
+            (Err(e), Err(_)) => Err(e.into()),
+        }
+    }
+}
+
+// Page type tags stored in the first byte of every page. Types 0 and 1 are
+// the original layout where values are always inline; they are still read
+// but no longer written.
+const LEAF_PAGE: u8 = 0;
+const INTERNAL_PAGE: u8 = 1;
+const SLOTTED_LEAF_PAGE: u8 = 2;
+const SLOTTED_INTERNAL_PAGE: u8 = 3;
+const OVERFLOW_PAGE: u8 = 4;
+const FREE_PAGE: u8 = 5;
+const FREE_LIST_PAGE: u8 = 6;
+const SEPARATOR_PAGE: u8 = 7;
+const LINKED_LEAF_PAGE: u8 = 8;
+const COUNTED_INTERNAL_PAGE: u8 = 9;
+const STABLE_LEAF_PAGE: u8 = 10;
+
+// In slotted pages every value is preceded by one of these markers.
+const INLINE_VALUE: u8 = 0;
+const OVERFLOW_VALUE: u8 = 1;
 
-        BtreeMetadata::deserialize(&buf)
+/// How a file stores the keys of its node pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyLayout {
+    /// Whole keys, as before version 6.
+    Plain,
+    /// Prefix-compressed keys on every page, as in version 6.
+    Prefixed,
+    /// From version 7 on, each page uses whichever of the two is smaller and
+    /// sets `PREFIXED_KEYS` in its item count when it compresses.
+    PerPage,
+}
+
+/// Item count bit marking a page whose keys are prefix-compressed; see
+/// `KeyLayout::PerPage`.
+const PREFIXED_KEYS: u32 = 1 << 31;
+
+/// Pages start with a header holding the type, the page's own id and a CRC32
+/// of the whole page taken with the checksum field zeroed. Version 1 files
+/// predate this and only carry the type byte.
+const PAGE_HEADER_SIZE: usize = 9;
+const LEGACY_HEADER_SIZE: usize = 1;
+const CHECKSUM_OFFSET: usize = 5;
+
+/// Page sizes a pager accepts. A page must hold both metadata slots, which
+/// also leaves room for every page header and link.
+const MIN_PAGE_SIZE: usize = 2 * BtreeMetadata::SLOT_SIZE;
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+fn check_page_size(page_size: usize) -> Result<()> {
+    if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(IndexError::InvalidInput(format!(
+            "Invalid page size {page_size}: must be between {MIN_PAGE_SIZE} and {MAX_PAGE_SIZE}"
+        ))
+        .into());
     }
+    Ok(())
+}
+
+/// Size of an out-of-line value reference: total length plus first page.
+const OVERFLOW_REF_SIZE: usize = 8;
+/// Overflow pages follow the page header with the next page in the chain
+/// and the number of bytes used.
+const OVERFLOW_LINK_SIZE: usize = 8;
+/// Free list pages follow the page header with the next free list page and
+/// the number of page ids stored in this one.
+const FREE_LIST_LINK_SIZE: usize = 8;
+
+enum ValueSlot<V> {
+    Inline(V),
+    Overflow { len: u32, first: PageID },
+}
+
+/// A node page as laid out on disk, before overflow values are read back.
+/// Separator pages have keys but no values.
+struct RawNode<K, V> {
+    page_type: u8,
+    keys: Vec<K>,
+    values: Vec<ValueSlot<V>>,
+    children: Vec<PageID>,
+    /// Subtree item counts, one per child, on counted internal pages.
+    counts: Vec<u64>,
+    /// Serial of a linked leaf page.
+    serial: u64,
+    /// Previous and next leaf of a linked leaf page.
+    siblings: (Link, Link),
 }
 
 impl Pager {
-    pub fn write_page(&mut self, page: &Page) -> Result<()> {
-        let mut buf = vec![0u8; self.page_size];
-        let page_id: u32;
-        match page {
-            Page::Internal {
+    /// Encodes a node page. Values are kept inline when the page allows it;
+    /// otherwise the largest ones move to overflow chains until it fits.
+    /// Chains the previous version of the page pointed at are reused when
+    /// their contents are unchanged and freed otherwise.
+    pub fn write_page<K: KeyCodec, V: Codec>(&mut self, page: &Page<K, V>) -> Result<()> {
+        let (page_id, page_type, children, siblings) = match page {
+            Page::Internal { id, children, .. } if self.counts_subtrees() => {
+                (*id, COUNTED_INTERNAL_PAGE, children.as_slice(), None)
+            }
+            Page::Internal { id, children, .. } => {
+                (*id, SLOTTED_INTERNAL_PAGE, children.as_slice(), None)
+            }
+            Page::Leaf { id, .. } => (*id, SLOTTED_LEAF_PAGE, &[][..], None),
+            Page::Separators { id, children, .. } => {
+                (*id, SEPARATOR_PAGE, children.as_slice(), None)
+            }
+            Page::LinkedLeaf {
                 id,
-                items,
-                children,
-            } => {
-                page_id = *id;
-
-                buf[0] = 1; // 1-> internal type
-                let items_count = items.len() as u32;
-                buf[1..5].copy_from_slice(&items_count.to_le_bytes());
-
-                let mut offset = 5;
-                for item in items {
-                    buf[offset..offset + 4].copy_from_slice(&item.key.to_le_bytes());
-                    offset += 4;
-
-                    let val_bytes = item.val.as_bytes();
-                    let len = val_bytes.len() as u32;
-                    buf[offset..offset + 4].copy_from_slice(&len.to_le_bytes());
-                    offset += 4;
-                    buf[offset..offset + len as usize].copy_from_slice(val_bytes);
-                    offset += len as usize;
-                }
+                serial,
+                prev,
+                next,
+                ..
+            } if self.stable_leaves() => (
+                *id,
+                STABLE_LEAF_PAGE,
+                &[][..],
+                Some((*serial, *prev, *next)),
+            ),
+            Page::LinkedLeaf { id, prev, next, .. } => {
+                (*id, LINKED_LEAF_PAGE, &[][..], Some((0, *prev, *next)))
+            }
+        };
 
-                for c in children {
-                    buf[offset..offset + 4].copy_from_slice(&c.to_le_bytes());
-                    offset += 4;
+        let keys: Vec<&K> = match page {
+            Page::Separators { keys, .. } => keys.iter().collect(),
+            Page::Internal { items, .. }
+            | Page::Leaf { items, .. }
+            | Page::LinkedLeaf { items, .. } => items.iter().map(|item| &item.key).collect(),
+        };
+        let plain = encode_keys(keys.iter().copied(), false);
+        let (prefixed, keys) = match self.key_layout() {
+            KeyLayout::Plain => (false, plain),
+            KeyLayout::Prefixed => (true, encode_keys(keys.iter().copied(), true)),
+            KeyLayout::PerPage => {
+                let compressed = encode_keys(keys.iter().copied(), true);
+                let size = |keys: &[Vec<u8>]| keys.iter().map(Vec::len).sum::<usize>();
+                if size(&compressed) < size(&plain) {
+                    (true, compressed)
+                } else {
+                    (false, plain)
                 }
             }
+        };
+        let encoded: Vec<(Vec<u8>, Option<Vec<u8>>)> = match page {
+            Page::Separators { .. } => keys.into_iter().map(|key| (key, None)).collect(),
+            Page::Internal { items, .. }
+            | Page::Leaf { items, .. }
+            | Page::LinkedLeaf { items, .. } => keys
+                .into_iter()
+                .zip(items)
+                .map(|(key, item)| (key, Some(encoded(&item.val))))
+                .collect(),
+        };
+
+        let counts = match page {
+            Page::Internal { counts, .. } if page_type == COUNTED_INTERNAL_PAGE => {
+                counts.as_slice()
+            }
+            _ => &[][..],
+        };
 
-            Page::Leaf { id, items } => {
-                page_id = *id;
+        let mut size = self.header_size() + 4 + children.len() * 4 + counts.len() * 8;
+        match page_type {
+            STABLE_LEAF_PAGE => size += 32,
+            LINKED_LEAF_PAGE => size += 8,
+            _ => {}
+        }
+        for (key, val) in &encoded {
+            size += key.len() + val.as_ref().map_or(0, |val| 1 + val.len());
+        }
 
-                buf[0] = 0; // 0-> Leaf type
-                let items_count = items.len() as u32;
-                buf[1..5].copy_from_slice(&items_count.to_le_bytes());
+        let value_len = |i: usize| encoded[i].1.as_ref().map_or(0, Vec::len);
+        let mut spilled = vec![false; encoded.len()];
+        while size > self.page_size {
+            let largest = (0..encoded.len())
+                .filter(|&i| !spilled[i] && value_len(i) > OVERFLOW_REF_SIZE)
+                .max_by_key(|&i| value_len(i));
+            let Some(i) = largest else {
+                return Err(IndexError::PageFull {
+                    page: page_id,
+                    needed: size,
+                    page_size: self.page_size,
+                }
+                .into());
+            };
+            spilled[i] = true;
+            size = size - value_len(i) + OVERFLOW_REF_SIZE;
+        }
 
-                let mut offset = 5;
-                for item in items {
-                    buf[offset..offset + 4].copy_from_slice(&item.key.to_le_bytes());
-                    offset += 4;
+        let mut old_chains = self.overflow_chains::<K, V>(page_id)?;
 
-                    let val_bytes = item.val.as_bytes();
-                    let len = val_bytes.len() as u32;
-                    buf[offset..offset + 4].copy_from_slice(&len.to_le_bytes());
-                    offset += 4;
-                    buf[offset..offset + len as usize].copy_from_slice(val_bytes);
-                    offset += len as usize;
+        let mut count = encoded.len() as u32;
+        if prefixed && self.key_layout() == KeyLayout::PerPage {
+            count |= PREFIXED_KEYS;
+        }
+        let mut buf = self.new_page_buf(page_type);
+        buf.extend_from_slice(&count.to_le_bytes());
+        if let Some((serial, prev, next)) = siblings {
+            if page_type == STABLE_LEAF_PAGE {
+                buf.extend_from_slice(&serial.to_le_bytes());
+            }
+            for link in [prev, next] {
+                buf.extend_from_slice(&link.page.to_le_bytes());
+                if page_type == STABLE_LEAF_PAGE {
+                    buf.extend_from_slice(&link.serial.to_le_bytes());
                 }
             }
         }
-        self.file.seek(std::io::SeekFrom::Start(
-            (page_id as u64) * self.page_size as u64,
-        ))?;
-        self.file.write_all(&buf)?;
+        for ((key, val), spilled) in encoded.iter().zip(spilled) {
+            buf.extend_from_slice(key);
+            let Some(val) = val else {
+                continue;
+            };
+            if spilled {
+                let first = self.store_overflow(val, &mut old_chains)?;
+                buf.push(OVERFLOW_VALUE);
+                buf.extend_from_slice(&(val.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&first.to_le_bytes());
+            } else {
+                buf.push(INLINE_VALUE);
+                buf.extend_from_slice(val);
+            }
+        }
+        for c in children {
+            buf.extend_from_slice(&c.to_le_bytes());
+        }
+        for count in counts {
+            buf.extend_from_slice(&count.to_le_bytes());
+        }
+        buf.resize(self.page_size, 0);
+
+        for (_, first) in old_chains {
+            self.free_chain(first)?;
+        }
+
+        let target = self.shadow(page_id)?;
+        self.write_raw(target, buf)
+    }
+
+    pub fn read_page<K: KeyCodec, V: Codec>(&self, page_id: PageID) -> Result<Page<K, V>> {
+        let raw = self.decode_node::<K, V>(&self.read_raw(page_id)?, page_id)?;
+        if raw.page_type == SEPARATOR_PAGE {
+            return Ok(Page::Separators {
+                id: page_id,
+                keys: raw.keys,
+                children: raw.children,
+            });
+        }
+
+        let mut items = Vec::with_capacity(raw.keys.len());
+        for (key, slot) in raw.keys.into_iter().zip(raw.values) {
+            let val = match slot {
+                ValueSlot::Inline(val) => val,
+                ValueSlot::Overflow { len, first } => {
+                    let bytes = self.load_overflow(first, len)?;
+                    V::decode(&bytes)?.0
+                }
+            };
+            items.push(Item { key, val });
+        }
+
+        Ok(match raw.page_type {
+            LEAF_PAGE | SLOTTED_LEAF_PAGE => Page::Leaf { id: page_id, items },
+            LINKED_LEAF_PAGE | STABLE_LEAF_PAGE => Page::LinkedLeaf {
+                id: page_id,
+                serial: raw.serial,
+                items,
+                prev: raw.siblings.0,
+                next: raw.siblings.1,
+            },
+            _ => Page::Internal {
+                id: page_id,
+                items,
+                counts: if raw.counts.is_empty() {
+                    vec![0; raw.children.len()]
+                } else {
+                    raw.counts
+                },
+                children: raw.children,
+            },
+        })
+    }
+
+    /// Releases a node page together with any overflow chains it references.
+    pub fn free_page<K: KeyCodec, V: Codec>(&mut self, page_id: PageID) -> Result<()> {
+        self.emit(Event::PageFree { page: page_id });
+        for (_, first) in self.overflow_chains::<K, V>(page_id)? {
+            self.free_chain(first)?;
+        }
+
+        self.unlinked.retain(|&id| id != page_id);
+        if let Some(moved) = self.relocated.remove(&page_id) {
+            self.release(moved)?;
+        }
+        self.release(page_id)
+    }
+
+    /// Returns a copy of the raw bytes of `page_id`, following it if it was
+    /// relocated by the current operation.
+    pub fn read_raw(&self, page_id: PageID) -> Result<Vec<u8>> {
+        let page_id = self.relocated.get(&page_id).copied().unwrap_or(page_id);
+        if page_id == 0 || page_id > self.num_pages {
+            return Err(corrupt(
+                page_id,
+                format!("page is outside the file's {} pages", self.num_pages),
+            ));
+        }
+
+        let mut pool = locked(&self.pool);
+        let frame = pool.fetch(&self.file, self.page_size, page_id, &|buf| {
+            self.verify_page(buf, page_id)
+        })?;
+        let data = pool.data(frame).to_vec();
+        pool.unpin(frame);
+        Ok(data)
+    }
+
+    /// Whether internal pages of this file record subtree item counts.
+    pub fn counts_subtrees(&self) -> bool {
+        self.format_version >= 5
+    }
+
+    fn key_layout(&self) -> KeyLayout {
+        match self.format_version {
+            7.. => KeyLayout::PerPage,
+            6 => KeyLayout::Prefixed,
+            _ => KeyLayout::Plain,
+        }
+    }
+
+    fn decode_node<K: KeyCodec, V: Codec>(
+        &self,
+        buf: &[u8],
+        page_id: PageID,
+    ) -> Result<RawNode<K, V>> {
+        decode_node(buf, page_id, self.header_size(), self.key_layout())
+    }
+
+    fn header_size(&self) -> usize {
+        if self.format_version >= 2 {
+            PAGE_HEADER_SIZE
+        } else {
+            LEGACY_HEADER_SIZE
+        }
+    }
+
+    /// Starts a page buffer of `page_type` with room reserved for the header.
+    fn new_page_buf(&self, page_type: u8) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.page_size);
+        buf.push(page_type);
+        buf.resize(self.header_size(), 0);
+        buf
+    }
+
+    /// Fills in the page id and checksum of a full page buffer.
+    fn stamp_header(&self, buf: &mut [u8], page_id: PageID) {
+        if self.format_version < 2 {
+            return;
+        }
+        buf[1..CHECKSUM_OFFSET].copy_from_slice(&page_id.to_le_bytes());
+        buf[CHECKSUM_OFFSET..PAGE_HEADER_SIZE].fill(0);
+        let checksum = crc32fast::hash(buf);
+        buf[CHECKSUM_OFFSET..PAGE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    fn verify_page(&self, buf: &[u8], page_id: PageID) -> Result<()> {
+        if self.format_version < 2 {
+            return Ok(());
+        }
+
+        let stored = u32::from_le_bytes(buf[CHECKSUM_OFFSET..PAGE_HEADER_SIZE].try_into().unwrap());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&buf[..CHECKSUM_OFFSET]);
+        hasher.update(&[0u8; PAGE_HEADER_SIZE - CHECKSUM_OFFSET]);
+        hasher.update(&buf[PAGE_HEADER_SIZE..]);
+        let actual = hasher.finalize();
+        if stored != actual {
+            return Err(corrupt(
+                page_id,
+                format!("checksum mismatch (stored {stored:#010x}, computed {actual:#010x})"),
+            ));
+        }
+
+        let stored_id = u32::from_le_bytes(buf[1..CHECKSUM_OFFSET].try_into().unwrap());
+        if stored_id != page_id {
+            return Err(corrupt(
+                page_id,
+                format!("header belongs to page {stored_id}"),
+            ));
+        }
+
+        if buf[0] > STABLE_LEAF_PAGE {
+            return Err(corrupt(page_id, format!("unknown page type {}", buf[0])));
+        }
         Ok(())
     }
 
-    pub fn read_page(&mut self, page_id: PageID) -> Result<Page> {
-        let mut buf = vec![0u8; self.page_size];
-        self.file.seek(std::io::SeekFrom::Start(
-            (page_id as u64) * (self.page_size as u64),
-        ))?;
-        self.file.read_exact(&mut buf)?;
+    fn write_raw(&mut self, page_id: PageID, mut buf: Vec<u8>) -> Result<()> {
+        self.stamp_header(&mut buf, page_id);
+        let pool = self.pool.get_mut().unwrap_or_else(PoisonError::into_inner);
+        pool.put(&self.file, self.page_size, page_id, buf)
+    }
 
-        let page_type = buf[0];
-        if page_type == 1 {
-            let items_count = u32::from_le_bytes(buf[1..5].try_into().unwrap());
+    fn mark_free(&mut self, page_id: PageID) -> Result<()> {
+        self.write_free(page_id)?;
+        self.released.insert(page_id);
+        self.free.push(page_id);
+        Ok(())
+    }
 
-            let mut offset = 5;
-            let mut items = Vec::with_capacity(items_count as usize);
+    fn write_free(&mut self, page_id: PageID) -> Result<()> {
+        let mut buf = self.new_page_buf(FREE_PAGE);
+        buf.resize(self.page_size, 0);
+        self.write_raw(page_id, buf)
+    }
 
-            for _ in 0..items_count {
-                let key = i32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
-                offset += 4;
+    /// Follows the link stored in a free page by version 2 snapshots.
+    fn next_free(&self, page_id: PageID) -> Result<PageID> {
+        let buf = self.read_raw(page_id)?;
+        if buf[0] != FREE_PAGE {
+            return Err(corrupt(
+                page_id,
+                format!("free list entry has page type {}", buf[0]),
+            ));
+        }
 
-                let val_len =
-                    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize;
-                offset += 4;
-                let val = String::from_utf8_lossy(&buf[offset..offset + val_len]).to_string();
-                offset += val_len;
+        let (next, _) = PageID::decode(tail(&buf, self.header_size(), page_id)?)?;
+        if next > self.num_pages {
+            return Err(corrupt(page_id, format!("free list links to page {next}")));
+        }
+        Ok(next)
+    }
 
-                items.push(Item { key, val });
-            }
+    /// Lists the `(length, first page)` of every overflow chain referenced by
+    /// the node currently stored at `page_id`.
+    fn overflow_chains<K: KeyCodec, V: Codec>(
+        &self,
+        page_id: PageID,
+    ) -> Result<Vec<(u32, PageID)>> {
+        let buf = self.read_raw(page_id)?;
+        if !matches!(
+            buf[0],
+            SLOTTED_LEAF_PAGE
+                | SLOTTED_INTERNAL_PAGE
+                | LINKED_LEAF_PAGE
+                | COUNTED_INTERNAL_PAGE
+                | STABLE_LEAF_PAGE
+        ) {
+            return Ok(Vec::new());
+        }
 
-            let mut children = Vec::with_capacity((items_count + 1) as usize);
-            for _ in 0..=items_count {
-                children.push(u32::from_le_bytes(
-                    buf[offset..offset + 4].try_into().unwrap(),
-                ));
-                offset += 4;
+        Ok(self
+            .decode_node::<K, V>(&buf, page_id)?
+            .values
+            .into_iter()
+            .filter_map(|slot| match slot {
+                ValueSlot::Overflow { len, first } => Some((len, first)),
+                ValueSlot::Inline(_) => None,
+            })
+            .collect())
+    }
+
+    /// Stores `bytes` in an overflow chain and returns its first page. An
+    /// identical chain from `candidates` is reused (and removed from the list)
+    /// instead of writing a new one.
+    fn store_overflow(
+        &mut self,
+        bytes: &[u8],
+        candidates: &mut Vec<(u32, PageID)>,
+    ) -> Result<PageID> {
+        for i in 0..candidates.len() {
+            let (len, first) = candidates[i];
+            if len as usize == bytes.len() && self.load_overflow(first, len)? == bytes {
+                candidates.swap_remove(i);
+                return Ok(first);
             }
+        }
 
-            Ok(Page::Internal {
-                id: page_id,
-                items,
-                children,
-            })
-        } else if page_type == 0 {
-            let items_count = u32::from_le_bytes(buf[1..5].try_into().unwrap());
-            let mut items = Vec::with_capacity(items_count as usize);
-            let mut offset = 5;
+        let chunk_size = self.page_size - self.header_size() - OVERFLOW_LINK_SIZE;
+        let chunks: Vec<&[u8]> = bytes.chunks(chunk_size).collect();
+        let mut ids = Vec::with_capacity(chunks.len());
+        for _ in &chunks {
+            ids.push(self.allocate_page()?);
+        }
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = ids.get(i + 1).copied().unwrap_or(0);
+            let mut buf = self.new_page_buf(OVERFLOW_PAGE);
+            buf.extend_from_slice(&next.to_le_bytes());
+            buf.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            buf.extend_from_slice(chunk);
+            buf.resize(self.page_size, 0);
+            self.write_raw(ids[i], buf)?;
+        }
 
-            for _ in 0..items_count {
-                let key = i32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
-                offset += 4;
+        Ok(ids[0])
+    }
 
-                let val_len =
-                    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize;
-                offset += 4;
-                let val = String::from_utf8_lossy(&buf[offset..offset + val_len]).to_string();
-                offset += val_len;
+    fn load_overflow(&self, first: PageID, len: u32) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(len as usize);
+        let mut next = first;
+        while next != 0 {
+            let buf = self.read_raw(next)?;
+            let (page_next, data) = parse_overflow_page(&buf, next, self.header_size())?;
+            bytes.extend_from_slice(data);
+            next = page_next;
+        }
 
-                items.push(Item { key, val });
-            }
+        if bytes.len() != len as usize {
+            return Err(corrupt(
+                first,
+                format!("overflow chain holds {} bytes, expected {len}", bytes.len()),
+            ));
+        }
+        Ok(bytes)
+    }
+
+    fn free_chain(&mut self, first: PageID) -> Result<()> {
+        let mut next = first;
+        while next != 0 {
+            let buf = self.read_raw(next)?;
+            let (page_next, _) = parse_overflow_page(&buf, next, self.header_size())?;
+            self.release(next)?;
+            next = page_next;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the next page in the chain and the bytes stored in this page.
+fn parse_overflow_page(buf: &[u8], page_id: PageID, header: usize) -> Result<(PageID, &[u8])> {
+    if buf[0] != OVERFLOW_PAGE {
+        return Err(corrupt(
+            page_id,
+            format!("expected an overflow page, found type {}", buf[0]),
+        ));
+    }
 
-            Ok(Page::Leaf { id: page_id, items })
+    let (next, _) = PageID::decode(tail(buf, header, page_id)?)?;
+    let (used, _) = u32::decode(tail(buf, header + 4, page_id)?)?;
+    let start = header + OVERFLOW_LINK_SIZE;
+    let data = buf
+        .get(start..start + used as usize)
+        .ok_or_else(|| corrupt(page_id, format!("overflow length {used} exceeds the page")))?;
+    Ok((next, data))
+}
+
+fn encoded<T: Codec>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf
+}
+
+fn tail(buf: &[u8], offset: usize, page_id: PageID) -> Result<&[u8]> {
+    buf.get(offset..)
+        .ok_or_else(|| corrupt(page_id, "entries run past the end of the page"))
+}
+
+/// Encodes `keys`, which must be in page order. When `prefixed`, each one is
+/// stored as the length of the prefix it shares with the key before it and
+/// the remaining bytes, both lengths as varints.
+fn encode_keys<'a, K: KeyCodec + 'a>(
+    keys: impl Iterator<Item = &'a K>,
+    prefixed: bool,
+) -> Vec<Vec<u8>> {
+    let mut prev = Vec::new();
+    keys.map(|key| {
+        if !prefixed {
+            return encoded(key);
+        }
+        let mut bytes = Vec::new();
+        key.key_bytes(&mut bytes);
+        let shared = prev.iter().zip(&bytes).take_while(|(a, b)| a == b).count();
+        let mut entry = Vec::with_capacity(bytes.len() - shared + 2);
+        put_varint(shared, &mut entry);
+        put_varint(bytes.len() - shared, &mut entry);
+        entry.extend_from_slice(&bytes[shared..]);
+        prev = bytes;
+        entry
+    })
+    .collect()
+}
+
+/// Reads one key written by `encode_keys` with `prefixed` set. `prev` holds
+/// the bytes of the key before it and is replaced by this key's.
+fn decode_prefixed<K: KeyCodec>(buf: &[u8], prev: &mut Vec<u8>) -> io::Result<(K, usize)> {
+    let (shared, mut used) = get_varint(buf)?;
+    let (len, n) = get_varint(&buf[used..])?;
+    used += n;
+    let suffix = buf[used..].get(..len).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Truncated key suffix in page")
+    })?;
+    if shared > prev.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Key shares {shared} bytes with a {}-byte key", prev.len()),
+        ));
+    }
+    prev.truncate(shared);
+    prev.extend_from_slice(suffix);
+    Ok((K::from_key_bytes(prev)?, used + len))
+}
+
+fn decode_node<K: KeyCodec, V: Codec>(
+    buf: &[u8],
+    page_id: PageID,
+    header: usize,
+    layout: KeyLayout,
+) -> Result<RawNode<K, V>> {
+    let in_page = |e: io::Error| corrupt(page_id, e.to_string());
+
+    let page_type = buf[0];
+    // (has values, values carry a slot marker, has children, has siblings)
+    let (valued, slotted, internal, linked) = match page_type {
+        LEAF_PAGE => (true, false, false, false),
+        INTERNAL_PAGE => (true, false, true, false),
+        SLOTTED_LEAF_PAGE => (true, true, false, false),
+        SLOTTED_INTERNAL_PAGE | COUNTED_INTERNAL_PAGE => (true, true, true, false),
+        SEPARATOR_PAGE => (false, false, true, false),
+        LINKED_LEAF_PAGE | STABLE_LEAF_PAGE => (true, true, false, true),
+        _ => {
+            return Err(corrupt(
+                page_id,
+                format!("expected a node page, found type {page_type}"),
+            ));
+        }
+    };
+
+    let (mut items_count, mut offset) =
+        u32::decode(tail(buf, header, page_id)?).map_err(in_page)?;
+    offset += header;
+    let prefixed = match layout {
+        KeyLayout::Plain => false,
+        KeyLayout::Prefixed => true,
+        KeyLayout::PerPage => {
+            let prefixed = items_count & PREFIXED_KEYS != 0;
+            items_count &= !PREFIXED_KEYS;
+            prefixed
+        }
+    };
+    if items_count as usize > buf.len() {
+        return Err(corrupt(
+            page_id,
+            format!("implausible item count {items_count}"),
+        ));
+    }
+
+    // Leaves without a stored serial are known by their page id.
+    let mut serial = page_id as u64;
+    let mut siblings = (Link::NONE, Link::NONE);
+    if page_type == STABLE_LEAF_PAGE {
+        let (own, used) = u64::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+        serial = own;
+        offset += used;
+        for link in [&mut siblings.0, &mut siblings.1] {
+            let ((page, serial), used) =
+                <(PageID, u64)>::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+            *link = Link { page, serial };
+            offset += used;
+        }
+    } else if linked {
+        let ((prev, next), used) =
+            <(PageID, PageID)>::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+        siblings = (
+            Link {
+                page: prev,
+                serial: prev as u64,
+            },
+            Link {
+                page: next,
+                serial: next as u64,
+            },
+        );
+        offset += used;
+    }
+
+    let mut keys = Vec::with_capacity(items_count as usize);
+    let mut values = Vec::new();
+    let mut prev = Vec::new();
+    for _ in 0..items_count {
+        let entry = tail(buf, offset, page_id)?;
+        let (key, used) = if prefixed {
+            decode_prefixed(entry, &mut prev)
         } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Unknown page type: {page_type}"),
-            ))
+            K::decode(entry)
+        }
+        .map_err(in_page)?;
+        offset += used;
+        keys.push(key);
+        if !valued {
+            continue;
+        }
+
+        let marker = if slotted {
+            let (marker, used) = u8::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+            offset += used;
+            marker
+        } else {
+            INLINE_VALUE
+        };
+        let slot = match marker {
+            INLINE_VALUE => {
+                let (val, used) = V::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+                offset += used;
+                ValueSlot::Inline(val)
+            }
+            OVERFLOW_VALUE => {
+                let ((len, first), used) =
+                    <(u32, PageID)>::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+                offset += used;
+                ValueSlot::Overflow { len, first }
+            }
+            _ => {
+                return Err(corrupt(page_id, format!("unknown value marker {marker}")));
+            }
+        };
+        values.push(slot);
+    }
+
+    let mut children = Vec::new();
+    if internal {
+        children.reserve((items_count + 1) as usize);
+        for _ in 0..=items_count {
+            let (child, used) = PageID::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+            children.push(child);
+            offset += used;
         }
     }
+
+    let mut counts = Vec::new();
+    if page_type == COUNTED_INTERNAL_PAGE {
+        counts.reserve(children.len());
+        for _ in 0..children.len() {
+            let (count, used) = u64::decode(tail(buf, offset, page_id)?).map_err(in_page)?;
+            counts.push(count);
+            offset += used;
+        }
+    }
+
+    Ok(RawNode {
+        page_type,
+        keys,
+        values,
+        children,
+        counts,
+        serial,
+        siblings,
+    })
 }
@@ -0,0 +1,245 @@
+use std::{
+    cmp::Ordering,
+    ops::{Bound, RangeBounds},
+};
+
+use super::{
+    Item,
+    codec::{Codec, KeyCodec},
+    error::Result,
+    node::{Node, ValueOrder, cmp_item},
+    paging::{PageID, Pager},
+};
+
+/// In-order iterator over the items whose keys fall inside a range.
+///
+/// Each end keeps its own stack of `(node, index)` cursors. For the front
+/// cursor `index` is the next item to yield from that node, for the back
+/// cursor it is one past it. Nodes are paged in as the cursors reach them,
+/// so a read failure is yielded as an error and ends the iteration. The two
+/// ends stop once they meet, which with duplicate keys is decided by the
+/// tree's value order.
+pub struct Range<'a, K, V> {
+    pager: &'a Pager,
+    root: Option<PageID>,
+    front: Option<Vec<(Node<K, V>, usize)>>,
+    back: Option<Vec<(Node<K, V>, usize)>>,
+    lower: Bound<K>,
+    upper: Bound<K>,
+    values: ValueOrder<V>,
+    last_front: Option<Item<K, V>>,
+    last_back: Option<Item<K, V>>,
+    done: bool,
+}
+
+impl<'a, K: KeyCodec, V: Codec> Range<'a, K, V> {
+    pub(super) fn new<R: RangeBounds<K>>(
+        pager: &'a Pager,
+        root: Option<PageID>,
+        bounds: R,
+        values: ValueOrder<V>,
+    ) -> Self {
+        Range {
+            pager,
+            root,
+            front: None,
+            back: None,
+            lower: bounds.start_bound().cloned(),
+            upper: bounds.end_bound().cloned(),
+            values,
+            last_front: None,
+            last_back: None,
+            done: root.is_none(),
+        }
+    }
+
+    fn read_node(&self, id: PageID) -> Result<Node<K, V>> {
+        Ok(Node::from_page(&self.pager.read_page(id)?)?)
+    }
+
+    fn seek_front(&self, mut id: PageID) -> Result<Vec<(Node<K, V>, usize)>> {
+        let mut stack = Vec::new();
+        loop {
+            let node = self.read_node(id)?;
+            let pos = node.items.partition_point(|item| match &self.lower {
+                Bound::Included(lo) => item.key < *lo,
+                Bound::Excluded(lo) => item.key <= *lo,
+                Bound::Unbounded => false,
+            });
+            let next = (!node.is_leaf()).then(|| node.children[pos]);
+            stack.push((node, pos));
+            match next {
+                Some(child) => id = child,
+                None => return Ok(stack),
+            }
+        }
+    }
+
+    fn seek_back(&self, mut id: PageID) -> Result<Vec<(Node<K, V>, usize)>> {
+        let mut stack = Vec::new();
+        loop {
+            let node = self.read_node(id)?;
+            let pos = node.items.partition_point(|item| match &self.upper {
+                Bound::Included(hi) => item.key <= *hi,
+                Bound::Excluded(hi) => item.key < *hi,
+                Bound::Unbounded => true,
+            });
+            let next = (!node.is_leaf()).then(|| node.children[pos]);
+            stack.push((node, pos));
+            match next {
+                Some(child) => id = child,
+                None => return Ok(stack),
+            }
+        }
+    }
+
+    fn advance_front(&mut self) -> Result<Option<Item<K, V>>> {
+        if self.front.is_none()
+            && let Some(root) = self.root
+        {
+            self.front = Some(self.seek_front(root)?);
+        }
+
+        let mut descend = None;
+        let mut found = None;
+        let Some(stack) = self.front.as_mut() else {
+            return Ok(None);
+        };
+        while let Some((node, pos)) = stack.last_mut() {
+            if *pos >= node.items.len() {
+                stack.pop();
+                continue;
+            }
+
+            found = Some(node.items[*pos].clone());
+            *pos += 1;
+            if !node.is_leaf() {
+                descend = Some(node.children[*pos]);
+            }
+            break;
+        }
+
+        let mut next = descend;
+        while let Some(id) = next {
+            let node = self.read_node(id)?;
+            next = (!node.is_leaf()).then(|| node.children[0]);
+            if let Some(stack) = self.front.as_mut() {
+                stack.push((node, 0));
+            }
+        }
+        Ok(found)
+    }
+
+    fn advance_back(&mut self) -> Result<Option<Item<K, V>>> {
+        if self.back.is_none()
+            && let Some(root) = self.root
+        {
+            self.back = Some(self.seek_back(root)?);
+        }
+
+        let mut descend = None;
+        let mut found = None;
+        let Some(stack) = self.back.as_mut() else {
+            return Ok(None);
+        };
+        while let Some((node, pos)) = stack.last_mut() {
+            if *pos == 0 {
+                stack.pop();
+                continue;
+            }
+
+            *pos -= 1;
+            found = Some(node.items[*pos].clone());
+            if !node.is_leaf() {
+                descend = Some(node.children[*pos]);
+            }
+            break;
+        }
+
+        let mut next = descend;
+        while let Some(id) = next {
+            let node = self.read_node(id)?;
+            next = (!node.is_leaf()).then(|| node.children[node.children.len() - 1]);
+            let len = node.items.len();
+            if let Some(stack) = self.back.as_mut() {
+                stack.push((node, len));
+            }
+        }
+        Ok(found)
+    }
+
+    fn below_upper(&self, key: &K) -> bool {
+        match &self.upper {
+            Bound::Included(hi) => key <= hi,
+            Bound::Excluded(hi) => key < hi,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn above_lower(&self, key: &K) -> bool {
+        match &self.lower {
+            Bound::Included(lo) => key >= lo,
+            Bound::Excluded(lo) => key > lo,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<K: KeyCodec, V: Codec> Iterator for Range<'_, K, V> {
+    type Item = Result<Item<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.advance_front() {
+            Ok(Some(item))
+                if self.below_upper(&item.key)
+                    && self.last_back.as_ref().is_none_or(|back| {
+                        cmp_item(&item, &back.key, Some(&back.val), self.values) == Ordering::Less
+                    }) =>
+            {
+                self.last_front = Some(item.clone());
+                Some(Ok(item))
+            }
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<K: KeyCodec, V: Codec> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.advance_back() {
+            Ok(Some(item))
+                if self.above_lower(&item.key)
+                    && self.last_front.as_ref().is_none_or(|front| {
+                        cmp_item(&item, &front.key, Some(&front.val), self.values)
+                            == Ordering::Greater
+                    }) =>
+            {
+                self.last_back = Some(item.clone());
+                Some(Ok(item))
+            }
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! A persistent [`Btree`] that many threads share through `&self`.
+//!
+//! [`SharedBtree`] puts the tree behind one read-write latch. Lookups,
+//! counts and scans take it shared and run in parallel; they only meet at
+//! the buffer pool, which is locked while a single page is fetched. Writes
+//! take it exclusively. The pager's free list, its copy-on-write state and
+//! the write-ahead log each have one writer, so writers run one at a time
+//! and every write is logged and snapshotted exactly as on a `Btree`.
+//!
+//! A scan does not hold the latch from start to end: it reads a batch of
+//! items under the shared latch, lets it go, and latches again for the next
+//! batch starting after the last key it returned. Writers get in between
+//! batches, so a long scan cannot stall them. Each batch is consistent, but
+//! a scan as a whole may see writes made while it runs; to read one fixed
+//! state, scan a [`ReadView`] instead.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::{Bound, RangeBounds},
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    Btree, Item,
+    check::Violation,
+    codec::{Codec, KeyCodec},
+    error::Result,
+    view::ReadView,
+};
+
+/// How many items a scan reads each time it takes the latch.
+const SCAN_BATCH: usize = 128;
+
+/// A `Btree` behind a read-write latch, for readers and writers on many
+/// threads. See the module documentation.
+pub struct SharedBtree<K = i32, V = String> {
+    tree: RwLock<Btree<K, V>>,
+}
+
+impl<K, V> fmt::Debug for SharedBtree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedBtree").finish_non_exhaustive()
+    }
+}
+
+impl<K: KeyCodec, V: Codec> From<Btree<K, V>> for SharedBtree<K, V> {
+    fn from(tree: Btree<K, V>) -> Self {
+        SharedBtree {
+            tree: RwLock::new(tree),
+        }
+    }
+}
+
+impl<K: KeyCodec, V: Codec> SharedBtree<K, V> {
+    /// Latches the tree shared, for reads this type does not wrap.
+    ///
+    /// A write that panicked part way may have left the tree inconsistent;
+    /// `check` tells whether it did.
+    pub fn read(&self) -> RwLockReadGuard<'_, Btree<K, V>> {
+        self.tree.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Latches the tree exclusively, for writes this type does not wrap.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Btree<K, V>> {
+        self.tree.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn search(&self, key: &K) -> Result<V> {
+        self.read().search(key)
+    }
+
+    pub fn count<R: RangeBounds<K>>(&self, bounds: R) -> Result<u64> {
+        self.read().count(bounds)
+    }
+
+    /// Iterates over the items whose keys fall within `bounds`, in key
+    /// order, a batch at a time; see the module documentation.
+    pub fn range<R: RangeBounds<K>>(&self, bounds: R) -> SharedRange<'_, K, V> {
+        SharedRange {
+            tree: self,
+            start: bounds.start_bound().cloned(),
+            end: bounds.end_bound().cloned(),
+            batch: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    pub fn insert(&self, item: Item<K, V>) -> Result<()> {
+        self.write().insert(item)
+    }
+
+    pub fn upsert(&self, item: Item<K, V>) -> Result<Option<V>> {
+        self.write().upsert(item)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<()> {
+        self.write().delete(key)
+    }
+
+    pub fn snapshot(&self) -> Result<usize> {
+        self.write().snapshot()
+    }
+
+    /// Opens a read view of the tree as it is now. The view can be scanned
+    /// on any thread without holding the latch.
+    pub fn read_view(&self) -> Result<ReadView<K, V>> {
+        self.write().read_view()
+    }
+
+    pub fn check(&self) -> Vec<Violation> {
+        self.read().check()
+    }
+}
+
+/// A scan of a `SharedBtree`, returned by `SharedBtree::range`. A read
+/// failure is yielded as an error and ends the scan.
+pub struct SharedRange<'a, K, V> {
+    tree: &'a SharedBtree<K, V>,
+    /// Where the next batch starts: after the last key returned so far.
+    start: Bound<K>,
+    end: Bound<K>,
+    batch: VecDeque<Item<K, V>>,
+    done: bool,
+}
+
+impl<K: KeyCodec, V: Codec> SharedRange<'_, K, V> {
+    /// Reads the next batch under the shared latch. A batch never ends part
+    /// way through a key's items, since the next one starts after that key.
+    fn refill(&mut self) -> Result<()> {
+        let tree = self.tree.read();
+        let mut items = tree.range((self.start.as_ref(), self.end.as_ref()));
+        for item in items.by_ref().take(SCAN_BATCH) {
+            self.batch.push_back(item?);
+        }
+        let Some(last) = self.batch.back() else {
+            self.done = true;
+            return Ok(());
+        };
+        let last = last.key.clone();
+        for item in items {
+            let item = item?;
+            if item.key != last {
+                break;
+            }
+            self.batch.push_back(item);
+        }
+        self.start = Bound::Excluded(last);
+        Ok(())
+    }
+}
+
+impl<K: KeyCodec, V: Codec> Iterator for SharedRange<'_, K, V> {
+    type Item = Result<Item<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty()
+            && !self.done
+            && let Err(e) = self.refill()
+        {
+            self.batch.clear();
+            self.done = true;
+            return Some(Err(e));
+        }
+        self.batch.pop_front().map(Ok)
+    }
+}
@@ -0,0 +1,325 @@
+// src/btree/tests/bplus_tests.rs
+use super::super::{
+    Btree, FORMAT_VERSION, Item,
+    bplus::BPlusTree,
+    paging::{Link, Page},
+};
+use super::{PAGE_SIZE, item};
+use std::{collections::BTreeMap, io::ErrorKind};
+use tempfile::NamedTempFile;
+
+fn keys(tree: &BPlusTree) -> Vec<i32> {
+    tree.range(..).map(|item| item.unwrap().key).collect()
+}
+
+/// Visits 0..n in a scrambled but deterministic order.
+fn scrambled(n: i32) -> impl Iterator<Item = i32> {
+    (0..n).map(move |i| (i * 37) % n)
+}
+
+#[test]
+fn test_insert_search_delete() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let mut model = BTreeMap::new();
+
+    for key in scrambled(500) {
+        tree.insert(item(key)).unwrap();
+        model.insert(key, format!("value-{key}"));
+    }
+    for key in scrambled(500).filter(|key| key % 3 != 0) {
+        tree.delete(&key).unwrap();
+        model.remove(&key);
+    }
+
+    for key in 0..500 {
+        match model.get(&key) {
+            Some(val) => assert_eq!(&tree.search(&key).unwrap(), val),
+            None => assert_eq!(tree.search(&key).unwrap_err().kind(), ErrorKind::NotFound),
+        }
+    }
+    assert_eq!(keys(&tree), model.keys().copied().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_duplicate_and_missing_keys() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    tree.insert(item(1)).unwrap();
+
+    let err = tree.insert(item(1)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(tree.delete(&2).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(keys(&tree), vec![1]);
+}
+
+#[test]
+fn test_delete_everything_and_refill() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+
+    for round in 0..3 {
+        for key in scrambled(200) {
+            tree.insert(item(key)).unwrap();
+        }
+        for key in scrambled(200) {
+            tree.delete(&key).unwrap();
+        }
+        assert!(keys(&tree).is_empty(), "round {round} left keys behind");
+    }
+}
+
+#[test]
+fn test_range_walks_leaves_both_ways() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in scrambled(300) {
+        tree.insert(item(key * 2)).unwrap();
+    }
+
+    let found: Vec<i32> = tree.range(101..=251).map(|i| i.unwrap().key).collect();
+    assert_eq!(found, (102..=250).step_by(2).collect::<Vec<_>>());
+
+    let backwards: Vec<i32> = tree.range(..11).rev().map(|i| i.unwrap().key).collect();
+    assert_eq!(backwards, vec![10, 8, 6, 4, 2, 0]);
+
+    // Both ends consume the same range and stop where they meet.
+    let mut range = tree.range(100..110);
+    let mut met = Vec::new();
+    while let (Some(front), back) = (range.next(), range.next_back()) {
+        met.push(front.unwrap().key);
+        if let Some(back) = back {
+            met.push(back.unwrap().key);
+        }
+    }
+    met.sort();
+    assert_eq!(met, vec![100, 102, 104, 106, 108]);
+
+    assert_eq!(tree.range(1000..).count(), 0);
+}
+
+#[test]
+fn test_range_steps_over_an_empty_leaf() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree =
+        BPlusTree::with_degree(temp_file.path().to_str().unwrap(), PAGE_SIZE, 2).unwrap();
+    for key in 0..100 {
+        tree.insert(item(key)).unwrap();
+    }
+
+    // Empty the leaf holding key 50 in place, keeping its links.
+    let (id, serial, emptied, prev, next) = (1..tree.pager.num_pages)
+        .find_map(|id| match tree.pager.read_page::<i32, String>(id) {
+            Ok(Page::LinkedLeaf {
+                serial,
+                items,
+                prev,
+                next,
+                ..
+            }) if items.iter().any(|item| item.key == 50) => {
+                let keys: Vec<i32> = items.iter().map(|item| item.key).collect();
+                Some((id, serial, keys, prev, next))
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert!(prev != Link::NONE && next != Link::NONE);
+    tree.pager
+        .write_page(&Page::<i32, String>::LinkedLeaf {
+            id,
+            serial,
+            items: Vec::new(),
+            prev,
+            next,
+        })
+        .unwrap();
+
+    let expected: Vec<i32> = (0..100).filter(|key| !emptied.contains(key)).collect();
+    assert_eq!(keys(&tree), expected);
+    let backwards: Vec<i32> = tree.range(..).rev().map(|i| i.unwrap().key).collect();
+    assert_eq!(
+        backwards,
+        expected.iter().rev().copied().collect::<Vec<_>>()
+    );
+
+    // Ranges that start or end inside the empty leaf.
+    let inside = emptied[0]..=emptied[emptied.len() - 1];
+    let found: Vec<i32> = tree.range(inside.clone()).map(|i| i.unwrap().key).collect();
+    assert!(found.is_empty());
+    let found: Vec<i32> = tree.range(50..).map(|i| i.unwrap().key).collect();
+    assert_eq!(
+        found,
+        expected
+            .iter()
+            .copied()
+            .filter(|key| *key >= 50)
+            .collect::<Vec<_>>()
+    );
+    let found: Vec<i32> = tree.range(..=50).rev().map(|i| i.unwrap().key).collect();
+    assert_eq!(
+        found,
+        expected
+            .iter()
+            .rev()
+            .copied()
+            .filter(|key| *key <= 50)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_large_values_spill_from_leaves() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in 0..20 {
+        tree.insert(Item {
+            key,
+            val: key.to_string().repeat(3000),
+        })
+        .unwrap();
+    }
+    for key in (0..20).step_by(2) {
+        tree.delete(&key).unwrap();
+    }
+
+    for key in (1..20).step_by(2) {
+        assert_eq!(tree.search(&key).unwrap(), key.to_string().repeat(3000));
+    }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut tree = BPlusTree::new(path, PAGE_SIZE).unwrap();
+    for key in scrambled(400) {
+        tree.insert(item(key)).unwrap();
+    }
+    tree.snapshot().unwrap();
+
+    let mut loaded = BPlusTree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(keys(&loaded), (0..400).collect::<Vec<_>>());
+
+    // Changing published leaves relocates them; links still find them by serial.
+    for key in (0..400).step_by(2) {
+        loaded.delete(&key).unwrap();
+    }
+    loaded.insert(item(1000)).unwrap();
+    loaded.snapshot().unwrap();
+
+    let reloaded = BPlusTree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let expected: Vec<i32> = (1..400).step_by(2).chain([1000]).collect();
+    assert_eq!(keys(&reloaded), expected);
+    let backwards: Vec<i32> = reloaded.range(..).rev().map(|i| i.unwrap().key).collect();
+    assert_eq!(backwards, expected.into_iter().rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_unpublished_changes_do_not_touch_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut tree = BPlusTree::new(path, PAGE_SIZE).unwrap();
+    for key in 0..200 {
+        tree.insert(item(key)).unwrap();
+    }
+    tree.snapshot().unwrap();
+
+    for key in 50..150 {
+        tree.delete(&key).unwrap();
+    }
+    // Crash after the new pages reached the disk but before the metadata flip.
+    tree.pager.flush().unwrap();
+    tree.pager.file.sync_all().unwrap();
+    drop(tree);
+
+    let reloaded = BPlusTree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(keys(&reloaded), (0..200).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_tree_kinds_reject_each_others_snapshots() {
+    let bplus_file = NamedTempFile::new().unwrap();
+    let bplus_path = bplus_file.path().to_str().unwrap();
+    let mut tree = BPlusTree::new(bplus_path, PAGE_SIZE).unwrap();
+    tree.insert(item(1)).unwrap();
+    tree.snapshot().unwrap();
+
+    let btree_file = NamedTempFile::new().unwrap();
+    let btree_path = btree_file.path().to_str().unwrap();
+    let mut btree = Btree::new(btree_path, PAGE_SIZE).unwrap();
+    btree.insert(item(1)).unwrap();
+    btree.snapshot().unwrap();
+
+    let err = Btree::<i32, String>::load_snapshot(bplus_path, PAGE_SIZE).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = BPlusTree::<i32, String>::load_snapshot(btree_path, PAGE_SIZE).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_insert_after_snapshot_copies_one_path() {
+    let bplus_file = NamedTempFile::new().unwrap();
+    let mut tree = BPlusTree::new(bplus_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let btree_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(btree_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in scrambled(2000) {
+        tree.insert(item(key * 2)).unwrap();
+        btree.insert(item(key * 2)).unwrap();
+    }
+    tree.snapshot().unwrap();
+    btree.snapshot().unwrap();
+
+    tree.insert(item(1001)).unwrap();
+    btree.insert(item(1001)).unwrap();
+    let copied = tree.snapshot().unwrap();
+    assert!(
+        copied <= btree.snapshot().unwrap() + 1,
+        "one insert flushed {copied} pages"
+    );
+    assert_eq!(keys(&tree).len(), 2001);
+}
+
+/// Churns a small-degree tree across snapshots and reloads, checking both
+/// scan directions against a model after every round.
+fn churn_leaves(version: Option<u32>) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut tree = BPlusTree::with_degree(path, PAGE_SIZE, 2).unwrap();
+    if let Some(version) = version {
+        tree.pager.format_version = version;
+    }
+    let mut model = BTreeMap::new();
+
+    for round in 0..8 {
+        for key in scrambled(300).filter(|key| (key + round) % 3 != 0) {
+            if model.remove(&key).is_some() {
+                tree.delete(&key).unwrap();
+            } else {
+                tree.insert(item(key)).unwrap();
+                model.insert(key, ());
+            }
+        }
+        let expected: Vec<i32> = model.keys().copied().collect();
+        assert_eq!(keys(&tree), expected, "round {round}");
+        let backwards: Vec<i32> = tree.range(..).rev().map(|i| i.unwrap().key).collect();
+        assert_eq!(backwards, expected.into_iter().rev().collect::<Vec<_>>());
+
+        tree.snapshot().unwrap();
+        if round % 2 == 1 {
+            tree = BPlusTree::load_snapshot(path, PAGE_SIZE).unwrap();
+        }
+    }
+    assert_eq!(tree.format_version(), version.unwrap_or(FORMAT_VERSION));
+}
+
+#[test]
+fn test_links_survive_relocation() {
+    churn_leaves(None);
+}
+
+#[test]
+fn test_links_by_page_id_in_older_files() {
+    churn_leaves(Some(6));
+}
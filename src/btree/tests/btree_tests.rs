@@ -1,4 +1,4 @@
-use super::super::{Btree, Item, MAX_ITEMS};
+use super::super::{Btree, Item, node::Node};
 use tempfile::NamedTempFile;
 
 fn create_test_btree() -> (Btree, NamedTempFile) {
@@ -7,6 +7,10 @@ fn create_test_btree() -> (Btree, NamedTempFile) {
     (btree, temp_file)
 }
 
+fn root_node(btree: &Btree) -> Node {
+    btree.read_node(btree.root.unwrap()).unwrap()
+}
+
 #[test]
 fn test_new_btree() {
     let (btree, _temp_file) = create_test_btree();
@@ -22,10 +26,10 @@ fn test_insert_single_item() {
         key: 42,
         val: "test".to_string(),
     };
-    btree.insert(item);
+    btree.insert(item).unwrap();
 
     assert!(btree.root.is_some());
-    let root = btree.root.as_ref().unwrap();
+    let root = root_node(&btree);
     assert_eq!(root.num_items, 1);
     assert_eq!(root.items[0].key, 42);
     assert_eq!(root.items[0].val, "test");
@@ -36,21 +40,27 @@ fn test_insert_multiple_items() {
     let (mut btree, _temp_file) = create_test_btree();
 
     // Insert items in random order
-    btree.insert(Item {
-        key: 50,
-        val: "fifty".to_string(),
-    });
-    btree.insert(Item {
-        key: 30,
-        val: "thirty".to_string(),
-    });
-    btree.insert(Item {
-        key: 70,
-        val: "seventy".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 50,
+            val: "fifty".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 30,
+            val: "thirty".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 70,
+            val: "seventy".to_string(),
+        })
+        .unwrap();
 
     // Verify they're stored in sorted order
-    let root = btree.root.as_ref().unwrap();
+    let root = root_node(&btree);
     assert_eq!(root.num_items, 3);
     assert_eq!(root.items[0].key, 30);
     assert_eq!(root.items[1].key, 50);
@@ -61,12 +71,14 @@ fn test_insert_multiple_items() {
 fn test_search_existing_key() {
     let (mut btree, _temp_file) = create_test_btree();
 
-    btree.insert(Item {
-        key: 25,
-        val: "twenty-five".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 25,
+            val: "twenty-five".to_string(),
+        })
+        .unwrap();
 
-    let result = btree.search(25).unwrap();
+    let result = btree.search(&25).unwrap();
     assert_eq!(result, "twenty-five");
 }
 
@@ -74,12 +86,14 @@ fn test_search_existing_key() {
 fn test_search_nonexistent_key() {
     let (mut btree, _temp_file) = create_test_btree();
 
-    btree.insert(Item {
-        key: 10,
-        val: "ten".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 10,
+            val: "ten".to_string(),
+        })
+        .unwrap();
 
-    let result = btree.search(99);
+    let result = btree.search(&99);
     assert!(result.is_err());
 }
 
@@ -87,14 +101,16 @@ fn test_search_nonexistent_key() {
 fn test_tree_splitting() {
     let (mut btree, _temp_file) = create_test_btree();
 
-    for i in 0..(MAX_ITEMS + 1) {
-        btree.insert(Item {
-            key: i,
-            val: format!("value-{i}"),
-        });
+    for i in 0..(btree.max_items() + 1) {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
     }
 
-    let root = btree.root.as_ref().unwrap();
+    let root = root_node(&btree);
     assert_eq!(root.num_items, 1);
     assert_eq!(root.children.len(), 2);
     assert!(!root.is_leaf());
@@ -104,39 +120,137 @@ fn test_tree_splitting() {
 fn test_duplicate_key_handling() {
     let (mut btree, _temp_file) = create_test_btree();
 
-    btree.insert(Item {
-        key: 100,
-        val: "first".to_string(),
-    });
-    btree.insert(Item {
-        key: 100,
-        val: "second".to_string(),
-    });
-
-    let root = btree.root.as_ref().unwrap();
+    btree
+        .insert(Item {
+            key: 100,
+            val: "first".to_string(),
+        })
+        .unwrap();
+    let err = btree
+        .insert(Item {
+            key: 100,
+            val: "second".to_string(),
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+    let root = root_node(&btree);
     assert_eq!(root.num_items, 1);
     assert_eq!(root.items[0].val, "first"); // First value should be preserved
 }
 
 #[test]
-fn test_delete_leaf_node() {
+fn test_duplicate_of_split_middle_is_rejected() {
     let (mut btree, _temp_file) = create_test_btree();
+    for key in 1..=7 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("v{key}"),
+            })
+            .unwrap();
+    }
+
+    // Key 6 is the middle of the full leaf [4, 5, 6, 7] and moves up when
+    // the insert splits it.
+    let err = btree
+        .insert(Item {
+            key: 6,
+            val: "again".to_string(),
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+    let keys: Vec<i32> = btree.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, (1..=7).collect::<Vec<_>>());
+    assert_eq!(btree.search(&6).unwrap(), "v6");
+}
+
+#[test]
+fn test_upsert_replaces_or_inserts() {
+    let (mut btree, _temp_file) = create_test_btree();
+    for key in 0..20 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("v{key}"),
+            })
+            .unwrap();
+    }
+
+    let old = btree
+        .upsert(Item {
+            key: 7,
+            val: "seven".to_string(),
+        })
+        .unwrap();
+    assert_eq!(old.as_deref(), Some("v7"));
+    assert_eq!(btree.search(&7).unwrap(), "seven");
+
+    let old = btree
+        .upsert(Item {
+            key: 50,
+            val: "fifty".to_string(),
+        })
+        .unwrap();
+    assert_eq!(old, None);
+    assert_eq!(btree.search(&50).unwrap(), "fifty");
+}
+
+#[test]
+fn test_update_requires_existing_key() {
+    let (mut btree, _temp_file) = create_test_btree();
+    for key in 0..20 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("v{key}"),
+            })
+            .unwrap();
+    }
 
-    btree.insert(Item {
-        key: 10,
-        val: "ten".to_string(),
-    });
-    btree.insert(Item {
-        key: 20,
-        val: "twenty".to_string(),
-    });
+    let old = btree
+        .update(Item {
+            key: 13,
+            val: "thirteen".to_string(),
+        })
+        .unwrap();
+    assert_eq!(old, "v13");
+    assert_eq!(btree.search(&13).unwrap(), "thirteen");
+
+    let err = btree
+        .update(Item {
+            key: 99,
+            val: "missing".to_string(),
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(btree.search(&99).is_err());
+}
 
-    assert!(btree.delete(10).is_ok());
+#[test]
+fn test_delete_leaf_node() {
+    let (mut btree, _temp_file) = create_test_btree();
 
-    let root = btree.root.as_ref().unwrap();
+    btree
+        .insert(Item {
+            key: 10,
+            val: "ten".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 20,
+            val: "twenty".to_string(),
+        })
+        .unwrap();
+
+    assert!(btree.delete(&10).is_ok());
+
+    let root = root_node(&btree);
     assert_eq!(root.num_items, 1);
     assert_eq!(root.items[0].key, 20);
-    assert!(btree.search(10).is_err());
+    assert!(btree.search(&10).is_err());
 }
 
 #[test]
@@ -144,18 +258,20 @@ fn test_delete_internal_node() {
     let (mut btree, _temp_file) = create_test_btree();
 
     for i in 0..7 {
-        btree.insert(Item {
-            key: i,
-            val: format!("value-{i}"),
-        });
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
     }
 
-    assert!(btree.delete(3).is_ok());
+    assert!(btree.delete(&3).is_ok());
 
-    assert!(btree.search(3).is_err());
+    assert!(btree.search(&3).is_err());
 
-    assert_eq!(btree.search(2).unwrap(), "value-2");
-    assert_eq!(btree.search(4).unwrap(), "value-4");
+    assert_eq!(btree.search(&2).unwrap(), "value-2");
+    assert_eq!(btree.search(&4).unwrap(), "value-4");
 }
 
 #[test]
@@ -163,20 +279,22 @@ fn test_delete_with_merge() {
     let (mut btree, _temp_file) = create_test_btree();
 
     for i in 0..7 {
-        btree.insert(Item {
-            key: i * 10,
-            val: format!("value-{}", i * 10),
-        });
+        btree
+            .insert(Item {
+                key: i * 10,
+                val: format!("value-{}", i * 10),
+            })
+            .unwrap();
     }
 
-    assert!(btree.delete(0).is_ok());
-    assert!(btree.delete(10).is_ok());
-    assert!(btree.delete(20).is_ok());
+    assert!(btree.delete(&0).is_ok());
+    assert!(btree.delete(&10).is_ok());
+    assert!(btree.delete(&20).is_ok());
 
-    assert!(btree.search(0).is_err());
-    assert!(btree.search(10).is_err());
-    assert!(btree.search(20).is_err());
-    assert_eq!(btree.search(30).unwrap(), "value-30");
+    assert!(btree.search(&0).is_err());
+    assert!(btree.search(&10).is_err());
+    assert!(btree.search(&20).is_err());
+    assert_eq!(btree.search(&30).unwrap(), "value-30");
 }
 
 #[test]
@@ -184,16 +302,18 @@ fn test_internal_node_values() {
     let (mut btree, _temp_file) = create_test_btree();
 
     for i in 0..7 {
-        btree.insert(Item {
-            key: i,
-            val: format!("value-{i}"),
-        });
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
     }
 
-    let root = btree.root.as_ref().unwrap();
+    let root = root_node(&btree);
     assert!(!root.is_leaf());
     assert_eq!(root.items[0].val, "value-2");
-    assert_eq!(btree.search(2).unwrap(), "value-2");
+    assert_eq!(btree.search(&2).unwrap(), "value-2");
 }
 
 #[test]
@@ -201,14 +321,16 @@ fn test_split_preserves_values() {
     let (mut btree, _temp_file) = create_test_btree();
 
     for i in 0..10 {
-        btree.insert(Item {
-            key: i,
-            val: format!("value-{i}"),
-        });
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
     }
 
     for i in 0..10 {
-        assert_eq!(btree.search(i).unwrap(), format!("value-{i}"));
+        assert_eq!(btree.search(&i).unwrap(), format!("value-{i}"));
     }
 }
 
@@ -217,15 +339,151 @@ fn test_merge_preserves_values() {
     let (mut btree, _temp_file) = create_test_btree();
 
     for i in 0..7 {
-        btree.insert(Item {
-            key: i * 10,
-            val: format!("value-{}", i * 10),
-        });
+        btree
+            .insert(Item {
+                key: i * 10,
+                val: format!("value-{}", i * 10),
+            })
+            .unwrap();
     }
 
-    assert!(btree.delete(0).is_ok());
-    assert!(btree.delete(10).is_ok());
+    assert!(btree.delete(&0).is_ok());
+    assert!(btree.delete(&10).is_ok());
 
-    assert_eq!(btree.search(20).unwrap(), "value-20");
-    assert_eq!(btree.search(30).unwrap(), "value-30");
+    assert_eq!(btree.search(&20).unwrap(), "value-20");
+    assert_eq!(btree.search(&30).unwrap(), "value-30");
+}
+
+#[test]
+fn test_custom_degree() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::with_degree(temp_file.path().to_str().unwrap(), 4096, 8).unwrap();
+
+    for i in 0..16 {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+    let root = root_node(&btree);
+    assert!(root.is_leaf());
+    assert_eq!(root.num_items, 16);
+
+    btree
+        .insert(Item {
+            key: 16,
+            val: "value-16".to_string(),
+        })
+        .unwrap();
+    let root = root_node(&btree);
+    assert!(!root.is_leaf());
+    assert_eq!(root.num_items, 1);
+}
+
+#[test]
+fn test_invalid_degree() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let result = Btree::<i32, String>::with_degree(temp_file.path().to_str().unwrap(), 4096, 1);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_range_scan() {
+    let (mut btree, _temp_file) = create_test_btree();
+
+    for i in [15, 3, 9, 0, 12, 6, 18, 21, 24, 27] {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+
+    let keys: Vec<i32> = btree.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, vec![0, 3, 6, 9, 12, 15, 18, 21, 24, 27]);
+
+    let keys: Vec<i32> = btree.range(6..=18).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, vec![6, 9, 12, 15, 18]);
+
+    let keys: Vec<i32> = btree.range(5..18).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, vec![6, 9, 12, 15]);
+
+    let keys: Vec<i32> = btree
+        .range(20..)
+        .rev()
+        .map(|item| item.unwrap().key)
+        .collect();
+    assert_eq!(keys, vec![27, 24, 21]);
+
+    let vals: Vec<String> = btree.range(..=3).map(|item| item.unwrap().val).collect();
+    assert_eq!(vals, vec!["value-0", "value-3"]);
+
+    assert_eq!(btree.range(100..).count(), 0);
+    assert_eq!(btree.range(4..6).count(), 0);
+}
+
+#[test]
+fn test_range_double_ended() {
+    let (mut btree, _temp_file) = create_test_btree();
+
+    for i in 0..50 {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+
+    let mut range = btree.range(10..20);
+    let mut keys = Vec::new();
+    loop {
+        match (range.next(), range.next_back()) {
+            (Some(front), Some(back)) => {
+                keys.push(front.unwrap().key);
+                keys.push(back.unwrap().key);
+            }
+            (Some(front), None) => keys.push(front.unwrap().key),
+            (None, _) => break,
+        }
+    }
+    keys.sort();
+    assert_eq!(keys, (10..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_range_empty_tree() {
+    let (btree, _temp_file) = create_test_btree();
+    assert_eq!(btree.range(..).count(), 0);
+}
+
+#[test]
+fn test_delete_many_keeps_remaining() {
+    let (mut btree, _temp_file) = create_test_btree();
+
+    for i in 0..200 {
+        btree
+            .insert(Item {
+                key: (i * 37) % 200,
+                val: format!("value-{}", (i * 37) % 200),
+            })
+            .unwrap();
+    }
+
+    for i in (0..200).filter(|i| i % 3 != 0) {
+        assert!(btree.delete(&i).is_ok(), "failed to delete {i}");
+    }
+
+    for i in 0..200 {
+        if i % 3 == 0 {
+            assert_eq!(btree.search(&i).unwrap(), format!("value-{i}"));
+        } else {
+            assert!(btree.search(&i).is_err());
+        }
+    }
+    let keys: Vec<i32> = btree.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, (0..200).filter(|i| i % 3 == 0).collect::<Vec<_>>());
 }
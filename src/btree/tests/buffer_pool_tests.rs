@@ -0,0 +1,122 @@
+use super::super::{
+    Btree, Item,
+    buffer_pool::{BufferPool, Clock, EvictionPolicy, Lru},
+};
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+fn fill(btree: &mut Btree, n: i32) {
+    for i in 0..n {
+        btree
+            .insert(Item {
+                key: (i * 7919) % n,
+                val: format!("value-{}", (i * 7919) % n),
+            })
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_small_pool_lru() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+    btree
+        .configure_buffer_pool(4, Box::new(Lru::default()))
+        .unwrap();
+
+    fill(&mut btree, 300);
+    for i in 0..300 {
+        assert_eq!(btree.search(&i).unwrap(), format!("value-{i}"));
+    }
+
+    let stats = btree.buffer_pool_stats();
+    assert!(stats.evictions > 0);
+    assert!(stats.writebacks > 0);
+    assert!(stats.hits > 0);
+    assert!(stats.misses > 0);
+}
+
+#[test]
+fn test_small_pool_clock() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree
+        .configure_buffer_pool(3, Box::new(Clock::default()))
+        .unwrap();
+
+    fill(&mut btree, 300);
+    for i in (0..300).step_by(2) {
+        btree.delete(&i).unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    for i in 0..300 {
+        assert_eq!(loaded_btree.search(&i).is_ok(), i % 2 == 1);
+    }
+}
+
+#[test]
+fn test_pool_hits_after_warmup() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+    fill(&mut btree, 50);
+
+    let before = btree.buffer_pool_stats();
+    btree.search(&10).unwrap();
+    let after = btree.buffer_pool_stats();
+    assert_eq!(after.misses, before.misses);
+    assert!(after.hits > before.hits);
+}
+
+#[test]
+fn test_zero_capacity_rejected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::<i32, String>::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+    assert!(
+        btree
+            .configure_buffer_pool(0, Box::new(Lru::default()))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_pinned_frames_are_not_evicted() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(&[0u8; 64 * 3]).unwrap();
+    let file = temp_file.as_file();
+
+    let mut pool = BufferPool::new(1, Box::new(Lru::default())).unwrap();
+    let frame = pool.fetch(file, 64, 1, &|_| Ok(())).unwrap();
+    assert!(pool.fetch(file, 64, 2, &|_| Ok(())).is_err());
+
+    pool.unpin(frame);
+    assert!(pool.fetch(file, 64, 2, &|_| Ok(())).is_ok());
+}
+
+#[test]
+fn test_lru_victim_order() {
+    let mut lru = Lru::default();
+    for frame in [0, 1, 2] {
+        lru.record_access(frame);
+    }
+    lru.record_access(0);
+
+    assert_eq!(lru.victim(&|_| false), Some(1));
+    assert_eq!(lru.victim(&|frame| frame == 2), Some(0));
+}
+
+#[test]
+fn test_clock_second_chance() {
+    let mut clock = Clock::default();
+    for frame in [0, 1, 2] {
+        clock.record_access(frame);
+    }
+
+    assert_eq!(clock.victim(&|_| false), Some(0));
+    clock.record_access(0);
+    clock.record_access(1);
+    assert_eq!(clock.victim(&|_| false), Some(2));
+    assert_eq!(clock.victim(&|frame| frame == 1), Some(0));
+}
@@ -0,0 +1,134 @@
+// src/btree/tests/bulk_load_tests.rs
+use super::super::{Btree, Item, paging::PageID};
+use std::io;
+use tempfile::NamedTempFile;
+
+fn items(keys: impl Iterator<Item = i32>) -> Vec<Item> {
+    keys.map(|key| Item {
+        key,
+        val: format!("value-{key}"),
+    })
+    .collect()
+}
+
+/// Returns the depth of every leaf below `id` after checking node sizes.
+fn leaf_depths(btree: &Btree, id: PageID, depth: usize, depths: &mut Vec<usize>) {
+    let node = btree.read_node(id).unwrap();
+    if Some(id) != btree.root {
+        assert!(node.num_items >= btree.min_items(), "page {id} underfull");
+    }
+    assert!(node.num_items <= btree.max_items(), "page {id} overfull");
+
+    if node.is_leaf() {
+        depths.push(depth);
+    } else {
+        assert_eq!(node.children.len(), node.items.len() + 1);
+        for &child in &node.children {
+            leaf_depths(btree, child, depth + 1, depths);
+        }
+    }
+}
+
+fn assert_well_formed(btree: &Btree) {
+    let mut depths = Vec::new();
+    leaf_depths(btree, btree.root.unwrap(), 0, &mut depths);
+    assert!(
+        depths.iter().all(|&d| d == depths[0]),
+        "leaves at {depths:?}"
+    );
+}
+
+#[test]
+fn test_bulk_load_builds_searchable_tree() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    assert_eq!(btree.bulk_load(items(0..10_000), 1.0).unwrap(), 10_000);
+    assert_well_formed(&btree);
+
+    for key in (0..10_000).step_by(97) {
+        assert_eq!(btree.search(&key).unwrap(), format!("value-{key}"));
+    }
+    let keys: Vec<i32> = btree.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(keys, (0..10_000).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_bulk_load_respects_fill_factor() {
+    for n in [1, 4, 5, 9, 10, 11, 50, 333, 1000] {
+        for fill in [0.1, 0.5, 0.75, 1.0] {
+            let temp_file = NamedTempFile::new().unwrap();
+            let mut btree =
+                Btree::with_degree(temp_file.path().to_str().unwrap(), 4096, 3).unwrap();
+            btree.bulk_load(items(0..n), fill).unwrap();
+            assert_well_formed(&btree);
+            assert_eq!(btree.range(..).count(), n as usize, "n={n} fill={fill}");
+        }
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut packed = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+    packed.bulk_load(items(0..1000), 1.0).unwrap();
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut loose = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+    loose.bulk_load(items(0..1000), 0.5).unwrap();
+    assert!(loose.pager.num_pages > packed.pager.num_pages);
+}
+
+#[test]
+fn test_bulk_load_rejects_unsorted_input() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    let err = btree
+        .bulk_load(items([1, 2, 5, 4].into_iter()), 1.0)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(btree.bulk_load(items([1, 1].into_iter()), 1.0).is_err());
+    assert!(btree.root.is_none());
+    assert_eq!(btree.pager.num_pages, 0);
+}
+
+#[test]
+fn test_bulk_load_rejects_bad_arguments() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    assert!(btree.bulk_load(items(0..10), 0.0).is_err());
+    assert!(btree.bulk_load(items(0..10), 1.5).is_err());
+
+    btree
+        .insert(Item {
+            key: 1,
+            val: "one".to_string(),
+        })
+        .unwrap();
+    assert!(btree.bulk_load(items(2..10), 1.0).is_err());
+}
+
+#[test]
+fn test_bulk_loaded_tree_accepts_updates_and_snapshots() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree
+        .bulk_load(items((0..2000).map(|k| k * 2)), 0.8)
+        .unwrap();
+    for key in 0..100 {
+        btree
+            .insert(Item {
+                key: key * 2 + 1,
+                val: format!("value-{}", key * 2 + 1),
+            })
+            .unwrap();
+        btree.delete(&(key * 4)).unwrap();
+    }
+    assert_well_formed(&btree);
+    btree.snapshot().unwrap();
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded.search(&3).unwrap(), "value-3");
+    assert!(loaded.search(&4).is_err());
+    assert_eq!(loaded.search(&3998).unwrap(), "value-3998");
+}
@@ -0,0 +1,196 @@
+// src/btree/tests/check_tests.rs
+use super::super::{
+    Btree, Item, bplus::BPlusTree, check::Violation, error::IndexError, node::Node,
+};
+use super::{PAGE_SIZE, item};
+use tempfile::NamedTempFile;
+
+/// Degree 2 tree holding 1..=7: root [3] over leaves [1, 2] and [4, 5, 6, 7].
+fn small_tree(path: &str) -> Btree {
+    let mut btree = Btree::with_degree(path, PAGE_SIZE, 2).unwrap();
+    for key in 1..=7 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree
+}
+
+fn reports(violations: &[Violation], page: u32, needle: &str) -> bool {
+    violations
+        .iter()
+        .any(|v| v.page == page && v.message.contains(needle))
+}
+
+#[test]
+fn test_healthy_trees_have_no_violations() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::with_degree(path, PAGE_SIZE, 3).unwrap();
+    assert!(btree.check().is_empty());
+
+    for i in 0..500 {
+        btree.insert(item((i * 7919) % 1000)).unwrap();
+        if i % 50 == 0 {
+            assert_eq!(btree.check(), vec![]);
+        }
+    }
+    for i in 0..400 {
+        btree.delete(&((i * 7919) % 1000)).unwrap();
+        if i % 50 == 0 {
+            btree.snapshot().unwrap();
+            assert_eq!(btree.check(), vec![]);
+        }
+    }
+    assert_eq!(btree.check(), vec![]);
+    btree.snapshot().unwrap();
+    assert_eq!(
+        Btree::<i32, String>::check_file(path, PAGE_SIZE).unwrap(),
+        vec![]
+    );
+}
+
+#[test]
+fn test_multimap_duplicates_pass_online_and_offline() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new_multimap(path, PAGE_SIZE).unwrap();
+    for i in 0..300 {
+        btree.insert(Item { key: i % 5, val: i }).unwrap();
+    }
+    assert_eq!(btree.check(), vec![]);
+
+    btree.snapshot().unwrap();
+    assert_eq!(
+        Btree::<i32, i32>::check_file(path, PAGE_SIZE).unwrap(),
+        vec![]
+    );
+}
+
+#[test]
+fn test_check_file_orders_multimap_values() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new_multimap(path, PAGE_SIZE).unwrap();
+    for val in 0..3 {
+        btree.insert(Item { key: 1, val }).unwrap();
+    }
+
+    let root = btree.root.unwrap();
+    let mut node = btree.read_node(root).unwrap();
+    node.items.swap(0, 2);
+    btree.write_node(&node).unwrap();
+    btree.snapshot().unwrap();
+
+    let violations = Btree::<i32, i32>::check_file(path, PAGE_SIZE).unwrap();
+    assert!(reports(&violations, root, "key 1 is not ordered before 1"));
+}
+
+#[test]
+fn test_check_file_refuses_bplus_trees() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut bplus = BPlusTree::new(path, PAGE_SIZE).unwrap();
+    for key in 0..100 {
+        bplus.insert(item(key)).unwrap();
+    }
+    bplus.snapshot().unwrap();
+
+    assert!(matches!(
+        Btree::<i32, String>::check_file(path, PAGE_SIZE),
+        Err(IndexError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn test_reports_misordered_items() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = small_tree(temp_file.path().to_str().unwrap());
+    let root = btree.read_node(btree.root.unwrap()).unwrap();
+    let (left, right) = (root.children[0], root.children[1]);
+
+    let mut node = btree.read_node(left).unwrap();
+    node.items.swap(0, 1);
+    btree.write_node(&node).unwrap();
+
+    let mut node = btree.read_node(right).unwrap();
+    node.items[0] = item(0);
+    btree.write_node(&node).unwrap();
+
+    let violations = btree.check();
+    assert!(reports(&violations, left, "key 2 is not ordered before 1"));
+    assert!(reports(
+        &violations,
+        right,
+        "key 0 is not above the separator 3"
+    ));
+}
+
+#[test]
+fn test_reports_occupancy_and_leaf_depth() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = small_tree(temp_file.path().to_str().unwrap());
+    let root = btree.read_node(btree.root.unwrap()).unwrap();
+    let (left, right) = (root.children[0], root.children[1]);
+
+    let mut node = btree.read_node(left).unwrap();
+    node.items.clear();
+    node.num_items = 0;
+    btree.write_node(&node).unwrap();
+
+    // Push the right leaf's items one level down.
+    let (low, high) = (
+        btree.pager.allocate_page().unwrap(),
+        btree.pager.allocate_page().unwrap(),
+    );
+    let mut leaf = Node::new(low);
+    leaf.items = vec![item(4)];
+    leaf.num_items = 1;
+    btree.write_node(&leaf).unwrap();
+    let mut leaf = Node::new(high);
+    leaf.items = vec![item(6), item(7)];
+    leaf.num_items = 2;
+    btree.write_node(&leaf).unwrap();
+    let mut node = btree.read_node(right).unwrap();
+    node.items = vec![item(5)];
+    node.children = vec![low, high];
+    node.counts = vec![1, 2];
+    node.num_items = 1;
+    node.num_children = 2;
+    btree.write_node(&node).unwrap();
+
+    let violations = btree.check();
+    assert!(reports(&violations, left, "fewer than the minimum 1"));
+    assert!(reports(
+        &violations,
+        low,
+        "leaf at depth 2, other leaves are at depth 1"
+    ));
+    assert!(reports(&violations, high, "leaf at depth 2"));
+}
+
+#[test]
+fn test_reports_bad_child_pointers() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = small_tree(temp_file.path().to_str().unwrap());
+    let root_id = btree.root.unwrap();
+    let mut root = btree.read_node(root_id).unwrap();
+    let left = root.children[0];
+
+    root.children[1] = 999;
+    btree.write_node(&root).unwrap();
+    assert!(reports(&btree.check(), root_id, "child 999 is outside"));
+
+    root.children[1] = left;
+    btree.write_node(&root).unwrap();
+    assert!(reports(&btree.check(), left, "referenced more than once"));
+}
+
+#[test]
+fn test_reports_children_referencing_freed_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = small_tree(temp_file.path().to_str().unwrap());
+    let root = btree.read_node(btree.root.unwrap()).unwrap();
+    let left = root.children[0];
+
+    btree.pager.free_page::<i32, String>(left).unwrap();
+    assert!(reports(&btree.check(), left, "marked free"));
+}
@@ -0,0 +1,192 @@
+// src/btree/tests/compression_tests.rs
+use super::super::{
+    Btree, FORMAT_VERSION, Item,
+    bplus::BPlusTree,
+    codec::{KeyCodec, get_varint, put_varint},
+    error::IndexError,
+    paging::Page,
+};
+use super::PAGE_SIZE;
+use tempfile::NamedTempFile;
+
+/// Long keys that differ only in their last few bytes.
+fn url(i: i32) -> String {
+    format!("https://example.com/catalogue/products/by-id/{i:06}")
+}
+
+fn insert_urls(btree: &mut Btree<String, String>, n: i32) -> Result<(), IndexError> {
+    for i in 0..n {
+        btree.insert(Item {
+            key: url((i * 37) % n),
+            val: i.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shared_prefixes_raise_fan_out() {
+    // 80 of these keys take about 5KB uncompressed, more than a page holds.
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree: Btree<String, String> = Btree::with_degree(path, PAGE_SIZE, 40).unwrap();
+    insert_urls(&mut btree, 500).unwrap();
+    assert!(btree.check().is_empty());
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    let loaded = Btree::<String, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    for i in 0..500 {
+        assert_eq!(loaded.search(&url((i * 37) % 500)).unwrap(), i.to_string());
+    }
+    assert_eq!(loaded.range(..).count(), 500);
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut legacy: Btree<String, String> =
+        Btree::with_degree(temp_file.path().to_str().unwrap(), PAGE_SIZE, 40).unwrap();
+    legacy.pager.format_version = 5;
+    assert!(matches!(
+        insert_urls(&mut legacy, 500),
+        Err(IndexError::PageFull { .. })
+    ));
+}
+
+/// Fills one leaf page of a file in `version` with `key(0)`, `key(1)`, ...
+/// and empty values, checks the full page reads back and returns how many
+/// items it holds.
+fn leaf_capacity(version: u32, key: impl Fn(i32) -> i32) -> usize {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree: Btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    btree.pager.format_version = version;
+    let id = btree.pager.allocate_page().unwrap();
+
+    let mut items = Vec::new();
+    loop {
+        let mut more = items.clone();
+        more.push(Item {
+            key: key(items.len() as i32),
+            val: String::new(),
+        });
+        match btree.pager.write_page(&Page::Leaf {
+            id,
+            items: more.clone(),
+        }) {
+            Ok(()) => items = more,
+            Err(e) => {
+                assert!(matches!(IndexError::from(e), IndexError::PageFull { .. }));
+                break;
+            }
+        }
+    }
+
+    btree
+        .pager
+        .write_page(&Page::Leaf {
+            id,
+            items: items.clone(),
+        })
+        .unwrap();
+    match btree.pager.read_page::<i32, String>(id).unwrap() {
+        Page::Leaf { items: read, .. } => {
+            let keys = |items: &[Item]| items.iter().map(|item| item.key).collect::<Vec<_>>();
+            assert_eq!(keys(&read), keys(&items));
+        }
+        page => panic!("expected a leaf, found {page:?}"),
+    }
+    items.len()
+}
+
+#[test]
+fn test_compression_never_costs_capacity() {
+    // Far apart keys share at most a byte, which the prefix header outweighs.
+    let scattered = |i: i32| i * 1_000_003;
+    let dense = |i: i32| i;
+
+    assert!(leaf_capacity(6, scattered) < leaf_capacity(5, scattered));
+    assert!(leaf_capacity(FORMAT_VERSION, scattered) >= leaf_capacity(5, scattered));
+    assert!(leaf_capacity(FORMAT_VERSION, dense) > leaf_capacity(5, dense));
+}
+
+#[test]
+fn test_integer_keys_round_trip() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    let keys = [i32::MIN, -70_000, -1, 0, 1, 255, 256, 65_536, i32::MAX];
+    for key in keys {
+        btree
+            .insert(Item {
+                key,
+                val: key.to_string(),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let found: Vec<i32> = loaded.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(found, keys);
+}
+
+#[test]
+fn test_bplus_separators_are_truncated() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree: BPlusTree<String, String> =
+        BPlusTree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let key = |i: i32| format!("{i:03}-{}", "x".repeat(40));
+    for i in 0..300 {
+        tree.insert(Item {
+            key: key((i * 37) % 300),
+            val: i.to_string(),
+        })
+        .unwrap();
+    }
+    for i in (0..300).step_by(4) {
+        tree.delete(&key(i)).unwrap();
+    }
+
+    let mut stack = vec![tree.root.unwrap()];
+    let mut separators = 0;
+    while let Some(id) = stack.pop() {
+        if let Page::Separators { keys, children, .. } =
+            tree.pager.read_page::<String, String>(id).unwrap()
+        {
+            assert!(keys.iter().all(|k| k.len() <= 3), "{keys:?}");
+            separators += keys.len();
+            stack.extend(children);
+        }
+    }
+    assert!(separators > 0);
+
+    for i in 0..300 {
+        assert_eq!(tree.search(&key(i)).is_ok(), i % 4 != 0, "{i}");
+    }
+}
+
+#[test]
+fn test_shortest_separators() {
+    let s = |left: &str, right: &str| String::separator(&left.to_string(), &right.to_string());
+    assert_eq!(s("apple", "banana"), "b");
+    assert_eq!(s("app", "apple"), "appl");
+    assert_eq!(s("apple", "apply"), "apply");
+    assert_eq!(s("", "a"), "a");
+    // Never cuts a character in half.
+    assert_eq!(s("caf", "café au lait"), "café");
+
+    assert_eq!(
+        Vec::<u8>::separator(&vec![1, 2, 3], &vec![1, 4, 0]),
+        vec![1, 4]
+    );
+    assert_eq!(i32::separator(&3, &10), 10);
+}
+
+#[test]
+fn test_varints() {
+    for n in [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX as usize] {
+        let mut buf = Vec::new();
+        put_varint(n, &mut buf);
+        assert_eq!(get_varint(&buf).unwrap(), (n, buf.len()));
+    }
+    assert!(get_varint(&[0x80, 0x80]).is_err());
+}
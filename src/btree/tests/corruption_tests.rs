@@ -0,0 +1,149 @@
+// src/btree/tests/corruption_tests.rs
+use super::super::{Btree, Item, error::IndexError, paging::PageID};
+use super::PAGE_SIZE;
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+use tempfile::NamedTempFile;
+
+fn build_snapshot(path: &str, count: i32) -> Btree {
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    for key in 0..count {
+        btree
+            .insert(Item {
+                key,
+                val: format!("value-{key}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+    btree
+}
+
+fn flip_byte(path: &str, offset: u64) {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    let mut byte = [0u8; 1];
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    byte[0] ^= 0xff;
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(&byte).unwrap();
+}
+
+fn corrupt_page(err: &IndexError) -> PageID {
+    match err {
+        IndexError::Corrupt { page, .. } => *page,
+        _ => panic!("expected a corrupt page error, got {err}"),
+    }
+}
+
+#[test]
+fn test_corrupt_root_page_is_reported() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let root = build_snapshot(path, 10).root.unwrap();
+
+    flip_byte(path, root as u64 * PAGE_SIZE as u64 + 20);
+
+    let err = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(corrupt_page(&err), root);
+}
+
+#[test]
+fn test_corrupt_leaf_is_reported_on_access() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let btree = build_snapshot(path, 50);
+    let root = btree.read_node(btree.root.unwrap()).unwrap();
+    let leaf = root.children[0];
+    let key = btree.read_node(leaf).unwrap().items[0].key;
+
+    flip_byte(path, leaf as u64 * PAGE_SIZE as u64 + PAGE_SIZE as u64 - 1);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let err = loaded.search(&key).unwrap_err();
+    assert_eq!(corrupt_page(&err), leaf);
+    assert!(err.to_string().contains("checksum mismatch"));
+}
+
+#[test]
+fn test_misplaced_page_is_reported() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let btree = build_snapshot(path, 50);
+    let root = btree.read_node(btree.root.unwrap()).unwrap();
+    let (first, second) = (root.children[0], root.children[1]);
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    let mut page = vec![0u8; PAGE_SIZE];
+    file.seek(SeekFrom::Start(first as u64 * PAGE_SIZE as u64))
+        .unwrap();
+    file.read_exact(&mut page).unwrap();
+    file.seek(SeekFrom::Start(second as u64 * PAGE_SIZE as u64))
+        .unwrap();
+    file.write_all(&page).unwrap();
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let err = loaded.range(..).find_map(Result::err).unwrap();
+    assert_eq!(corrupt_page(&err), second);
+    assert!(
+        err.to_string()
+            .contains(&format!("belongs to page {first}"))
+    );
+}
+
+#[test]
+fn test_corrupt_metadata_is_reported() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = build_snapshot(path, 10);
+    btree
+        .insert(Item {
+            key: 10,
+            val: "value-10".to_string(),
+        })
+        .unwrap();
+    btree.snapshot().unwrap();
+
+    // Both metadata slots hold a snapshot now; damage each of them.
+    flip_byte(path, 8);
+    flip_byte(path, 512 + 8);
+
+    let err = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap_err();
+    assert_eq!(corrupt_page(&err), 0);
+    assert!(!Btree::<i32, String>::is_valid_snapshot(path, PAGE_SIZE));
+}
+
+#[test]
+fn test_version_one_snapshot_still_loads() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree.pager.format_version = 1;
+    for key in 0..50 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("value-{key}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(loaded.pager.format_version, 1);
+    for key in 0..50 {
+        assert_eq!(loaded.search(&key).unwrap(), format!("value-{key}"));
+    }
+}
@@ -0,0 +1,268 @@
+// src/btree/tests/dump_tests.rs
+use super::super::{Btree, Item, dump::DumpFormat, error::IndexError};
+use super::PAGE_SIZE;
+use tempfile::NamedTempFile;
+
+fn items(btree: &Btree) -> Vec<(i32, String)> {
+    btree
+        .range(..)
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key, item.val)
+        })
+        .collect()
+}
+
+/// A tree whose values need quoting or escaping in both formats.
+fn awkward_btree(path: &str) -> Btree {
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    let values = [
+        "plain",
+        "with, comma",
+        "with \"quotes\"",
+        "two\nlines",
+        "windows\r\nline break",
+        "lone\rreturn",
+        "tab\tand\\backslash",
+        "café ☕ 𝄞",
+        "",
+    ];
+    for key in 0..200 {
+        btree
+            .insert(Item {
+                key: (key * 37) % 200 - 100,
+                val: format!("{}-{key}", values[key as usize % values.len()]),
+            })
+            .unwrap();
+    }
+    btree
+}
+
+#[test]
+fn test_round_trip_in_both_formats() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let btree = awkward_btree(temp_file.path().to_str().unwrap());
+
+    for format in [DumpFormat::JsonLines, DumpFormat::Csv] {
+        let mut dump = Vec::new();
+        assert_eq!(btree.export(&mut dump, format).unwrap(), 200);
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut copy = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+        assert_eq!(copy.import(dump.as_slice(), format).unwrap(), 200);
+        assert!(copy.check().is_empty());
+        assert_eq!(items(&copy), items(&btree), "{format:?}");
+    }
+}
+
+#[test]
+fn test_dump_is_in_key_order() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in [3, -1, 2] {
+        btree
+            .insert(Item {
+                key,
+                val: format!("v{key}"),
+            })
+            .unwrap();
+    }
+
+    let mut json = Vec::new();
+    btree.export(&mut json, DumpFormat::JsonLines).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{\"key\":-1,\"value\":\"v-1\"}\n\
+         {\"key\":2,\"value\":\"v2\"}\n\
+         {\"key\":3,\"value\":\"v3\"}\n"
+    );
+
+    let mut csv = Vec::new();
+    btree.export(&mut csv, DumpFormat::Csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "key,value\n-1,v-1\n2,v2\n3,v3\n"
+    );
+}
+
+#[test]
+fn test_import_migrates_to_another_page_size() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let btree = awkward_btree(temp_file.path().to_str().unwrap());
+    let mut dump = Vec::new();
+    btree.export(&mut dump, DumpFormat::JsonLines).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut small: Btree = Btree::new(path, 1024).unwrap();
+    small
+        .import(dump.as_slice(), DumpFormat::JsonLines)
+        .unwrap();
+    small.snapshot().unwrap();
+    drop(small);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, 1024).unwrap();
+    assert!(loaded.check().is_empty());
+    assert_eq!(items(&loaded), items(&btree));
+}
+
+#[test]
+fn test_malformed_dump_changes_nothing() {
+    let dumps = [
+        (
+            DumpFormat::JsonLines,
+            "{\"key\":1,\"value\":\"a\"}\n{\"key\":2}\n",
+        ),
+        (
+            DumpFormat::JsonLines,
+            "{\"key\":1,\"value\":\"a\"}\n{\"key\":x,\"value\":\"b\"}\n",
+        ),
+        (
+            DumpFormat::JsonLines,
+            "{\"key\":1,\"value\":\"a\"}\n{\"key\":2,\"value\":\"\\ud800\"}\n",
+        ),
+        (DumpFormat::Csv, "key,value\n2,b,c\n"),
+        (DumpFormat::Csv, "key,value\n2,\"never closed\n"),
+    ];
+    for (format, dump) in dumps {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+        match btree.import(dump.as_bytes(), format) {
+            Err(IndexError::InvalidInput(reason)) => {
+                assert!(reason.starts_with("Line 2 of the dump"), "{reason}")
+            }
+            other => panic!("expected invalid input for {dump:?}, got {other:?}"),
+        }
+        assert!(items(&btree).is_empty());
+    }
+}
+
+#[test]
+fn test_duplicate_key_rejects_the_whole_import() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    btree
+        .insert(Item {
+            key: 2,
+            val: "existing".to_string(),
+        })
+        .unwrap();
+
+    // One key is already in the tree, the other repeats within the dump.
+    for dump in ["key,value\n1,a\n2,b\n3,c\n", "key,value\n1,a\n3,b\n1,c\n"] {
+        assert!(matches!(
+            btree.import(dump.as_bytes(), DumpFormat::Csv),
+            Err(IndexError::Duplicate(_))
+        ));
+        assert_eq!(items(&btree), [(2, "existing".to_string())]);
+    }
+}
+
+#[test]
+fn test_multimap_imports_repeated_keys() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree: Btree =
+        Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let dump = "key,value\n1,a\n1,b\n";
+    assert_eq!(btree.import(dump.as_bytes(), DumpFormat::Csv).unwrap(), 2);
+    assert_eq!(btree.search_all(&1).unwrap(), ["a", "b"]);
+}
+
+#[test]
+fn test_failed_multimap_import_changes_nothing() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree: Btree =
+        Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    btree
+        .insert(Item {
+            key: 1,
+            val: "a".to_string(),
+        })
+        .unwrap();
+
+    // One pair is already in the tree, the other repeats within the dump.
+    for dump in ["key,value\n1,b\n2,c\n1,a\n", "key,value\n1,b\n2,c\n2,c\n"] {
+        assert!(matches!(
+            btree.import(dump.as_bytes(), DumpFormat::Csv),
+            Err(IndexError::Duplicate(_))
+        ));
+        assert_eq!(items(&btree), [(1, "a".to_string())]);
+    }
+}
+
+#[test]
+fn test_import_undoes_inserts_after_a_failure() {
+    // The long key does not fit in a page, which only its insert finds out.
+    // A multimap also takes a second value under the key it already holds.
+    let long_key = "k".repeat(PAGE_SIZE);
+    for (multimap, extra) in [
+        (false, ""),
+        (
+            true, "z,1
+",
+        ),
+    ] {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut btree: Btree<String, String> = if multimap {
+            Btree::new_multimap(path, PAGE_SIZE).unwrap()
+        } else {
+            Btree::new(path, PAGE_SIZE).unwrap()
+        };
+        btree
+            .insert(Item {
+                key: "z".to_string(),
+                val: "0".to_string(),
+            })
+            .unwrap();
+
+        let dump = format!(
+            "key,value
+b,1
+{extra}a,2
+{long_key},3
+c,4
+"
+        );
+        let result = btree.import(dump.as_bytes(), DumpFormat::Csv);
+        assert!(
+            matches!(result, Err(IndexError::PageFull { .. })),
+            "{result:?}"
+        );
+        let items: Vec<(String, String)> = btree
+            .range(..)
+            .map(|item| item.map(|item| (item.key, item.val)).unwrap())
+            .collect();
+        assert_eq!(items, [("z".to_string(), "0".to_string())], "{multimap}");
+        assert!(btree.check().is_empty());
+    }
+}
+
+#[test]
+fn test_csv_needs_its_header() {
+    for dump in ["1,a\n2,b\n", "value,key\n1,a\n", ""] {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+        match btree.import(dump.as_bytes(), DumpFormat::Csv) {
+            Err(IndexError::InvalidInput(reason)) => {
+                assert!(reason.starts_with("Line 1 of the dump"), "{reason}")
+            }
+            other => panic!("expected a missing header for {dump:?}, got {other:?}"),
+        }
+        assert!(items(&btree).is_empty());
+    }
+}
+
+#[test]
+fn test_dump_format_names() {
+    assert_eq!("json".parse::<DumpFormat>().unwrap(), DumpFormat::JsonLines);
+    assert_eq!(
+        "JSONL".parse::<DumpFormat>().unwrap(),
+        DumpFormat::JsonLines
+    );
+    assert_eq!("csv".parse::<DumpFormat>().unwrap(), DumpFormat::Csv);
+    assert!(matches!(
+        "xml".parse::<DumpFormat>(),
+        Err(IndexError::InvalidInput(_))
+    ));
+}
@@ -0,0 +1,162 @@
+// src/btree/tests/error_tests.rs
+use super::super::{
+    Btree, Item, bplus::BPlusTree, buffer_pool::Lru, error::IndexError, metadata::BtreeMetadata,
+    paging::Pager,
+};
+use super::{PAGE_SIZE, item};
+use std::io;
+use tempfile::{NamedTempFile, TempDir};
+
+#[test]
+fn test_conflicts_and_missing_keys_are_typed() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in 0..20 {
+        btree.insert(item(key)).unwrap();
+    }
+
+    assert!(matches!(
+        btree.insert(item(7)),
+        Err(IndexError::Duplicate(_))
+    ));
+    assert!(matches!(btree.search(&99), Err(IndexError::NotFound(_))));
+    assert!(matches!(btree.delete(&99), Err(IndexError::NotFound(_))));
+    assert!(matches!(
+        btree.update(item(99)),
+        Err(IndexError::NotFound(_))
+    ));
+    assert!(matches!(
+        btree.delete_pair(&1, &"value-1".to_string()),
+        Err(IndexError::InvalidInput(_))
+    ));
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut multimap = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    multimap.insert(Item { key: 1, val: 10 }).unwrap();
+    multimap.insert(Item { key: 1, val: 11 }).unwrap();
+    assert!(matches!(
+        multimap.insert(Item { key: 1, val: 10 }),
+        Err(IndexError::Duplicate(_))
+    ));
+}
+
+#[test]
+fn test_invalid_arguments_are_rejected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    assert!(matches!(
+        Btree::<i32, String>::with_degree(path, PAGE_SIZE, 1),
+        Err(IndexError::InvalidInput(_))
+    ));
+
+    let mut btree: Btree = Btree::new(path, PAGE_SIZE).unwrap();
+    assert!(matches!(
+        btree.configure_buffer_pool(0, Box::new(Lru::default())),
+        Err(IndexError::InvalidInput(_))
+    ));
+    assert!(matches!(btree.snapshot(), Err(IndexError::InvalidInput(_))));
+}
+
+#[test]
+fn test_unusable_page_sizes_are_rejected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    // Too small for a page header, for a header and link, for the metadata
+    // slots, and larger than the limit.
+    for page_size in [0, 8, 16, 1000, 2 * 1024 * 1024] {
+        assert!(matches!(
+            Btree::<i32, String>::new(path, page_size),
+            Err(IndexError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            BPlusTree::<i32, String>::new(path, page_size),
+            Err(IndexError::InvalidInput(_))
+        ));
+    }
+
+    let mut btree: Btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree.insert(item(1)).unwrap();
+    btree.snapshot().unwrap();
+    assert!(matches!(
+        Btree::<i32, String>::load_snapshot(path, 8),
+        Err(IndexError::InvalidInput(_))
+    ));
+    assert!(!Btree::<i32, String>::is_valid_snapshot(path, 8));
+}
+
+#[test]
+fn test_oversized_key_is_page_full() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree: Btree<String, String> =
+        Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let err = btree
+        .insert(Item {
+            key: "k".repeat(PAGE_SIZE),
+            val: String::new(),
+        })
+        .unwrap_err();
+    match err {
+        IndexError::PageFull {
+            needed, page_size, ..
+        } => {
+            assert!(needed > PAGE_SIZE);
+            assert_eq!(page_size, PAGE_SIZE);
+        }
+        other => panic!("expected PageFull, got {other}"),
+    }
+}
+
+#[test]
+fn test_newer_snapshot_is_a_version_mismatch() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree.insert(item(1)).unwrap();
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    let (mut pager, mut metadata) = Pager::open_snapshot(path, PAGE_SIZE).unwrap();
+    metadata.version = BtreeMetadata::VERSION + 1;
+    metadata.sequence += 1;
+    pager.write_metadata(&metadata).unwrap();
+    drop(pager);
+
+    match Btree::<i32, String>::load_snapshot(path, PAGE_SIZE) {
+        Err(IndexError::VersionMismatch { found, supported }) => {
+            assert_eq!(found, BtreeMetadata::VERSION + 1);
+            assert_eq!(supported, BtreeMetadata::VERSION);
+        }
+        other => panic!("expected VersionMismatch, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_file_errors_are_io_not_missing_keys() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("missing").join("tree.snap");
+    match Btree::<i32, String>::load_snapshot(path.to_str().unwrap(), PAGE_SIZE) {
+        Err(IndexError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+        other => panic!("expected an I/O error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_errors_survive_a_round_trip_through_io_error() {
+    let err: io::Error = IndexError::Corrupt {
+        page: 7,
+        reason: "bad checksum".to_string(),
+    }
+    .into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match IndexError::from(err) {
+        IndexError::Corrupt { page, reason } => {
+            assert_eq!(page, 7);
+            assert_eq!(reason, "bad checksum");
+        }
+        other => panic!("expected Corrupt, got {other}"),
+    }
+
+    let err = IndexError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(matches!(err, IndexError::Io(_)));
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,120 @@
+// src/btree/tests/events_tests.rs
+use super::super::{
+    Btree,
+    bplus::BPlusTree,
+    events::{Event, Observer, Verbosity},
+};
+use super::{PAGE_SIZE, item};
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+
+/// Keeps every event it sees, rendered, in a log shared with the test.
+#[derive(Debug, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Observer for Recorder {
+    fn on_event(&mut self, event: &Event<'_>) {
+        self.0.lock().unwrap().push(event.to_string());
+    }
+}
+
+fn recorded(btree: &mut Btree) -> Arc<Mutex<Vec<String>>> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    btree.set_observer(Some(Box::new(Recorder(log.clone()))));
+    log
+}
+
+#[test]
+fn test_inserts_report_allocations_and_splits() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::with_degree(temp_file.path().to_str().unwrap(), PAGE_SIZE, 2).unwrap();
+    let log = recorded(&mut btree);
+
+    for key in 1..=5 {
+        btree.insert(item(key)).unwrap();
+    }
+    assert!(btree.insert(item(3)).is_err());
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "allocated page 1",
+            "insert 1",
+            "insert 2",
+            "insert 3",
+            "insert 4",
+            "allocated page 2",
+            "split page 1 into 1 and 2",
+            "allocated page 3",
+            "root split, new root 3",
+            "insert 5",
+        ]
+    );
+}
+
+#[test]
+fn test_deletes_report_borrows_and_merges() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::with_degree(temp_file.path().to_str().unwrap(), PAGE_SIZE, 2).unwrap();
+    // Root [3] over leaves [1, 2] and [4, 5, 6].
+    for key in 1..=6 {
+        btree.insert(item(key)).unwrap();
+    }
+    let log = recorded(&mut btree);
+
+    btree.delete(&1).unwrap();
+    btree.delete(&2).unwrap();
+    btree.delete(&3).unwrap();
+    assert!(btree.delete(&3).is_err());
+
+    let log = log.lock().unwrap();
+    assert!(log.contains(&"delete 1".to_string()));
+    assert!(log.contains(&"page 1 borrowed from page 2".to_string()));
+    assert!(log.iter().any(|event| event.starts_with("merged page")));
+    assert!(log.iter().any(|event| event.starts_with("freed page")));
+    assert_eq!(log.iter().filter(|event| *event == "delete 3").count(), 1);
+}
+
+#[test]
+fn test_detached_observer_stops_receiving_events() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let log = recorded(&mut btree);
+    btree.insert(item(1)).unwrap();
+    btree.set_observer(None);
+    btree.insert(item(2)).unwrap();
+
+    assert_eq!(*log.lock().unwrap(), vec!["allocated page 1", "insert 1"]);
+}
+
+#[test]
+fn test_bplus_reports_leaf_splits() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut tree: BPlusTree =
+        BPlusTree::with_degree(temp_file.path().to_str().unwrap(), PAGE_SIZE, 2).unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    tree.set_observer(Some(Box::new(Recorder(log.clone()))));
+
+    for key in 1..=5 {
+        tree.insert(item(key)).unwrap();
+    }
+
+    let log = log.lock().unwrap();
+    assert!(log.contains(&"split page 1 into 1 and 2".to_string()));
+    assert!(log.contains(&"root split, new root 3".to_string()));
+}
+
+#[test]
+fn test_verbosity_levels() {
+    assert_eq!(Event::Insert { key: &1 }.verbosity(), Verbosity::Detail);
+    assert_eq!(Event::PageAlloc { page: 1 }.verbosity(), Verbosity::Detail);
+    assert_eq!(
+        Event::Merge {
+            page: 1,
+            sibling: 2
+        }
+        .verbosity(),
+        Verbosity::Structure
+    );
+    assert!(Verbosity::Structure < Verbosity::Detail);
+}
@@ -0,0 +1,159 @@
+// src/btree/tests/free_list_tests.rs
+use super::super::{
+    Btree, Item,
+    error::IndexError,
+    upgrade::{Upgrade, upgrade_file},
+};
+use tempfile::NamedTempFile;
+
+fn insert_range(btree: &mut Btree, keys: std::ops::Range<i32>) {
+    for key in keys {
+        btree
+            .insert(Item {
+                key,
+                val: format!("value-{key}"),
+            })
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_freed_overflow_pages_are_reused() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    btree
+        .insert(Item {
+            key: 1,
+            val: "x".repeat(9000),
+        })
+        .unwrap();
+    let pages = btree.pager.num_pages;
+
+    btree.delete(&1).unwrap();
+    assert_eq!(btree.free_pages(), 3);
+
+    btree
+        .insert(Item {
+            key: 2,
+            val: "y".repeat(9000),
+        })
+        .unwrap();
+    assert_eq!(btree.free_pages(), 0);
+    assert_eq!(btree.pager.num_pages, pages);
+    assert_eq!(btree.search(&2).unwrap(), "y".repeat(9000));
+}
+
+#[test]
+fn test_delete_heavy_churn_does_not_grow_file() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    insert_range(&mut btree, 0..300);
+    let pages = btree.pager.num_pages;
+
+    for round in 0..3 {
+        for key in 0..300 {
+            btree.delete(&key).unwrap();
+        }
+        assert!(btree.free_pages() > 0, "round {round} freed nothing");
+
+        insert_range(&mut btree, 0..300);
+        assert!(
+            btree.pager.num_pages <= pages,
+            "round {round} grew the file"
+        );
+    }
+
+    for key in 0..300 {
+        assert_eq!(btree.search(&key).unwrap(), format!("value-{key}"));
+    }
+}
+
+#[test]
+fn test_free_list_survives_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    insert_range(&mut btree, 0..200);
+    for key in 0..150 {
+        btree.delete(&key).unwrap();
+    }
+    assert!(btree.free_pages() > 0);
+    btree.snapshot().unwrap();
+    let free = btree.free_pages();
+    let pages = btree.pager.num_pages;
+
+    let mut loaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded.free_pages(), free);
+
+    insert_range(&mut loaded, 0..100);
+    assert_eq!(loaded.pager.num_pages, pages);
+    assert!(loaded.free_pages() < free);
+    for key in (0..100).chain(150..200) {
+        assert_eq!(loaded.search(&key).unwrap(), format!("value-{key}"));
+    }
+}
+
+#[test]
+fn test_free_list_never_strands_a_page() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    // The only page to list is free, so it cannot also hold the list.
+    let mut btree = Btree::new(path, 4096).unwrap();
+    insert_range(&mut btree, 0..1);
+    let page = btree.pager.allocate_page().unwrap();
+    btree.pager.free_page::<i32, String>(page).unwrap();
+    btree.snapshot().unwrap();
+
+    let mut loaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded.free_pages(), 1);
+
+    // The root moves into the free page; the old root and the old list page
+    // are listed on a new one.
+    insert_range(&mut loaded, 1..2);
+    loaded.snapshot().unwrap();
+    let loaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded.free_pages(), 2);
+    assert_eq!(loaded.pager.num_pages, 4);
+}
+
+#[test]
+fn test_version_two_file_is_read_only_until_upgraded() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree.pager.format_version = 2;
+    insert_range(&mut btree, 0..200);
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    // Pages freed now would never make it onto a version 2 free list.
+    let mut loaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    let rejected = |result| matches!(result, Err(IndexError::InvalidInput(_)));
+    assert!(rejected(loaded.delete(&5)));
+    assert!(rejected(loaded.insert(Item {
+        key: 500,
+        val: "x".to_string(),
+    })));
+    assert!(rejected(loaded.snapshot().map(drop)));
+    assert_eq!(loaded.search(&5).unwrap(), "value-5");
+    drop(loaded);
+
+    assert!(matches!(
+        upgrade_file::<i32, String>(path, 4096).unwrap(),
+        Upgrade::Upgraded { from: 2, .. }
+    ));
+    let mut upgraded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    for key in 0..100 {
+        upgraded.delete(&key).unwrap();
+    }
+    upgraded.snapshot().unwrap();
+    let free = upgraded.free_pages();
+    assert!(free > 0);
+    let reloaded = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(reloaded.free_pages(), free);
+}
@@ -0,0 +1,259 @@
+// src/btree/tests/latching_tests.rs
+//
+// Checks `InMemoryLatchedBtree` against a `BTreeMap` oracle on one thread, then
+// runs mixed workloads across threads and checks what every thread saw and
+// the tree's invariants once they finish. Degree 2 keeps nodes small, so
+// splits, borrows and merges happen on nearly every write.
+use super::super::{error::IndexError, latching::InMemoryLatchedBtree};
+use super::item;
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{
+        Barrier,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+};
+
+const THREADS: i32 = 8;
+
+fn tree(degree: i32) -> InMemoryLatchedBtree {
+    InMemoryLatchedBtree::with_degree(degree).unwrap()
+}
+
+fn keys(tree: &InMemoryLatchedBtree) -> Vec<i32> {
+    tree.range(..).into_iter().map(|item| item.key).collect()
+}
+
+/// A small xorshift generator, seeded per thread.
+fn rng(seed: u64) -> impl FnMut(u64) -> i32 {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    move |bound| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        ((state >> 32) % bound) as i32
+    }
+}
+
+#[test]
+fn test_matches_oracle_on_one_thread() {
+    for degree in [2, 3, 5] {
+        let tree = tree(degree);
+        let mut oracle = BTreeMap::new();
+        let mut next = rng(degree as u64);
+        for step in 0..4000 {
+            let key = next(300);
+            match next(3) {
+                0 | 1 => {
+                    let inserted = tree.insert(item(key));
+                    assert_eq!(
+                        inserted.is_ok(),
+                        oracle.insert(key, item(key).val).is_none(),
+                        "step {step}: insert {key}"
+                    );
+                    if let Err(e) = inserted {
+                        assert!(matches!(e, IndexError::Duplicate(_)));
+                    }
+                }
+                _ => assert_eq!(
+                    tree.delete(&key).is_ok(),
+                    oracle.remove(&key).is_some(),
+                    "step {step}: delete {key}"
+                ),
+            }
+            assert_eq!(tree.search(&key).ok(), oracle.get(&key).cloned());
+            if step % 100 == 0 {
+                assert_eq!(tree.check(), Vec::<String>::new(), "step {step}");
+            }
+        }
+        assert_eq!(keys(&tree), oracle.keys().copied().collect::<Vec<_>>());
+        let bounds = [
+            (Bound::Included(100), Bound::Included(200)),
+            (Bound::Excluded(100), Bound::Excluded(200)),
+            (Bound::Excluded(-1), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Included(150)),
+            (Bound::Included(299), Bound::Unbounded),
+        ];
+        for bounds in bounds {
+            assert_eq!(
+                tree.range(bounds)
+                    .into_iter()
+                    .map(|item| item.key)
+                    .collect::<Vec<_>>(),
+                oracle.range(bounds).map(|(k, _)| *k).collect::<Vec<_>>(),
+                "{bounds:?}"
+            );
+        }
+
+        for key in oracle.keys() {
+            tree.delete(key).unwrap();
+        }
+        assert!(keys(&tree).is_empty());
+        assert!(tree.check().is_empty());
+    }
+}
+
+#[test]
+fn test_disjoint_writers() {
+    let tree = tree(2);
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let tree = &tree;
+            scope.spawn(move || {
+                for i in 0..500 {
+                    tree.insert(item(i * THREADS + t)).unwrap();
+                }
+                for i in (0..500).step_by(2) {
+                    tree.delete(&(i * THREADS + t)).unwrap();
+                }
+            });
+        }
+    });
+
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+    let expected: Vec<i32> = (0..500 * THREADS)
+        .filter(|key| (key / THREADS) % 2 == 1)
+        .collect();
+    assert_eq!(keys(&tree), expected);
+}
+
+#[test]
+fn test_racing_writers_succeed_once_per_key() {
+    let tree = tree(2);
+    let barrier = Barrier::new(THREADS as usize);
+    let inserted = AtomicU64::new(0);
+    let deleted = AtomicU64::new(0);
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let (tree, barrier, inserted, deleted) = (&tree, &barrier, &inserted, &deleted);
+            scope.spawn(move || {
+                // Every thread walks the same keys from a different start.
+                let keys = || (0..1000).map(|i| (i * 7 + t * 125) % 1000);
+                barrier.wait();
+                for key in keys() {
+                    match tree.insert(item(key)) {
+                        Ok(()) => inserted.fetch_add(1, Ordering::Relaxed),
+                        Err(IndexError::Duplicate(_)) => 0,
+                        Err(e) => panic!("insert {key}: {e}"),
+                    };
+                }
+                barrier.wait();
+                for key in keys().filter(|key| key % 3 != 0) {
+                    match tree.delete(&key) {
+                        Ok(()) => deleted.fetch_add(1, Ordering::Relaxed),
+                        Err(IndexError::NotFound(_)) => 0,
+                        Err(e) => panic!("delete {key}: {e}"),
+                    };
+                }
+            });
+        }
+    });
+
+    assert_eq!(inserted.into_inner(), 1000);
+    assert_eq!(deleted.into_inner(), 666);
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+    assert_eq!(keys(&tree), (0..1000).step_by(3).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_readers_always_find_stable_keys() {
+    // Even keys are inserted up front and never touched again; writers churn
+    // the odd keys around them while readers look the even ones up.
+    let tree = tree(2);
+    for key in (0..2000).step_by(2) {
+        tree.insert(item(key)).unwrap();
+    }
+
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let writers: Vec<_> = (0..THREADS / 2)
+            .map(|t| {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut next = rng(t as u64 + 1);
+                    for _ in 0..20_000 {
+                        let key = next(1000) * 2 + 1;
+                        let _ = match next(2) {
+                            0 => tree.insert(item(key)),
+                            _ => tree.delete(&key),
+                        };
+                    }
+                })
+            })
+            .collect();
+
+        for t in 0..THREADS / 2 {
+            let (tree, done) = (&tree, &done);
+            scope.spawn(move || {
+                let mut next = rng(t as u64 + 100);
+                while !done.load(Ordering::Relaxed) {
+                    let key = next(1000) * 2;
+                    assert_eq!(tree.search(&key).unwrap(), item(key).val);
+
+                    // Scans see every stable key in the window, in order.
+                    let window = tree.range(key..key + 40);
+                    assert!(window.windows(2).all(|w| w[0].key < w[1].key));
+                    let stable = window.iter().filter(|item| item.key % 2 == 0).count();
+                    assert_eq!(stable, (key..(key + 40).min(2000)).step_by(2).count());
+                }
+            });
+        }
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+    let stable: Vec<i32> = keys(&tree).into_iter().filter(|k| k % 2 == 0).collect();
+    assert_eq!(stable, (0..2000).step_by(2).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_mixed_workload_keeps_invariants() {
+    for degree in [2, 4] {
+        let tree = tree(degree);
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut next = rng((degree * 100 + t) as u64);
+                    for _ in 0..10_000 {
+                        let key = next(500);
+                        match next(10) {
+                            0..=3 => {
+                                if let Ok(val) = tree.search(&key) {
+                                    assert_eq!(val, item(key).val);
+                                }
+                            }
+                            4..=6 => {
+                                let _ = tree.insert(item(key));
+                            }
+                            _ => {
+                                let _ = tree.delete(&key);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(tree.check().is_empty(), "{:?}", tree.check());
+        let items = tree.range(..);
+        assert!(items.windows(2).all(|w| w[0].key < w[1].key));
+        for item in items {
+            assert_eq!(tree.search(&item.key).unwrap(), item.val);
+        }
+    }
+}
+
+#[test]
+fn test_invalid_degree() {
+    assert!(matches!(
+        InMemoryLatchedBtree::<i32, String>::with_degree(1),
+        Err(IndexError::InvalidInput(_))
+    ));
+}
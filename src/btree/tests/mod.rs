@@ -1,2 +1,35 @@
+use super::Item;
+
+mod bplus_tests;
 mod btree_tests;
+mod buffer_pool_tests;
+mod bulk_load_tests;
+mod check_tests;
+mod compression_tests;
+mod corruption_tests;
+mod dump_tests;
+mod error_tests;
+mod events_tests;
+mod free_list_tests;
+mod latching_tests;
+mod model_tests;
+mod multimap_tests;
+mod overflow_tests;
+mod rank_tests;
+mod shadow_tests;
+mod shared_tests;
 mod snapshot_tests;
+mod upgrade_tests;
+mod view_tests;
+mod visualizer_tests;
+mod wal_tests;
+
+/// Page size the test trees are created with.
+const PAGE_SIZE: usize = 4096;
+
+fn item(key: i32) -> Item {
+    Item {
+        key,
+        val: format!("value-{key}"),
+    }
+}
@@ -0,0 +1,362 @@
+// src/btree/tests/model_tests.rs
+//
+// Runs seeded random operation sequences against `Btree` and a `BTreeMap`
+// oracle, checking every result and the tree's invariants after each step.
+// Read views opened along the way are checked against a copy of the oracle
+// taken when they were opened. A failing sequence is shrunk to a minimal
+// reproduction before reporting. Set `INDEXIUM_MODEL_SEED` to replay a
+// single seed.
+use super::super::{
+    Btree, Item,
+    buffer_pool::{DEFAULT_POOL_CAPACITY, Lru},
+    error::IndexError,
+    view::ReadView,
+};
+use super::PAGE_SIZE;
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    panic::{self, AssertUnwindSafe},
+};
+use tempfile::TempDir;
+
+const SEEDS: u64 = 48;
+const OPS_PER_SEED: usize = 400;
+const KEY_SPACE: u64 = 48;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+    Insert(i32, i32),
+    Upsert(i32, i32),
+    Delete(i32),
+    Search(i32),
+    /// Publishes a snapshot.
+    Snapshot,
+    /// Drops the open read views and the tree, then reopens the last
+    /// published snapshot, losing anything written after it.
+    Reload,
+    /// Opens a read view.
+    OpenView,
+    /// Drops the open read view at this index modulo their number, if any.
+    DropView(usize),
+}
+
+/// How a run sets up its tree and which operations it draws.
+#[derive(Clone, Copy, Debug)]
+struct Config {
+    degree: i32,
+    /// Buffer pool capacity; a small pool writes pages back early, so a
+    /// page overwritten while a view still needs it reaches the file.
+    pool: usize,
+    views: bool,
+}
+
+impl Config {
+    fn new(degree: i32) -> Self {
+        Config {
+            degree,
+            pool: DEFAULT_POOL_CAPACITY,
+            views: false,
+        }
+    }
+
+    fn with_views(degree: i32, pool: usize) -> Self {
+        Config {
+            degree,
+            pool,
+            views: true,
+        }
+    }
+}
+
+fn generate(seed: u64, len: usize, views: bool) -> Vec<Op> {
+    let mut state = seed;
+    let mut next = |bound: u64| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((state >> 33) % bound) as i32
+    };
+    (0..len)
+        .map(|_| match (views, next(100)) {
+            (false, 0..=44) => Op::Insert(next(KEY_SPACE), next(1000)),
+            (false, 45..=74) => Op::Delete(next(KEY_SPACE)),
+            (false, 75..=89) => Op::Search(next(KEY_SPACE)),
+            (false, 90..=95) => Op::Snapshot,
+            (false, _) => Op::Reload,
+            (true, 0..=29) => Op::Insert(next(KEY_SPACE), next(1000)),
+            (true, 30..=49) => Op::Upsert(next(KEY_SPACE), next(1000)),
+            (true, 50..=74) => Op::Delete(next(KEY_SPACE)),
+            (true, 75..=79) => Op::Search(next(KEY_SPACE)),
+            (true, 80..=86) => Op::Snapshot,
+            (true, 87..=92) => Op::OpenView,
+            (true, 93..=98) => Op::DropView(next(4) as usize),
+            (true, _) => Op::Reload,
+        })
+        .collect()
+}
+
+fn value(val: i32) -> String {
+    format!("value-{val}")
+}
+
+fn new_tree(path: &str, config: Config) -> Result<Btree, IndexError> {
+    let mut btree = Btree::with_degree(path, PAGE_SIZE, config.degree)?;
+    btree.configure_buffer_pool(config.pool, Box::new(Lru::default()))?;
+    Ok(btree)
+}
+
+fn contents(
+    items: impl Iterator<Item = Result<Item, IndexError>>,
+) -> Result<Vec<(i32, String)>, IndexError> {
+    items
+        .map(|item| item.map(|item| (item.key, item.val)))
+        .collect()
+}
+
+/// Runs `ops` on a fresh tree, returning a description of the first step
+/// where the tree or a read view and the oracle disagree or the tree breaks
+/// an invariant.
+fn run(ops: &[Op], config: Config) -> Result<(), String> {
+    let dir = TempDir::new().map_err(|e| e.to_string())?;
+    let path = dir.path().join("model.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = new_tree(path, config).map_err(|e| e.to_string())?;
+    let mut model: BTreeMap<i32, String> = BTreeMap::new();
+    let mut published: Option<BTreeMap<i32, String>> = None;
+    let mut views: Vec<(ReadView, BTreeMap<i32, String>)> = Vec::new();
+
+    for (step, op) in ops.iter().enumerate() {
+        let fail = |msg: String| format!("step {step} ({op:?}): {msg}");
+        match *op {
+            Op::Insert(key, val) => {
+                let result = btree.insert(Item {
+                    key,
+                    val: value(val),
+                });
+                match (result, model.contains_key(&key)) {
+                    (Ok(()), false) => {
+                        model.insert(key, value(val));
+                    }
+                    (Err(e), true) if e.kind() == ErrorKind::AlreadyExists => {}
+                    (result, present) => {
+                        return Err(fail(format!("returned {result:?}, key present: {present}")));
+                    }
+                }
+            }
+            Op::Upsert(key, val) => {
+                let result = btree.upsert(Item {
+                    key,
+                    val: value(val),
+                });
+                let expected = model.insert(key, value(val));
+                if result.as_ref().ok() != Some(&expected) {
+                    return Err(fail(format!(
+                        "returned {result:?}, model held {expected:?}"
+                    )));
+                }
+            }
+            Op::Delete(key) => {
+                let result = btree.delete(&key);
+                match (result, model.remove(&key)) {
+                    (Ok(()), Some(_)) => {}
+                    (Err(e), None) if e.kind() == ErrorKind::NotFound => {}
+                    (result, expected) => {
+                        return Err(fail(format!(
+                            "returned {result:?}, model held {expected:?}"
+                        )));
+                    }
+                }
+            }
+            Op::Search(key) => {
+                let found = btree.search(&key).ok();
+                if found.as_ref() != model.get(&key) {
+                    return Err(fail(format!(
+                        "found {found:?}, model holds {:?}",
+                        model.get(&key)
+                    )));
+                }
+            }
+            // A tree that never held an item has no root page to publish.
+            Op::Snapshot if btree.root.is_none() => match btree.snapshot() {
+                Err(e) if e.kind() == ErrorKind::InvalidInput => {}
+                result => return Err(fail(format!("returned {result:?} without a root"))),
+            },
+            Op::Snapshot => {
+                btree.snapshot().map_err(|e| fail(e.to_string()))?;
+                let violations = Btree::<i32, String>::check_file(path, PAGE_SIZE)
+                    .map_err(|e| fail(e.to_string()))?;
+                if !violations.is_empty() {
+                    return Err(fail(format!("snapshot file is broken: {violations:?}")));
+                }
+                published = Some(model.clone());
+            }
+            Op::Reload => {
+                views.clear();
+                drop(btree);
+                match &published {
+                    Some(snapshot) => {
+                        btree = Btree::load_snapshot(path, PAGE_SIZE)
+                            .map_err(|e| fail(e.to_string()))?;
+                        btree
+                            .configure_buffer_pool(config.pool, Box::new(Lru::default()))
+                            .map_err(|e| fail(e.to_string()))?;
+                        model = snapshot.clone();
+                    }
+                    None => {
+                        btree = new_tree(path, config).map_err(|e| fail(e.to_string()))?;
+                        model.clear();
+                    }
+                }
+            }
+            Op::OpenView => {
+                let view = btree.read_view().map_err(|e| fail(e.to_string()))?;
+                views.push((view, model.clone()));
+            }
+            Op::DropView(_) if views.is_empty() => {}
+            Op::DropView(i) => {
+                views.remove(i % views.len());
+            }
+        }
+
+        for (i, (view, expected)) in views.iter().enumerate() {
+            let seen = contents(view.range(..)).map_err(|e| fail(format!("view {i}: {e}")))?;
+            if !seen.iter().map(|(k, v)| (k, v)).eq(expected.iter()) {
+                return Err(fail(format!(
+                    "view {i} holds {seen:?}, it was opened on {expected:?}"
+                )));
+            }
+        }
+
+        let violations = btree.check();
+        if !violations.is_empty() {
+            return Err(fail(format!("invariants broken: {violations:?}")));
+        }
+        let contents = contents(btree.range(..)).map_err(|e| fail(e.to_string()))?;
+        if !contents.iter().map(|(k, v)| (k, v)).eq(model.iter()) {
+            return Err(fail(format!(
+                "tree holds {contents:?}, model holds {model:?}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Like `run`, but also turns a panic inside the tree into a failure.
+fn run_caught(ops: &[Op], config: Config) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(|| run(ops, config)))
+        .unwrap_or_else(|_| Err("the tree panicked".to_string()))
+}
+
+/// Removes chunks of `items`, halving the chunk size down to single
+/// elements, for as long as `fails` keeps failing. The result fails and no
+/// single element can be dropped from it.
+fn shrink<T: Clone>(mut items: Vec<T>, fails: impl Fn(&[T]) -> bool) -> Vec<T> {
+    let mut chunk = items.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        let mut removed = false;
+        while start < items.len() {
+            let end = (start + chunk).min(items.len());
+            let candidate: Vec<T> = items[..start]
+                .iter()
+                .chain(&items[end..])
+                .cloned()
+                .collect();
+            if fails(&candidate) {
+                items = candidate;
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    items
+}
+
+fn check_seed(seed: u64, config: Config) {
+    let ops = generate(seed, OPS_PER_SEED, config.views);
+    if let Err(error) = run_caught(&ops, config) {
+        let minimal = shrink(ops, |ops| run_caught(ops, config).is_err());
+        let minimal_error = run_caught(&minimal, config).unwrap_err();
+        panic!(
+            "seed {seed}, {config:?}: {error}\n\
+             minimal reproduction ({} ops): {minimal:?}\n{minimal_error}",
+            minimal.len()
+        );
+    }
+}
+
+fn seeds() -> Vec<u64> {
+    match std::env::var("INDEXIUM_MODEL_SEED") {
+        Ok(seed) => vec![seed.parse().expect("INDEXIUM_MODEL_SEED must be a number")],
+        Err(_) => (0..SEEDS).collect(),
+    }
+}
+
+#[test]
+fn test_random_operations_match_btreemap_degree_2() {
+    for seed in seeds() {
+        check_seed(seed, Config::new(2));
+    }
+}
+
+#[test]
+fn test_random_operations_match_btreemap_degree_3() {
+    for seed in seeds() {
+        check_seed(seed, Config::new(3));
+    }
+}
+
+#[test]
+fn test_read_views_match_btreemap_small_pool() {
+    for seed in seeds() {
+        check_seed(seed, Config::with_views(2, 4));
+    }
+}
+
+#[test]
+fn test_read_views_match_btreemap() {
+    for seed in seeds() {
+        check_seed(seed, Config::with_views(2, DEFAULT_POOL_CAPACITY));
+    }
+}
+
+#[test]
+fn test_shrink_finds_minimal_failing_sequence() {
+    let items: Vec<i32> = (0..100).collect();
+    let minimal = shrink(items, |items| items.contains(&13) && items.contains(&71));
+    assert_eq!(minimal, vec![13, 71]);
+}
+
+#[test]
+fn test_harness_detects_and_shrinks_a_divergence() {
+    // A model that forgets deletes diverges from the tree; the shrinker
+    // should cut the sequence down to the insert and delete of one key.
+    let ops = vec![
+        Op::Insert(1, 1),
+        Op::Insert(2, 2),
+        Op::Search(1),
+        Op::Delete(2),
+        Op::Snapshot,
+    ];
+    let diverges = |ops: &[Op]| {
+        let mut model = BTreeMap::new();
+        for op in ops {
+            match *op {
+                Op::Insert(key, val) => {
+                    model.entry(key).or_insert(val);
+                }
+                Op::Delete(key) if model.contains_key(&key) => return true,
+                _ => {}
+            }
+        }
+        false
+    };
+    assert!(run(&ops, Config::new(2)).is_ok());
+    assert_eq!(shrink(ops, diverges), vec![Op::Insert(2, 2), Op::Delete(2)]);
+}
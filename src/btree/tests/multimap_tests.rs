@@ -0,0 +1,195 @@
+// src/btree/tests/multimap_tests.rs
+use super::super::{
+    Btree, Item,
+    wal::{SyncPolicy, Wal},
+};
+use super::PAGE_SIZE;
+use std::{collections::BTreeSet, io::ErrorKind};
+use tempfile::{NamedTempFile, TempDir};
+
+fn pair(key: i32, val: i32) -> Item<i32, i32> {
+    Item { key, val }
+}
+
+fn pairs(btree: &Btree<i32, i32>) -> Vec<(i32, i32)> {
+    btree
+        .range(..)
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key, item.val)
+        })
+        .collect()
+}
+
+#[test]
+fn test_duplicate_runs_span_splits() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+
+    // Enough values per key that every run is split across several nodes.
+    for i in 0..300 {
+        let (key, val) = (i % 10, (i * 37) % 300);
+        btree.insert(pair(key, val)).unwrap();
+    }
+
+    for key in 0..10 {
+        let expected: Vec<i32> = (0..300)
+            .filter(|i| i % 10 == key)
+            .map(|i| (i * 37) % 300)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(btree.search_all(&key).unwrap(), expected);
+        assert_eq!(btree.search(&key).unwrap(), expected[0]);
+    }
+
+    let forward = pairs(&btree);
+    let mut sorted = forward.clone();
+    sorted.sort();
+    assert_eq!(forward, sorted);
+
+    // Both ends meet in the middle of a run without skipping or repeating.
+    let mut range = btree.range(3..=4);
+    let mut seen = Vec::new();
+    while let Some(front) = range.next() {
+        seen.push(front.unwrap().val);
+        if let Some(back) = range.next_back() {
+            seen.push(back.unwrap().val);
+        }
+    }
+    assert_eq!(seen.len(), 60);
+}
+
+#[test]
+fn test_delete_one_pair_or_all_of_a_key() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in 0..5 {
+        for val in 0..20 {
+            btree.insert(pair(key, val)).unwrap();
+        }
+    }
+
+    btree.delete_pair(&2, &7).unwrap();
+    let expected: Vec<i32> = (0..20).filter(|&v| v != 7).collect();
+    assert_eq!(btree.search_all(&2).unwrap(), expected);
+    let err = btree.delete_pair(&2, &7).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    btree.delete(&3).unwrap();
+    assert!(btree.search_all(&3).unwrap().is_empty());
+    assert_eq!(btree.delete(&3).unwrap_err().kind(), ErrorKind::NotFound);
+
+    for key in [0, 1, 4] {
+        assert_eq!(btree.search_all(&key).unwrap(), (0..20).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_identical_pairs_and_replacing_values_are_rejected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    btree.insert(pair(1, 10)).unwrap();
+    btree.insert(pair(1, 11)).unwrap();
+
+    assert_eq!(
+        btree.insert(pair(1, 10)).unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        btree.update(pair(1, 12)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        btree.upsert(pair(1, 12)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(btree.search_all(&1).unwrap(), vec![10, 11]);
+}
+
+#[test]
+fn test_delete_pair_needs_multimap() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree: Btree<i32, i32> =
+        Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    btree.insert(pair(1, 10)).unwrap();
+
+    let err = btree.delete_pair(&1, &10).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(btree.search_all(&1).unwrap(), vec![10]);
+}
+
+#[test]
+fn test_random_operations_match_model() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let mut model = BTreeSet::new();
+
+    let mut state: u64 = 42;
+    let mut next = |bound: u64| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((state >> 33) % bound) as i32
+    };
+    for round in 0..2000 {
+        let (key, val) = (next(20), next(30));
+        match next(10) {
+            0..=5 => {
+                assert_eq!(
+                    btree.insert(pair(key, val)).is_ok(),
+                    model.insert((key, val))
+                );
+            }
+            6..=8 => {
+                assert_eq!(
+                    btree.delete_pair(&key, &val).is_ok(),
+                    model.remove(&(key, val))
+                );
+            }
+            _ => {
+                let had = model.iter().any(|&(k, _)| k == key);
+                model.retain(|&(k, _)| k != key);
+                assert_eq!(btree.delete(&key).is_ok(), had);
+            }
+        }
+        if round % 100 == 0 {
+            assert_eq!(pairs(&btree), model.iter().copied().collect::<Vec<_>>());
+        }
+    }
+    assert_eq!(pairs(&btree), model.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_multimap_survives_snapshot_and_wal() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("multimap.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = Btree::new_multimap(path, PAGE_SIZE).unwrap();
+    for val in 0..50 {
+        btree.insert(pair(val % 2, val)).unwrap();
+    }
+    btree.snapshot().unwrap();
+    btree
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    btree.delete_pair(&0, &10).unwrap();
+    assert!(btree.upsert(pair(1, 7)).is_err());
+    assert!(btree.update(pair(1, 7)).is_err());
+    btree.insert(pair(1, 100)).unwrap();
+    drop(btree);
+
+    let err = Btree::<i32, i32>::load_snapshot(path, PAGE_SIZE).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut loaded = Btree::<i32, i32>::load_multimap(path, PAGE_SIZE).unwrap();
+    let replay = loaded
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    assert_eq!((replay.applied, replay.skipped), (2, 0));
+    let evens: Vec<i32> = (0..50).step_by(2).filter(|&v| v != 10).collect();
+    let odds: Vec<i32> = (1..50).step_by(2).chain([100]).collect();
+    assert_eq!(loaded.search_all(&0).unwrap(), evens);
+    assert_eq!(loaded.search_all(&1).unwrap(), odds);
+}
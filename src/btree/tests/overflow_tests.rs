@@ -0,0 +1,127 @@
+use super::super::{Btree, Item};
+use tempfile::NamedTempFile;
+
+fn big_value(seed: i32, len: usize) -> String {
+    (0..len)
+        .map(|i| (b'a' + ((i as i32 + seed) % 26) as u8) as char)
+        .collect()
+}
+
+fn count_free_pages(btree: &Btree) -> usize {
+    (1..=btree.pager.num_pages)
+        .filter(|&id| btree.pager.read_raw(id).unwrap()[0] == 5)
+        .count()
+}
+
+#[test]
+fn test_large_values_round_trip() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for i in 0..20 {
+        let len = if i % 3 == 0 { 10_000 + i as usize } else { 10 };
+        btree
+            .insert(Item {
+                key: i,
+                val: big_value(i, len),
+            })
+            .unwrap();
+    }
+    for i in 0..20 {
+        let len = if i % 3 == 0 { 10_000 + i as usize } else { 10 };
+        assert_eq!(btree.search(&i).unwrap(), big_value(i, len));
+    }
+    btree.snapshot().unwrap();
+
+    let loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    for i in 0..20 {
+        let len = if i % 3 == 0 { 10_000 + i as usize } else { 10 };
+        assert_eq!(loaded_btree.search(&i).unwrap(), big_value(i, len));
+    }
+}
+
+#[test]
+fn test_values_spill_only_when_needed() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    btree
+        .insert(Item {
+            key: 1,
+            val: big_value(1, 1000),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 2,
+            val: big_value(2, 1000),
+        })
+        .unwrap();
+    assert_eq!(btree.pager.num_pages, 1);
+
+    btree
+        .insert(Item {
+            key: 3,
+            val: big_value(3, 3000),
+        })
+        .unwrap();
+    assert_eq!(btree.pager.num_pages, 2);
+    assert_eq!(btree.search(&1).unwrap(), big_value(1, 1000));
+    assert_eq!(btree.search(&3).unwrap(), big_value(3, 3000));
+}
+
+#[test]
+fn test_unchanged_overflow_chain_is_reused() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    btree
+        .insert(Item {
+            key: 10,
+            val: big_value(10, 9000),
+        })
+        .unwrap();
+    let pages = btree.pager.num_pages;
+
+    btree
+        .insert(Item {
+            key: 5,
+            val: "small".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 20,
+            val: "small".to_string(),
+        })
+        .unwrap();
+    assert_eq!(btree.pager.num_pages, pages);
+    assert_eq!(count_free_pages(&btree), 0);
+}
+
+#[test]
+fn test_delete_frees_overflow_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+
+    btree
+        .insert(Item {
+            key: 1,
+            val: "small".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 2,
+            val: big_value(2, 9000),
+        })
+        .unwrap();
+    let chain_pages = btree.pager.num_pages as usize - 1;
+    assert_eq!(chain_pages, 3);
+
+    btree.delete(&2).unwrap();
+    assert_eq!(count_free_pages(&btree), chain_pages);
+    assert_eq!(btree.search(&1).unwrap(), "small");
+    assert!(btree.search(&2).is_err());
+}
@@ -0,0 +1,135 @@
+// src/btree/tests/rank_tests.rs
+use super::super::{Btree, Item, error::IndexError};
+use super::{PAGE_SIZE, item};
+use std::ops::Bound;
+use tempfile::NamedTempFile;
+
+/// Compares rank, select and count against `keys`, which must be sorted.
+fn assert_statistics(btree: &Btree, keys: &[i32]) {
+    assert!(btree.check().is_empty(), "{:?}", btree.check());
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(btree.rank(key).unwrap(), i as u64);
+        assert_eq!(btree.select(i as u64).unwrap().key, *key);
+    }
+    assert!(matches!(
+        btree.select(keys.len() as u64),
+        Err(IndexError::NotFound(_))
+    ));
+
+    for lo in (-2..=keys.len() as i32 * 3).step_by(7) {
+        for hi in (lo - 3..=keys.len() as i32 * 3).step_by(11) {
+            let expected = keys.iter().filter(|&&k| lo <= k && k <= hi).count() as u64;
+            assert_eq!(btree.count(lo..=hi).unwrap(), expected, "{lo}..={hi}");
+            let expected = keys.iter().filter(|&&k| lo < k && k < hi).count() as u64;
+            assert_eq!(
+                btree
+                    .count((Bound::Excluded(lo), Bound::Excluded(hi)))
+                    .unwrap(),
+                expected,
+                "{lo}<..{hi}"
+            );
+        }
+    }
+    assert_eq!(btree.count(..).unwrap(), keys.len() as u64);
+}
+
+#[test]
+fn test_statistics_follow_inserts_and_deletes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let mut keys: Vec<i32> = (0..120).map(|i| (i * 37) % 120 * 3).collect();
+    for &key in &keys {
+        btree.insert(item(key)).unwrap();
+    }
+    keys.sort();
+    assert_statistics(&btree, &keys);
+
+    // A failed insert or delete must leave the counts alone.
+    assert!(btree.insert(item(keys[5])).is_err());
+    assert!(btree.delete(&1).is_err());
+    assert_statistics(&btree, &keys);
+
+    for i in (0..120).step_by(3) {
+        let key = (i * 53) % 120 * 3;
+        btree.delete(&key).unwrap();
+        keys.retain(|&k| k != key);
+    }
+    assert_statistics(&btree, &keys);
+}
+
+#[test]
+fn test_rank_of_missing_keys() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    assert_eq!(btree.rank(&5).unwrap(), 0);
+    assert_eq!(btree.count(..).unwrap(), 0);
+    assert!(matches!(btree.select(0), Err(IndexError::NotFound(_))));
+
+    for key in (10..=100).step_by(10) {
+        btree.insert(item(key)).unwrap();
+    }
+    assert_eq!(btree.rank(&5).unwrap(), 0);
+    assert_eq!(btree.rank(&55).unwrap(), 5);
+    assert_eq!(btree.rank(&1000).unwrap(), 10);
+    assert_eq!(
+        btree
+            .count((Bound::Included(60), Bound::Excluded(20)))
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_counts_persist_across_snapshots() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree
+        .bulk_load((0..200).map(|key| item(key * 2)), 0.7)
+        .unwrap();
+    btree.delete(&100).unwrap();
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let keys: Vec<i32> = (0..200).map(|key| key * 2).filter(|&k| k != 100).collect();
+    assert_statistics(&loaded, &keys);
+}
+
+#[test]
+fn test_older_formats_count_by_walking() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree.pager.format_version = 4;
+    for key in 0..80 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(loaded.pager.format_version, 4);
+    assert_statistics(&loaded, &(0..80).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_duplicate_keys_are_counted_per_pair() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut multimap = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for key in 0..20 {
+        for val in 0..3 {
+            multimap.insert(Item { key, val }).unwrap();
+        }
+    }
+
+    assert_eq!(multimap.rank(&4).unwrap(), 12);
+    assert_eq!(multimap.count(4..=4).unwrap(), 3);
+    assert_eq!(multimap.count(4..6).unwrap(), 6);
+    let item = multimap.select(13).unwrap();
+    assert_eq!((item.key, item.val), (4, 1));
+
+    multimap.delete_pair(&4, &1).unwrap();
+    assert_eq!(multimap.count(4..=4).unwrap(), 2);
+    assert_eq!(multimap.rank(&5).unwrap(), 14);
+}
@@ -0,0 +1,124 @@
+// src/btree/tests/shadow_tests.rs
+use super::super::{Btree, Item, buffer_pool::Lru};
+use super::{PAGE_SIZE, item};
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+use tempfile::NamedTempFile;
+
+fn snapshot_with(path: &str, keys: std::ops::Range<i32>) {
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    for key in keys {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+}
+
+fn assert_holds_exactly(btree: &Btree, keys: std::ops::Range<i32>) {
+    let found: Vec<i32> = btree.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(found, keys.collect::<Vec<_>>());
+}
+
+#[test]
+fn test_unpublished_pages_do_not_touch_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    snapshot_with(path, 0..100);
+
+    let mut btree = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    for key in 0..60 {
+        btree.delete(&key).unwrap();
+    }
+    for key in 100..200 {
+        btree.insert(item(key)).unwrap();
+    }
+    // Crash after the new pages reached the disk but before the metadata flip.
+    btree.pager.flush().unwrap();
+    btree.pager.file.sync_all().unwrap();
+    drop(btree);
+
+    let reloaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_holds_exactly(&reloaded, 0..100);
+}
+
+#[test]
+fn test_evictions_do_not_touch_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    snapshot_with(path, 0..200);
+
+    let mut btree = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    btree
+        .configure_buffer_pool(4, Box::new(Lru::default()))
+        .unwrap();
+    for key in (0..200).step_by(2) {
+        btree.delete(&key).unwrap();
+    }
+    assert!(btree.buffer_pool_stats().writebacks > 0);
+    drop(btree);
+
+    let reloaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_holds_exactly(&reloaded, 0..200);
+}
+
+#[test]
+fn test_torn_metadata_falls_back_to_previous_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    snapshot_with(path, 0..50);
+
+    let mut btree = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    for key in 50..80 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+    let sequence = btree.pager.sequence;
+    drop(btree);
+
+    let latest = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(latest.pager.sequence, sequence);
+    assert_holds_exactly(&latest, 0..80);
+    drop(latest);
+
+    // Tear the slot the second snapshot was written to.
+    let slot = (sequence % 2) * 512;
+    let mut file = OpenOptions::new().write(true).open(path).unwrap();
+    file.seek(SeekFrom::Start(slot + 20)).unwrap();
+    file.write_all(&[0xff; 16]).unwrap();
+    drop(file);
+
+    let previous = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(previous.pager.sequence, sequence - 1);
+    assert_holds_exactly(&previous, 0..50);
+}
+
+#[test]
+fn test_replaced_pages_are_reused_after_publish() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    snapshot_with(path, 0..100);
+
+    let mut btree = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    let mut sizes = Vec::new();
+    for round in 0..10 {
+        for key in 0..100 {
+            btree.delete(&key).unwrap();
+            btree
+                .insert(Item {
+                    key,
+                    val: format!("round-{round}"),
+                })
+                .unwrap();
+        }
+        btree.snapshot().unwrap();
+        sizes.push(btree.pager.num_pages);
+    }
+
+    assert!(
+        sizes[5..].iter().all(|&size| size == sizes[5]),
+        "file kept growing: {sizes:?}"
+    );
+    let reloaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(reloaded.search(&42).unwrap(), "round-9");
+}
@@ -0,0 +1,214 @@
+// src/btree/tests/shared_tests.rs
+//
+// Runs readers, scans and writers on several threads against one persistent
+// tree, then checks what they saw, the tree's invariants and the snapshot.
+use super::super::{Btree, Item, buffer_pool::Lru, error::IndexError, shared::SharedBtree};
+use super::{PAGE_SIZE, item};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+use tempfile::NamedTempFile;
+
+const THREADS: i32 = 8;
+
+fn shared(path: &str) -> SharedBtree {
+    let mut btree: Btree = Btree::new(path, PAGE_SIZE).unwrap();
+    // A small pool makes readers fetch pages while writers evict them.
+    btree
+        .configure_buffer_pool(8, Box::new(Lru::default()))
+        .unwrap();
+    btree.into()
+}
+
+fn keys(tree: &SharedBtree) -> Vec<i32> {
+    tree.range(..).map(|item| item.unwrap().key).collect()
+}
+
+/// A small xorshift generator, seeded per thread.
+fn rng(seed: u64) -> impl FnMut(u64) -> i32 {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    move |bound| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        ((state >> 32) % bound) as i32
+    }
+}
+
+#[test]
+fn test_disjoint_writers_persist() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let tree = shared(path);
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let tree = &tree;
+            scope.spawn(move || {
+                for i in 0..300 {
+                    tree.insert(item(i * THREADS + t)).unwrap();
+                }
+                for i in (0..300).step_by(2) {
+                    tree.delete(&(i * THREADS + t)).unwrap();
+                }
+            });
+        }
+    });
+
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+    let expected: Vec<i32> = (0..300 * THREADS)
+        .filter(|key| (key / THREADS) % 2 == 1)
+        .collect();
+    assert_eq!(keys(&tree), expected);
+
+    tree.snapshot().unwrap();
+    let reloaded: Btree = Btree::load_snapshot(path, PAGE_SIZE).unwrap();
+    let reloaded: Vec<i32> = reloaded.range(..).map(|item| item.unwrap().key).collect();
+    assert_eq!(reloaded, expected);
+}
+
+#[test]
+fn test_readers_and_scans_see_stable_keys() {
+    // Even keys are inserted up front and never touched again; writers churn
+    // the odd keys around them and snapshot now and then, while readers look
+    // the even ones up and scan windows longer than a batch.
+    let temp_file = NamedTempFile::new().unwrap();
+    let tree = shared(temp_file.path().to_str().unwrap());
+    for key in (0..2000).step_by(2) {
+        tree.insert(item(key)).unwrap();
+    }
+
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let writers: Vec<_> = (0..THREADS / 2)
+            .map(|t| {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut next = rng(t as u64 + 1);
+                    for op in 0..1000 {
+                        let key = next(1000) * 2 + 1;
+                        let result = match next(2) {
+                            _ if op % 250 == 249 => tree.snapshot().map(|_| ()),
+                            0 => tree.upsert(item(key)).map(|_| ()),
+                            _ => tree.delete(&key),
+                        };
+                        assert!(matches!(result, Ok(()) | Err(IndexError::NotFound(_))));
+                    }
+                })
+            })
+            .collect();
+
+        for t in 0..THREADS / 2 {
+            let (tree, done) = (&tree, &done);
+            scope.spawn(move || {
+                let mut next = rng(t as u64 + 100);
+                while !done.load(Ordering::Relaxed) {
+                    let key = next(1000) * 2;
+                    assert_eq!(tree.search(&key).unwrap(), item(key).val);
+
+                    let window: Vec<Item> =
+                        tree.range(key..key + 600).map(Result::unwrap).collect();
+                    assert!(window.windows(2).all(|w| w[0].key < w[1].key));
+                    let stable = window.iter().filter(|item| item.key % 2 == 0).count();
+                    assert_eq!(stable, (key..(key + 600).min(2000)).step_by(2).count());
+                }
+            });
+        }
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+    let stable: Vec<i32> = keys(&tree).into_iter().filter(|k| k % 2 == 0).collect();
+    assert_eq!(stable, (0..2000).step_by(2).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scan_lets_writers_in() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let tree = Arc::new(shared(temp_file.path().to_str().unwrap()));
+    for key in 0..1000 {
+        tree.insert(item(key * 2)).unwrap();
+    }
+
+    let mut scan = tree.range(..);
+    assert_eq!(scan.next().unwrap().unwrap().key, 0);
+
+    // The scan is part way through; a writer must not wait for it to end.
+    // Should it wait, the test fails and dropping the scan lets it finish.
+    let (sent, received) = mpsc::channel();
+    thread::spawn({
+        let tree = Arc::clone(&tree);
+        move || {
+            tree.insert(item(1999)).unwrap();
+            sent.send(()).unwrap();
+        }
+    });
+    received
+        .recv_timeout(Duration::from_secs(10))
+        .expect("insert waited for the scan");
+
+    // Batches after the insert see it.
+    let rest: Vec<i32> = scan.map(|item| item.unwrap().key).collect();
+    assert_eq!(rest.len(), 1000);
+    assert!(rest.contains(&1999));
+}
+
+#[test]
+fn test_scan_keeps_a_key_in_one_batch() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new_multimap(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    // The run of key 1 straddles the end of the first batch.
+    for (key, vals) in [(0, 100), (1, 300), (2, 10)] {
+        for val in 0..vals {
+            btree.insert(Item { key, val }).unwrap();
+        }
+    }
+    let tree: SharedBtree<i32, i32> = btree.into();
+
+    let items: Vec<(i32, i32)> = tree
+        .range(..)
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key, item.val)
+        })
+        .collect();
+    let expected: Vec<(i32, i32)> = [(0, 100), (1, 300), (2, 10)]
+        .into_iter()
+        .flat_map(|(key, vals)| (0..vals).map(move |val| (key, val)))
+        .collect();
+    assert_eq!(items, expected);
+}
+
+#[test]
+fn test_read_view_is_fixed_while_threads_write() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let tree = shared(temp_file.path().to_str().unwrap());
+    for key in 0..500 {
+        tree.insert(item(key)).unwrap();
+    }
+    let view = tree.read_view().unwrap();
+
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let tree = &tree;
+            scope.spawn(move || {
+                for key in (0..500).filter(|key| key % THREADS == t) {
+                    tree.delete(&key).unwrap();
+                }
+            });
+        }
+    });
+
+    assert_eq!(tree.count(..).unwrap(), 0);
+    assert_eq!(view.count(..).unwrap(), 500);
+    assert!(tree.check().is_empty(), "{:?}", tree.check());
+}
@@ -8,14 +8,18 @@ fn test_snapshot_creation() {
     let temp_file = NamedTempFile::new().unwrap();
     let mut btree = Btree::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
 
-    btree.insert(Item {
-        key: 1,
-        val: "one".to_string(),
-    });
-    btree.insert(Item {
-        key: 2,
-        val: "two".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 1,
+            val: "one".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 2,
+            val: "two".to_string(),
+        })
+        .unwrap();
 
     let result = btree.snapshot();
     assert!(result.is_ok());
@@ -30,21 +34,25 @@ fn test_snapshot_loading() {
     let path = temp_file.path().to_str().unwrap();
 
     let mut btree = Btree::new(path, 4096).unwrap();
-    btree.insert(Item {
-        key: 10,
-        val: "ten".to_string(),
-    });
-    btree.insert(Item {
-        key: 20,
-        val: "twenty".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 10,
+            val: "ten".to_string(),
+        })
+        .unwrap();
+    btree
+        .insert(Item {
+            key: 20,
+            val: "twenty".to_string(),
+        })
+        .unwrap();
 
     btree.snapshot().unwrap();
 
-    let loaded_btree = Btree::load_snapshot(path, 4096).unwrap();
+    let loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
 
-    let value1 = loaded_btree.search(10).unwrap();
-    let value2 = loaded_btree.search(20).unwrap();
+    let value1 = loaded_btree.search(&10).unwrap();
+    let value2 = loaded_btree.search(&20).unwrap();
 
     assert_eq!(value1, "ten");
     assert_eq!(value2, "twenty");
@@ -55,16 +63,18 @@ fn test_snapshot_validation() {
     let temp_file = NamedTempFile::new().unwrap();
     let path = temp_file.path().to_str().unwrap();
 
-    assert!(!Btree::is_valid_snapshot(path, 4096));
+    assert!(!Btree::<i32, String>::is_valid_snapshot(path, 4096));
 
     let mut btree = Btree::new(path, 4096).unwrap();
-    btree.insert(Item {
-        key: 5,
-        val: "five".to_string(),
-    });
+    btree
+        .insert(Item {
+            key: 5,
+            val: "five".to_string(),
+        })
+        .unwrap();
     btree.snapshot().unwrap();
 
-    assert!(Btree::is_valid_snapshot(path, 4096));
+    assert!(Btree::<i32, String>::is_valid_snapshot(path, 4096));
 }
 
 #[test]
@@ -74,25 +84,236 @@ fn test_snapshot_persistence() {
 
     let mut btree = Btree::new(path, 4096).unwrap();
     for i in 0..10 {
-        btree.insert(Item {
-            key: i,
-            val: format!("value-{i}"),
-        });
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
     }
 
     btree.snapshot().unwrap();
 
-    let loaded_btree = Btree::load_snapshot(path, 4096).unwrap();
+    let loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
 
     for i in 0..10 {
-        match loaded_btree.search(i) {
+        match loaded_btree.search(&i) {
             Ok(value) => println!("Key {i}: Found '{value}'"),
             Err(e) => println!("Key {i}: ERROR - {e}"),
         }
     }
 
     for i in 0..10 {
-        let value = loaded_btree.search(i).unwrap();
+        let value = loaded_btree.search(&i).unwrap();
         assert_eq!(value, format!("value-{i}"));
     }
 }
+
+#[test]
+fn test_snapshot_generic_keys() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::<u64, Vec<u8>>::new(path, 4096).unwrap();
+    for i in 0..20u64 {
+        btree
+            .insert(Item {
+                key: i * 1_000_000_007,
+                val: vec![i as u8; 3],
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let loaded_btree = Btree::<u64, Vec<u8>>::load_snapshot(path, 4096).unwrap();
+    for i in 0..20u64 {
+        assert_eq!(
+            loaded_btree.search(&(i * 1_000_000_007)).unwrap(),
+            vec![i as u8; 3]
+        );
+    }
+}
+
+#[test]
+fn test_snapshot_composite_keys() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::<(Vec<u8>, u32), String>::new(path, 4096).unwrap();
+    for (name, n) in [("b", 2), ("a", 9), ("b", 1), ("a", 1)] {
+        btree
+            .insert(Item {
+                key: (name.as_bytes().to_vec(), n),
+                val: format!("{name}{n}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let loaded_btree = Btree::<(Vec<u8>, u32), String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded_btree.search(&(b"a".to_vec(), 9)).unwrap(), "a9");
+    assert_eq!(loaded_btree.search(&(b"b".to_vec(), 1)).unwrap(), "b1");
+    assert!(loaded_btree.search(&(b"c".to_vec(), 1)).is_err());
+}
+
+#[test]
+fn test_snapshot_preserves_degree() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::with_degree(path, 4096, 5).unwrap();
+    for i in 0..50 {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let mut loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded_btree.degree, 5);
+    let root = loaded_btree.read_node(loaded_btree.root.unwrap()).unwrap();
+    assert!(root.num_items <= 10);
+
+    loaded_btree
+        .insert(Item {
+            key: 50,
+            val: "value-50".to_string(),
+        })
+        .unwrap();
+    for i in 0..=50 {
+        assert_eq!(loaded_btree.search(&i).unwrap(), format!("value-{i}"));
+    }
+}
+
+#[test]
+fn test_snapshot_reload_and_modify() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for i in 0..100 {
+        btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+
+    let mut loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    for i in 0..50 {
+        loaded_btree.delete(&i).unwrap();
+    }
+    for i in 100..150 {
+        loaded_btree
+            .insert(Item {
+                key: i,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+    loaded_btree.snapshot().unwrap();
+
+    let reloaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    for i in 0..150 {
+        match reloaded_btree.search(&i) {
+            Ok(value) => assert_eq!(value, format!("value-{i}")),
+            Err(_) => assert!(i < 50, "key {i} missing after reload"),
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_flushes_only_changed_pages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for i in 0..500 {
+        btree
+            .insert(Item {
+                key: i * 2,
+                val: format!("value-{i}"),
+            })
+            .unwrap();
+    }
+    let full = btree.snapshot().unwrap();
+    assert!(full >= btree.pager.num_pages as usize - 1);
+
+    btree
+        .insert(Item {
+            key: 501,
+            val: "odd".to_string(),
+        })
+        .unwrap();
+    let incremental = btree.snapshot().unwrap();
+    assert!(incremental > 0);
+    assert!(
+        incremental * 10 < full,
+        "{incremental} of {full} pages rewritten"
+    );
+
+    assert_eq!(btree.snapshot().unwrap(), 0);
+
+    let loaded_btree = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    assert_eq!(loaded_btree.search(&501).unwrap(), "odd");
+    assert_eq!(loaded_btree.search(&998).unwrap(), "value-499");
+}
+
+#[test]
+fn test_new_tree_never_overwrites_a_snapshot() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for key in 0..100 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("value-{key}"),
+            })
+            .unwrap();
+    }
+    btree.snapshot().unwrap();
+    drop(btree);
+
+    // Neither a readable snapshot nor one opened with the wrong page size
+    // or with a damaged metadata page may be replaced.
+    let before = fs::read(path).unwrap();
+    assert!(Btree::<i32, String>::new(path, 4096).is_err());
+    assert!(Btree::<i32, String>::new(path, 8192).is_err());
+    let mut damaged = before.clone();
+    damaged[20] ^= 0xff;
+    damaged[512 + 20] ^= 0xff;
+    fs::write(path, &damaged).unwrap();
+    assert!(!Btree::<i32, String>::is_valid_snapshot(path, 4096));
+    assert!(Btree::<i32, String>::new(path, 4096).is_err());
+    assert_eq!(fs::read(path).unwrap(), damaged);
+}
+
+#[test]
+fn test_never_published_file_is_replaced() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for key in 0..100 {
+        btree
+            .insert(Item {
+                key,
+                val: format!("value-{key}"),
+            })
+            .unwrap();
+    }
+    btree.pager.flush().unwrap();
+    drop(btree);
+    assert!(fs::metadata(path).unwrap().len() > 0);
+
+    let btree = Btree::<i32, String>::new(path, 4096).unwrap();
+    assert!(btree.search(&1).is_err());
+    assert_eq!(fs::metadata(path).unwrap().len(), 0);
+}
@@ -0,0 +1,176 @@
+// src/btree/tests/upgrade_tests.rs
+use super::super::{
+    Btree, FORMAT_VERSION, Item,
+    bplus::BPlusTree,
+    error::IndexError,
+    metadata::BtreeMetadata,
+    paging::Pager,
+    upgrade::{Upgrade, upgrade_file},
+};
+use super::{PAGE_SIZE, item};
+use std::fs;
+use tempfile::TempDir;
+
+fn keys(btree: &Btree) -> Vec<i32> {
+    btree.range(..).map(|item| item.unwrap().key).collect()
+}
+
+/// Writes a tree in format `version` holding every third key below 300.
+fn old_btree(path: &str, version: u32) {
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    btree.pager.format_version = version;
+    for key in (0..300).step_by(3) {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+}
+
+#[test]
+fn test_old_btree_is_rewritten_in_the_current_format() {
+    for version in [1, 4] {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("tree.snap");
+        let path = path.to_str().unwrap();
+        old_btree(path, version);
+
+        let backup = match upgrade_file::<i32, String>(path, PAGE_SIZE).unwrap() {
+            Upgrade::Upgraded {
+                from,
+                items,
+                backup,
+            } => {
+                assert_eq!(from, version);
+                assert_eq!(items, 100);
+                backup
+            }
+            other => panic!("expected an upgrade, got {other:?}"),
+        };
+        assert!(!dir.path().join("tree.snap.upgrade").exists());
+
+        let upgraded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+        assert_eq!(upgraded.format_version(), FORMAT_VERSION);
+        assert!(upgraded.check().is_empty());
+        assert_eq!(keys(&upgraded), (0..300).step_by(3).collect::<Vec<_>>());
+        assert_eq!(upgraded.rank(&150).unwrap(), 50);
+
+        let original =
+            Btree::<i32, String>::load_snapshot(backup.to_str().unwrap(), PAGE_SIZE).unwrap();
+        assert_eq!(original.format_version(), version);
+        assert_eq!(keys(&original), keys(&upgraded));
+    }
+}
+
+#[test]
+fn test_current_file_is_left_alone() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tree.snap");
+    let path = path.to_str().unwrap();
+    old_btree(path, FORMAT_VERSION);
+    let before = fs::read(path).unwrap();
+
+    assert_eq!(
+        upgrade_file::<i32, String>(path, PAGE_SIZE).unwrap(),
+        Upgrade::Current
+    );
+    assert_eq!(fs::read(path).unwrap(), before);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_multimap_and_bplus_keep_their_kind() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("multi.snap");
+    let path = path.to_str().unwrap();
+    let mut multimap = Btree::new_multimap(path, PAGE_SIZE).unwrap();
+    multimap.pager.format_version = 4;
+    for key in 0..10 {
+        for val in 0..4 {
+            multimap
+                .insert(Item {
+                    key,
+                    val: format!("value-{val}"),
+                })
+                .unwrap();
+        }
+    }
+    multimap.snapshot().unwrap();
+    drop(multimap);
+
+    upgrade_file::<i32, String>(path, PAGE_SIZE).unwrap();
+    let upgraded = Btree::<i32, String>::load_multimap(path, PAGE_SIZE).unwrap();
+    assert_eq!(upgraded.format_version(), FORMAT_VERSION);
+    assert_eq!(upgraded.search_all(&3).unwrap().len(), 4);
+    assert_eq!(upgraded.count(..).unwrap(), 40);
+
+    let path = dir.path().join("bplus.snap");
+    let path = path.to_str().unwrap();
+    let mut tree: BPlusTree = BPlusTree::new(path, PAGE_SIZE).unwrap();
+    tree.pager.format_version = 4;
+    for key in 0..200 {
+        tree.insert(item(key)).unwrap();
+    }
+    tree.snapshot().unwrap();
+    drop(tree);
+
+    upgrade_file::<i32, String>(path, PAGE_SIZE).unwrap();
+    let upgraded = BPlusTree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert_eq!(upgraded.format_version(), FORMAT_VERSION);
+    assert_eq!(upgraded.range(..).count(), 200);
+    assert_eq!(upgraded.search(&123).unwrap(), "value-123");
+}
+
+#[test]
+fn test_newer_file_is_not_touched() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tree.snap");
+    let path = path.to_str().unwrap();
+    old_btree(path, FORMAT_VERSION);
+    let (mut pager, mut metadata) = Pager::open_snapshot(path, PAGE_SIZE).unwrap();
+    metadata.version = BtreeMetadata::VERSION + 1;
+    metadata.sequence += 1;
+    pager.write_metadata(&metadata).unwrap();
+    drop(pager);
+    let before = fs::read(path).unwrap();
+
+    assert!(matches!(
+        upgrade_file::<i32, String>(path, PAGE_SIZE),
+        Err(IndexError::VersionMismatch { .. })
+    ));
+    assert_eq!(fs::read(path).unwrap(), before);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_newer_slot_with_longer_fields_is_a_version_mismatch() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tree.snap");
+    let path = path.to_str().unwrap();
+    old_btree(path, FORMAT_VERSION);
+    let (_, metadata) = Pager::open_snapshot(path, PAGE_SIZE).unwrap();
+
+    // A future slot with 8 more bytes of fields, all covered by its checksum,
+    // written as the newest snapshot.
+    let mut file = fs::read(path).unwrap();
+    let mut slot = metadata.serialize();
+    slot[4..8].copy_from_slice(&(BtreeMetadata::VERSION + 1).to_le_bytes());
+    slot[44..52].copy_from_slice(&(metadata.sequence + 1).to_le_bytes());
+    slot[56..64].copy_from_slice(&[7; 8]);
+    slot[32..36].fill(0);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&slot[..32]);
+    hasher.update(&slot[36..64]);
+    slot[32..36].copy_from_slice(&hasher.finalize().to_le_bytes());
+    let offset = ((metadata.sequence + 1) % 2) as usize * BtreeMetadata::SLOT_SIZE;
+    file[offset..offset + slot.len()].copy_from_slice(&slot);
+    fs::write(path, &file).unwrap();
+
+    match Btree::<i32, String>::load_snapshot(path, PAGE_SIZE) {
+        Err(IndexError::VersionMismatch { found, supported }) => {
+            assert_eq!(found, BtreeMetadata::VERSION + 1);
+            assert_eq!(supported, BtreeMetadata::VERSION);
+        }
+        other => panic!("expected VersionMismatch, got {other:?}"),
+    }
+    assert!(Btree::<i32, String>::new(path, PAGE_SIZE).is_err());
+    assert_eq!(fs::read(path).unwrap(), file);
+}
@@ -0,0 +1,223 @@
+// src/btree/tests/view_tests.rs
+use super::super::{Btree, Item, buffer_pool::Lru, view::ReadView};
+use super::PAGE_SIZE;
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+use tempfile::NamedTempFile;
+
+fn item(key: i32, version: u32) -> Item {
+    Item {
+        key,
+        val: format!("value-{key}-v{version}"),
+    }
+}
+
+fn btree(path: &str, n: i32) -> Btree {
+    let mut btree = Btree::new(path, PAGE_SIZE).unwrap();
+    // A small pool writes pages back early, so anything overwriting a page
+    // a view still needs reaches the file.
+    btree
+        .configure_buffer_pool(4, Box::new(Lru::default()))
+        .unwrap();
+    for key in 0..n {
+        btree.insert(item((key * 37) % n, 0)).unwrap();
+    }
+    btree
+}
+
+/// Rewrites every value, which touches every leaf.
+fn rewrite(btree: &mut Btree, n: i32, version: u32) {
+    for key in 0..n {
+        btree.upsert(item(key, version)).unwrap();
+    }
+}
+
+fn contents(view: &ReadView) -> Vec<(i32, String)> {
+    view.range(..)
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key, item.val)
+        })
+        .collect()
+}
+
+fn expected(keys: impl Iterator<Item = i32>, version: u32) -> Vec<(i32, String)> {
+    keys.map(|key| (key, item(key, version).val)).collect()
+}
+
+#[test]
+fn test_view_keeps_its_point_in_time() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = btree(temp_file.path().to_str().unwrap(), 300);
+    let view = btree.read_view().unwrap();
+
+    rewrite(&mut btree, 300, 1);
+    for key in (0..300).step_by(2) {
+        btree.delete(&key).unwrap();
+    }
+    for key in 300..400 {
+        btree.insert(item(key, 1)).unwrap();
+    }
+
+    assert_eq!(contents(&view), expected(0..300, 0));
+    assert_eq!(view.search(&10).unwrap(), "value-10-v0");
+    assert!(view.search(&350).is_err());
+    assert_eq!(view.count(100..200).unwrap(), 100);
+
+    assert!(btree.check().is_empty());
+    assert_eq!(btree.search(&11).unwrap(), "value-11-v1");
+    assert!(btree.search(&10).is_err());
+    assert_eq!(btree.count(..).unwrap(), 250);
+}
+
+#[test]
+fn test_scan_runs_alongside_writes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = btree(temp_file.path().to_str().unwrap(), 400);
+    let view = btree.read_view().unwrap();
+
+    // Every step of the scan is followed by writes to the live tree.
+    let mut seen = Vec::new();
+    for (i, found) in view.range(..).enumerate() {
+        let found = found.unwrap();
+        seen.push((found.key, found.val));
+        let i = i as i32;
+        btree.delete(&((i * 7) % 400)).ok();
+        btree.insert(item(1000 + i, 1)).unwrap();
+    }
+
+    assert_eq!(seen, expected(0..400, 0));
+    assert!(btree.check().is_empty());
+    assert_eq!(btree.count(1000..).unwrap(), 400);
+}
+
+#[test]
+fn test_old_versions_are_reclaimed_once_views_close() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = btree(temp_file.path().to_str().unwrap(), 500);
+
+    let view = btree.read_view().unwrap();
+    let before = btree.pager.num_pages;
+    rewrite(&mut btree, 500, 1);
+    let grown = btree.pager.num_pages - before;
+    assert!(grown > 0);
+    // The view's pages are held back from reuse while it is open.
+    assert!(btree.pager.free_pages() as u32 >= grown);
+    assert_eq!(contents(&view), expected(0..500, 0));
+    drop(view);
+
+    // The next view's copies land in the pages the first one held.
+    let view = btree.read_view().unwrap();
+    let before = btree.pager.num_pages;
+    rewrite(&mut btree, 500, 2);
+    assert!(
+        btree.pager.num_pages - before < grown / 4,
+        "grew by {} pages after reclaiming {grown}",
+        btree.pager.num_pages - before
+    );
+    assert_eq!(contents(&view), expected(0..500, 1));
+    assert!(btree.check().is_empty());
+}
+
+#[test]
+fn test_views_of_different_ages() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = btree(temp_file.path().to_str().unwrap(), 300);
+    let first = btree.read_view().unwrap();
+    rewrite(&mut btree, 300, 1);
+    let second = btree.read_view().unwrap();
+    rewrite(&mut btree, 300, 2);
+
+    // Closing the older view frees only what the newer one cannot reach.
+    drop(first);
+    for version in 3..6 {
+        rewrite(&mut btree, 300, version);
+    }
+    assert_eq!(contents(&second), expected(0..300, 1));
+    assert_eq!(btree.search(&7).unwrap(), "value-7-v5");
+}
+
+#[test]
+fn test_view_outlives_snapshots() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let mut btree = btree(path, 300);
+    btree.snapshot().unwrap();
+    let view = btree.read_view().unwrap();
+
+    // Publishing would normally free the pages the previous snapshot used.
+    for version in 1..4 {
+        rewrite(&mut btree, 300, version);
+        btree.snapshot().unwrap();
+    }
+    assert_eq!(contents(&view), expected(0..300, 0));
+    drop(view);
+    drop(btree);
+
+    let loaded = Btree::<i32, String>::load_snapshot(path, PAGE_SIZE).unwrap();
+    assert!(loaded.check().is_empty());
+    assert_eq!(loaded.search(&42).unwrap(), "value-42-v3");
+}
+
+#[test]
+fn test_view_of_empty_tree() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    let view = btree.read_view().unwrap();
+    btree.insert(item(1, 0)).unwrap();
+
+    assert_eq!(view.range(..).count(), 0);
+    assert_eq!(view.count(..).unwrap(), 0);
+    assert!(view.search(&1).is_err());
+}
+
+#[test]
+fn test_view_moves_to_another_thread() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = btree(temp_file.path().to_str().unwrap(), 300);
+    let view = btree.read_view().unwrap();
+
+    // The view is read on its own thread while the tree changes, and dropped
+    // there once the changes are done.
+    let rewritten = Arc::new(Barrier::new(2));
+    let reader = thread::spawn({
+        let rewritten = Arc::clone(&rewritten);
+        move || {
+            let seen = contents(&view);
+            rewritten.wait();
+            seen
+        }
+    });
+    let before = btree.pager.num_pages;
+    rewrite(&mut btree, 300, 1);
+    let grown = btree.pager.num_pages - before;
+    rewritten.wait();
+    assert_eq!(reader.join().unwrap(), expected(0..300, 0));
+
+    // The next view's copies land in the pages the first one held.
+    let view = btree.read_view().unwrap();
+    let before = btree.pager.num_pages;
+    rewrite(&mut btree, 300, 2);
+    assert!(btree.pager.num_pages - before < grown / 4);
+    assert_eq!(contents(&view), expected(0..300, 1));
+    assert!(btree.check().is_empty());
+}
+
+#[test]
+fn test_view_reads_while_the_tree_evicts() {
+    // The view shares the tree's file and pool size, so its scans keep
+    // reading pages on one thread while the tree writes evicted pages back
+    // on the other.
+    for round in 0..8 {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut btree = btree(temp_file.path().to_str().unwrap(), 1000);
+        let view = btree.read_view().unwrap();
+
+        let reader = thread::spawn(move || (0..3).all(|_| contents(&view) == expected(0..1000, 0)));
+        rewrite(&mut btree, 1000, round + 1);
+        assert!(reader.join().unwrap());
+        assert!(btree.check().is_empty());
+    }
+}
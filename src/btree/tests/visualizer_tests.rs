@@ -0,0 +1,30 @@
+// src/btree/tests/visualizer_tests.rs
+use super::super::{Btree, Item, utils::Visualizer};
+use super::PAGE_SIZE;
+use std::fs;
+use tempfile::{NamedTempFile, TempDir};
+
+#[test]
+fn test_long_values_are_cut_by_character() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut btree = Btree::new(temp_file.path().to_str().unwrap(), PAGE_SIZE).unwrap();
+    for (key, val) in [(1, "ééé"), (2, "éééééé"), (3, "short")] {
+        btree
+            .insert(Item {
+                key,
+                val: val.to_string(),
+            })
+            .unwrap();
+    }
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tree.md");
+    Visualizer::new(path.to_str().unwrap())
+        .update(&btree)
+        .unwrap();
+
+    let diagram = fs::read_to_string(path).unwrap();
+    assert!(diagram.contains("1:ééé |"), "{diagram}");
+    assert!(diagram.contains("2:ééééé... |"), "{diagram}");
+    assert!(diagram.contains("3:short"), "{diagram}");
+}
@@ -0,0 +1,254 @@
+// src/btree/tests/wal_tests.rs
+use super::super::{
+    Btree, Item,
+    error::IndexError,
+    wal::{Replay, SyncPolicy, Wal},
+};
+use super::item;
+use std::{fs, io::Write};
+use tempfile::TempDir;
+
+#[test]
+fn test_wal_replays_mutations_after_snapshot() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    assert_eq!(
+        btree
+            .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+            .unwrap()
+            .applied,
+        0
+    );
+    for key in 0..20 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+    for key in 20..40 {
+        btree.insert(item(key)).unwrap();
+    }
+    for key in 0..10 {
+        btree.delete(&key).unwrap();
+    }
+    // Simulate a crash: nothing after the snapshot reaches the snapshot file.
+    drop(btree);
+
+    let mut recovered = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    let replay = recovered
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    assert_eq!(replay.applied, 30);
+    for key in 0..10 {
+        assert!(recovered.search(&key).is_err());
+    }
+    for key in 10..40 {
+        assert_eq!(recovered.search(&key).unwrap(), format!("value-{key}"));
+    }
+}
+
+#[test]
+fn test_wal_replays_upserts_updates_and_conflicts() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    for key in 0..10 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.snapshot().unwrap();
+    btree
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+
+    let changed = |key: i32| Item {
+        key,
+        val: format!("changed-{key}"),
+    };
+    assert!(btree.insert(changed(1)).is_err());
+    btree.upsert(changed(2)).unwrap();
+    btree.upsert(changed(20)).unwrap();
+    btree.update(changed(3)).unwrap();
+    assert!(btree.update(changed(30)).is_err());
+    drop(btree);
+
+    let mut recovered = Btree::<i32, String>::load_snapshot(path, 4096).unwrap();
+    // Only the operations that succeeded were logged.
+    let replay = recovered
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    assert_eq!(replay.applied, 3);
+    assert_eq!(replay.skipped, 0);
+    assert_eq!(recovered.search(&1).unwrap(), "value-1");
+    assert_eq!(recovered.search(&2).unwrap(), "changed-2");
+    assert_eq!(recovered.search(&20).unwrap(), "changed-20");
+    assert_eq!(recovered.search(&3).unwrap(), "changed-3");
+    assert!(recovered.search(&30).is_err());
+}
+
+#[test]
+fn test_wal_replays_without_snapshot() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree
+        .attach_wal(&Wal::path_for(path), SyncPolicy::EveryN(4))
+        .unwrap();
+    for key in 0..10 {
+        btree.insert(item(key)).unwrap();
+    }
+    btree.set_wal_sync_policy(SyncPolicy::Never).unwrap();
+    drop(btree);
+
+    let mut recovered: Btree = Btree::new(path, 4096).unwrap();
+    recovered
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    for key in 0..10 {
+        assert_eq!(recovered.search(&key).unwrap(), format!("value-{key}"));
+    }
+}
+
+#[test]
+fn test_snapshot_empties_wal() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    btree.insert(item(1)).unwrap();
+    assert!(fs::metadata(Wal::path_for(path)).unwrap().len() > 0);
+
+    btree.snapshot().unwrap();
+    assert_eq!(fs::metadata(Wal::path_for(path)).unwrap().len(), 0);
+}
+
+#[test]
+fn test_torn_wal_tail_is_discarded() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+    let wal_path = Wal::path_for(path);
+
+    let mut btree = Btree::new(path, 4096).unwrap();
+    btree.attach_wal(&wal_path, SyncPolicy::Always).unwrap();
+    btree.insert(item(1)).unwrap();
+    btree.insert(item(2)).unwrap();
+    drop(btree);
+
+    let good_len = fs::metadata(&wal_path).unwrap().len();
+    let mut file = fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+    file.write_all(&[42, 0, 0, 0, 1, 2, 3]).unwrap();
+    drop(file);
+
+    let mut recovered: Btree = Btree::new(path, 4096).unwrap();
+    assert_eq!(
+        recovered
+            .attach_wal(&wal_path, SyncPolicy::Always)
+            .unwrap()
+            .applied,
+        2
+    );
+    assert_eq!(fs::metadata(&wal_path).unwrap().len(), good_len);
+    assert_eq!(recovered.search(&2).unwrap(), "value-2");
+}
+
+#[test]
+fn test_sync_policy_requires_wal() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+
+    let mut btree: Btree = Btree::new(path.to_str().unwrap(), 4096).unwrap();
+    assert!(btree.set_wal_sync_policy(SyncPolicy::Always).is_err());
+}
+
+#[test]
+fn test_rejected_operations_are_not_logged() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+
+    let mut btree: Btree<String, String> = Btree::new(path, 4096).unwrap();
+    btree
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    btree
+        .insert(Item {
+            key: "a".to_string(),
+            val: "1".to_string(),
+        })
+        .unwrap();
+    // A key alone too large for a page fails with PageFull.
+    let rejected = btree.insert(Item {
+        key: "k".repeat(5000),
+        val: "2".to_string(),
+    });
+    assert!(matches!(rejected, Err(IndexError::PageFull { .. })));
+    btree
+        .insert(Item {
+            key: "b".to_string(),
+            val: "3".to_string(),
+        })
+        .unwrap();
+    drop(btree);
+
+    let mut recovered: Btree<String, String> = Btree::new(path, 4096).unwrap();
+    let replay = recovered
+        .attach_wal(&Wal::path_for(path), SyncPolicy::Always)
+        .unwrap();
+    assert_eq!(
+        replay,
+        Replay {
+            applied: 2,
+            skipped: 0
+        }
+    );
+    assert_eq!(recovered.search(&"b".to_string()).unwrap(), "3");
+}
+
+#[test]
+fn test_replay_skips_records_the_tree_rejects() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("btree.snap");
+    let path = path.to_str().unwrap();
+    let wal_path = Wal::path_for(path);
+
+    // Earlier builds logged before validating, leaving records like these.
+    let (mut wal, _) = Wal::open::<i32, String>(&wal_path, SyncPolicy::Always).unwrap();
+    wal.log_insert(&item(1)).unwrap();
+    wal.log_insert(&item(1)).unwrap();
+    wal.log_update(&item(7)).unwrap();
+    wal.log_insert(&item(2)).unwrap();
+    drop(wal);
+
+    let mut recovered: Btree = Btree::new(path, 4096).unwrap();
+    let replay = recovered.attach_wal(&wal_path, SyncPolicy::Always).unwrap();
+    assert_eq!(
+        replay,
+        Replay {
+            applied: 2,
+            skipped: 2
+        }
+    );
+    assert_eq!(recovered.search(&2).unwrap(), "value-2");
+
+    // The log stays attached and later records replay after the skipped ones.
+    recovered.insert(item(3)).unwrap();
+    drop(recovered);
+    let mut again: Btree = Btree::new(path, 4096).unwrap();
+    assert_eq!(
+        again
+            .attach_wal(&wal_path, SyncPolicy::Always)
+            .unwrap()
+            .applied,
+        3
+    );
+    assert_eq!(again.search(&3).unwrap(), "value-3");
+}
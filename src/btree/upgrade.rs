@@ -0,0 +1,130 @@
+use std::{fs, path::PathBuf};
+
+use super::{
+    Btree,
+    bplus::BPlusTree,
+    codec::{Codec, KeyCodec},
+    error::{IndexError, Result},
+    metadata::BtreeMetadata,
+    paging::{Page, Pager},
+};
+
+/// What `upgrade_file` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upgrade {
+    /// The file already uses the current format and was left alone.
+    Current,
+    /// The file was rewritten from format version `from`, after the original
+    /// was copied to `backup`.
+    Upgraded {
+        from: u32,
+        items: u64,
+        backup: PathBuf,
+    },
+}
+
+/// Rewrites the snapshot in `filename` in the current format, whichever kind
+/// of tree it holds. The items are copied into a fresh file next to it,
+/// which then replaces the original in one rename; until then the original
+/// is untouched. A copy of the original is kept as `<filename>.v<version>.bak`.
+///
+/// A write-ahead log next to the file is left as it is: its records are
+/// independent of the page format and replay onto the upgraded file.
+pub fn upgrade_file<K: KeyCodec, V: Codec + Ord>(
+    filename: &str,
+    page_size: usize,
+) -> Result<Upgrade> {
+    let (pager, metadata) = Pager::open_snapshot(filename, page_size)?;
+    if metadata.version == BtreeMetadata::VERSION {
+        return Ok(Upgrade::Current);
+    }
+    let bplus = matches!(
+        pager.read_page::<K, V>(metadata.root_page_id.max(1))?,
+        Page::Separators { .. } | Page::LinkedLeaf { .. }
+    );
+    drop(pager);
+
+    let degree = metadata.degree as i32;
+    let staging = format!("{filename}.upgrade");
+    // Left behind by an upgrade that was interrupted before the rename.
+    let _ = fs::remove_file(&staging);
+    let copied = if bplus {
+        copy_bplus::<K, V>(filename, &staging, page_size, degree)
+    } else {
+        let multimap = metadata.flags & BtreeMetadata::MULTIMAP != 0;
+        copy_btree::<K, V>(filename, &staging, page_size, degree, multimap)
+    };
+    let items = match copied {
+        Ok(items) => items,
+        Err(e) => {
+            let _ = fs::remove_file(&staging);
+            return Err(e);
+        }
+    };
+
+    let backup = PathBuf::from(format!("{filename}.v{}.bak", metadata.version));
+    fs::copy(filename, &backup)?;
+    fs::rename(&staging, filename)?;
+    Ok(Upgrade::Upgraded {
+        from: metadata.version,
+        items,
+        backup,
+    })
+}
+
+fn copy_btree<K: KeyCodec, V: Codec + Ord>(
+    from: &str,
+    to: &str,
+    page_size: usize,
+    degree: i32,
+    multimap: bool,
+) -> Result<u64> {
+    let (old, mut new) = if multimap {
+        let mut new = Btree::<K, V>::with_degree(to, page_size, degree)?;
+        new.values = Some(V::cmp);
+        new.pager.flags |= BtreeMetadata::MULTIMAP;
+        (Btree::load_multimap(from, page_size)?, new)
+    } else {
+        (
+            Btree::load_snapshot(from, page_size)?,
+            Btree::with_degree(to, page_size, degree)?,
+        )
+    };
+
+    let mut items = 0;
+    for item in old.range(..) {
+        new.insert(item?)?;
+        items += 1;
+    }
+    if items == 0 {
+        return Err(nothing_to_upgrade());
+    }
+    new.snapshot()?;
+    Ok(items)
+}
+
+fn copy_bplus<K: KeyCodec, V: Codec>(
+    from: &str,
+    to: &str,
+    page_size: usize,
+    degree: i32,
+) -> Result<u64> {
+    let old = BPlusTree::<K, V>::load_snapshot(from, page_size)?;
+    let mut new = BPlusTree::with_degree(to, page_size, degree)?;
+
+    let mut items = 0;
+    for item in old.range(..) {
+        new.insert(item?)?;
+        items += 1;
+    }
+    if items == 0 {
+        return Err(nothing_to_upgrade());
+    }
+    new.snapshot()?;
+    Ok(items)
+}
+
+// A new tree has no root until its first insert and cannot be snapshotted.
+fn nothing_to_upgrade() -> IndexError {
+    IndexError::InvalidInput("Snapshot holds no items; there is nothing to upgrade".to_string())
+}
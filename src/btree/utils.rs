@@ -1,8 +1,13 @@
+use std::fmt::Display;
 use std::fs::{self, write};
 use std::io;
 use std::path::Path;
 
-use super::{Btree, Node};
+use super::{
+    Btree,
+    codec::{Codec, KeyCodec},
+    paging::PageID,
+};
 
 pub struct Visualizer {
     output_path: String,
@@ -10,19 +15,23 @@ pub struct Visualizer {
 
 impl Visualizer {
     pub fn new(path: &str) -> Self {
-        if let Some(parent) = Path::new(path).parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent).expect("Failed to create visualization directory");
-            }
-        }
-
         Visualizer {
             output_path: path.to_string(),
         }
     }
 
-    pub fn update(&self, btree: &Btree) -> io::Result<()> {
-        let mermaid = self.generate_mermaid(btree);
+    pub fn update<K, V>(&self, btree: &Btree<K, V>) -> io::Result<()>
+    where
+        K: KeyCodec + Display,
+        V: Codec + Display,
+    {
+        if let Some(parent) = Path::new(&self.output_path).parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mermaid = self.generate_mermaid(btree)?;
         let content = format!(
             "# B-tree Visualization\n\n\
             Current state of the B-tree:\n\n\
@@ -34,29 +43,45 @@ impl Visualizer {
         write(&self.output_path, content)
     }
 
-    fn generate_mermaid(&self, btree: &Btree) -> String {
-        match &btree.root {
-            None => String::from("empty[Empty Tree]"),
+    fn generate_mermaid<K, V>(&self, btree: &Btree<K, V>) -> io::Result<String>
+    where
+        K: KeyCodec + Display,
+        V: Codec + Display,
+    {
+        match btree.root {
+            None => Ok(String::from("empty[Empty Tree]")),
             Some(root) => {
                 let mut nodes = Vec::new();
                 let mut edges = Vec::new();
-                self.generate_node_diagram(&mut nodes, &mut edges, root, None);
-                format!("{}\n{}", nodes.join("\n"), edges.join("\n"))
+                self.generate_node_diagram(btree, &mut nodes, &mut edges, root, None)?;
+                Ok(format!("{}\n{}", nodes.join("\n"), edges.join("\n")))
             }
         }
     }
 
-    fn generate_node_diagram(
+    fn generate_node_diagram<K, V>(
         &self,
+        btree: &Btree<K, V>,
         nodes: &mut Vec<String>,
         edges: &mut Vec<String>,
-        node: &Node,
+        id: PageID,
         parent_id: Option<u32>,
-    ) {
+    ) -> io::Result<()>
+    where
+        K: KeyCodec + Display,
+        V: Codec + Display,
+    {
+        let node = btree.read_node(id)?;
         let items: Vec<String> = node
             .items
             .iter()
-            .map(|item| format!("{}:{}", item.key, self.truncate_value(&item.val, 5)))
+            .map(|item| {
+                format!(
+                    "{}:{}",
+                    item.key,
+                    self.truncate_value(&item.val.to_string(), 5)
+                )
+            })
             .collect();
 
         nodes.push(format!(
@@ -70,16 +95,20 @@ impl Visualizer {
             edges.push(format!("    n{} --> n{}", parent, node.id));
         }
 
-        for child in &node.children {
-            self.generate_node_diagram(nodes, edges, child, Some(node.id));
+        for &child in &node.children {
+            self.generate_node_diagram(btree, nodes, edges, child, Some(node.id))?;
         }
+        Ok(())
     }
 
+    /// Cuts `value` to its first `max_len` characters.
     fn truncate_value(&self, value: &str, max_len: usize) -> String {
-        if value.len() <= max_len {
-            value.to_string()
+        let mut chars = value.chars();
+        let head: String = chars.by_ref().take(max_len).collect();
+        if chars.next().is_none() {
+            head
         } else {
-            format!("{}...", &value[..max_len])
+            format!("{head}...")
         }
     }
 }
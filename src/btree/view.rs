@@ -0,0 +1,61 @@
+use std::{marker::PhantomData, ops::RangeBounds};
+
+use super::{
+    Btree,
+    codec::{Codec, KeyCodec},
+    error::Result,
+    paging::ViewPin,
+    range::Range,
+};
+
+/// A consistent, point-in-time view of a `Btree`, opened with
+/// `Btree::read_view`. It answers from the tree as it was when opened while
+/// the tree goes on taking inserts and deletes. A view can be handed to
+/// another thread, read there and dropped there.
+///
+/// Writes never modify a page in place once a view may be reading it: like
+/// pages of a published snapshot, such pages are copied on their next write
+/// and the old version is kept until every view that can reach it has been
+/// dropped.
+#[derive(Debug)]
+pub struct ReadView<K = i32, V = String> {
+    tree: Btree<K, V>,
+    _pin: ViewPin,
+}
+
+impl<K: KeyCodec, V: Codec> Btree<K, V> {
+    /// Opens a read view of the tree as it is now. Opening one flushes the
+    /// buffer pool, since the view reads pages back through its own cache of
+    /// the same capacity.
+    pub fn read_view(&mut self) -> Result<ReadView<K, V>> {
+        let (pager, pin) = self.pager.open_view()?;
+        Ok(ReadView {
+            tree: Btree {
+                pager,
+                root: self.root,
+                degree: self.degree,
+                wal: None,
+                values: self.values,
+                marker: PhantomData,
+            },
+            _pin: pin,
+        })
+    }
+}
+
+impl<K: KeyCodec, V: Codec> ReadView<K, V> {
+    /// See `Btree::search`.
+    pub fn search(&self, key: &K) -> Result<V> {
+        self.tree.search(key)
+    }
+
+    /// See `Btree::range`.
+    pub fn range<R: RangeBounds<K>>(&self, bounds: R) -> Range<'_, K, V> {
+        self.tree.range(bounds)
+    }
+
+    /// See `Btree::count`.
+    pub fn count<R: RangeBounds<K>>(&self, bounds: R) -> Result<u64> {
+        self.tree.count(bounds)
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    Item,
+    codec::{Codec, KeyCodec},
+};
+
+const INSERT_RECORD: u8 = 1;
+const DELETE_RECORD: u8 = 2;
+const UPSERT_RECORD: u8 = 3;
+const UPDATE_RECORD: u8 = 4;
+const DELETE_PAIR_RECORD: u8 = 5;
+/// Every record starts with the payload length and a CRC32 of the payload.
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// When appended records are forced to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// fsync after every record.
+    Always,
+    /// fsync once every `n` records.
+    EveryN(u32),
+    /// Leave it to the OS; the log is still synced when a snapshot is taken.
+    Never,
+}
+
+#[derive(Debug)]
+pub enum WalRecord<K, V> {
+    Insert(Item<K, V>),
+    Upsert(Item<K, V>),
+    Update(Item<K, V>),
+    Delete(K),
+    /// One key-value pair of a tree with duplicate keys.
+    DeletePair(Item<K, V>),
+}
+
+/// What replaying a log with `Btree::attach_wal` did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Replay {
+    /// Records applied to the tree.
+    pub applied: usize,
+    /// Records the tree rejected, which were left out.
+    pub skipped: usize,
+}
+
+/// Append-only log of the mutations made since the last snapshot. It lives
+/// next to the snapshot file and is emptied once a snapshot is durable.
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+    policy: SyncPolicy,
+    unsynced: u32,
+}
+
+impl Wal {
+    /// The log that belongs to the snapshot at `snapshot`.
+    pub fn path_for(snapshot: &str) -> PathBuf {
+        let mut path = snapshot.to_string();
+        path.push_str(".wal");
+        PathBuf::from(path)
+    }
+
+    /// Opens (or creates) the log and returns it together with the records
+    /// it holds. A torn record at the tail, left by a crash in the middle of
+    /// an append, is dropped and the file truncated to the last whole record.
+    pub fn open<K: KeyCodec, V: Codec>(
+        path: &Path,
+        policy: SyncPolicy,
+    ) -> Result<(Self, Vec<WalRecord<K, V>>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while let Some(payload) = next_payload(&bytes[offset..]) {
+            match decode_record(payload) {
+                Ok(record) => records.push(record),
+                Err(_) => break,
+            }
+            offset += RECORD_HEADER_SIZE + payload.len();
+        }
+
+        if offset < bytes.len() {
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(offset as u64))?;
+
+        let wal = Wal {
+            file,
+            policy,
+            unsynced: 0,
+        };
+        Ok((wal, records))
+    }
+
+    pub fn set_policy(&mut self, policy: SyncPolicy) -> Result<()> {
+        self.policy = policy;
+        self.sync()
+    }
+
+    pub fn log_insert<K: Codec, V: Codec>(&mut self, item: &Item<K, V>) -> Result<()> {
+        self.log_item(INSERT_RECORD, item)
+    }
+
+    pub fn log_upsert<K: Codec, V: Codec>(&mut self, item: &Item<K, V>) -> Result<()> {
+        self.log_item(UPSERT_RECORD, item)
+    }
+
+    pub fn log_update<K: Codec, V: Codec>(&mut self, item: &Item<K, V>) -> Result<()> {
+        self.log_item(UPDATE_RECORD, item)
+    }
+
+    pub fn log_delete_pair<K: Codec, V: Codec>(&mut self, key: &K, val: &V) -> Result<()> {
+        let mut payload = vec![DELETE_PAIR_RECORD];
+        key.encode(&mut payload);
+        val.encode(&mut payload);
+        self.append(&payload)
+    }
+
+    fn log_item<K: Codec, V: Codec>(&mut self, kind: u8, item: &Item<K, V>) -> Result<()> {
+        let mut payload = vec![kind];
+        item.key.encode(&mut payload);
+        item.val.encode(&mut payload);
+        self.append(&payload)
+    }
+
+    pub fn log_delete<K: Codec>(&mut self, key: &K) -> Result<()> {
+        let mut payload = vec![DELETE_RECORD];
+        key.encode(&mut payload);
+        self.append(&payload)
+    }
+
+    /// Drops every record; called once a snapshot covering them is on disk.
+    pub fn truncate(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.unsynced = 0;
+        self.file.sync_all()
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        record.extend_from_slice(payload);
+        self.file.write_all(&record)?;
+        self.unsynced += 1;
+
+        match self.policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::EveryN(n) if self.unsynced >= n => self.sync(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the payload of the record at the front of `bytes`, or `None` if
+/// the record is incomplete or fails its checksum.
+fn next_payload(bytes: &[u8]) -> Option<&[u8]> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let checksum = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+    let payload = bytes.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + len)?;
+    (crc32fast::hash(payload) == checksum).then_some(payload)
+}
+
+fn decode_record<K: KeyCodec, V: Codec>(payload: &[u8]) -> Result<WalRecord<K, V>> {
+    let (&kind, rest) = payload
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty WAL record"))?;
+    let (key, used) = K::decode(rest)?;
+    match kind {
+        INSERT_RECORD | UPSERT_RECORD | UPDATE_RECORD | DELETE_PAIR_RECORD => {
+            let (val, _) = V::decode(&rest[used..])?;
+            let item = Item { key, val };
+            Ok(match kind {
+                INSERT_RECORD => WalRecord::Insert(item),
+                UPSERT_RECORD => WalRecord::Upsert(item),
+                UPDATE_RECORD => WalRecord::Update(item),
+                _ => WalRecord::DeletePair(item),
+            })
+        }
+        DELETE_RECORD => Ok(WalRecord::Delete(key)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown WAL record type {kind}"),
+        )),
+    }
+}
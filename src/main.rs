@@ -1,44 +1,379 @@
-use std::{fs, path::PathBuf};
+use std::{env, fs, path::PathBuf, process, thread, time::Instant};
 
-use btree::Btree;
+use btree::{
+    Btree, DEGREE, FORMAT_VERSION, Item,
+    bplus::BPlusTree,
+    error::IndexError,
+    latching::InMemoryLatchedBtree,
+    shared::SharedBtree,
+    upgrade::{Upgrade, upgrade_file},
+    view::ReadView,
+    wal::{SyncPolicy, Wal},
+};
 use input_handler::InputHandler;
 use parsing::parse_command;
 mod btree;
 mod parsing;
 pub struct IndexSession {
     btree: Btree,
+    bplus: BPlusTree,
+    multimap: Btree,
+    /// Read view opened with `btree view`, if any.
+    view: Option<ReadView>,
 }
 impl IndexSession {
-    fn new() -> Self {
+    fn new() -> Result<Self, IndexError> {
         let filename = "data/btree.snap";
         let page_size = 4096;
 
-        let btree =
-            if PathBuf::from(filename).exists() && Btree::is_valid_snapshot(filename, page_size) {
-                match Btree::load_snapshot(filename, page_size) {
-                    Ok(bt) => bt,
-                    Err(e) => {
-                        eprintln!("Failed to load snapshot: {e}. Creating new B-tree.");
-                        Btree::new(filename, page_size).expect("Failed to create Btree")
+        let mut btree: Btree = open_or_create(
+            filename,
+            Btree::<i32, String>::is_valid_snapshot(filename, page_size),
+            || Btree::load_snapshot(filename, page_size),
+            || Btree::new(filename, page_size),
+        )?;
+
+        // Running on without the log would lose every change from here on.
+        let replay = match btree.attach_wal(&Wal::path_for(filename), SyncPolicy::Always) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("Failed to replay write-ahead log {filename}.wal");
+                return Err(e);
+            }
+        };
+        if replay.applied > 0 {
+            println!(
+                "Replayed {} record(s) from the write-ahead log",
+                replay.applied
+            );
+        }
+        if replay.skipped > 0 {
+            println!(
+                "Skipped {} write-ahead log record(s) the tree rejected",
+                replay.skipped
+            );
+        }
+
+        let filename = "data/bplus.snap";
+        let bplus: BPlusTree = open_or_create(
+            filename,
+            BPlusTree::<i32, String>::is_valid_snapshot(filename, page_size),
+            || BPlusTree::load_snapshot(filename, page_size),
+            || BPlusTree::new(filename, page_size),
+        )?;
+
+        let filename = "data/multimap.snap";
+        let multimap: Btree = open_or_create(
+            filename,
+            Btree::<i32, String>::is_valid_snapshot(filename, page_size),
+            || Btree::load_multimap(filename, page_size),
+            || Btree::new_multimap(filename, page_size),
+        )?;
+
+        warn_if_outdated("data/btree.snap", btree.format_version());
+        warn_if_outdated("data/bplus.snap", bplus.format_version());
+        warn_if_outdated("data/multimap.snap", multimap.format_version());
+
+        Ok(IndexSession {
+            btree,
+            bplus,
+            multimap,
+            view: None,
+        })
+    }
+}
+
+/// Loads the snapshot in `filename` when `valid`, or starts a new tree.
+/// Load errors are returned rather than answered with a new tree, and
+/// `Pager::create` refuses a file that holds published metadata, so a damaged
+/// or newer snapshot is never overwritten.
+fn open_or_create<T>(
+    filename: &str,
+    valid: bool,
+    load: impl FnOnce() -> Result<T, IndexError>,
+    create: impl FnOnce() -> Result<T, IndexError>,
+) -> Result<T, IndexError> {
+    if valid {
+        return load();
+    }
+    match create() {
+        Ok(tree) => Ok(tree),
+        Err(refused) => {
+            eprintln!("{filename} is not a readable snapshot; move it aside to start a new tree");
+            // Loading says why, e.g. that the file comes from a newer build.
+            Err(load().err().unwrap_or(refused))
+        }
+    }
+}
+
+fn warn_if_outdated(filename: &str, version: u32) {
+    if version < FORMAT_VERSION {
+        // Files before version 3 cannot record freed pages and refuse writes.
+        let read_only = if version < 3 {
+            " (it is read-only until then)"
+        } else {
+            ""
+        };
+        println!(
+            "{filename} uses snapshot format version {version}; run `indexium upgrade {filename}` to move it to version {FORMAT_VERSION}{read_only}"
+        );
+    }
+}
+
+/// `indexium upgrade <file>` rewrites a snapshot in the current format.
+fn upgrade(filename: Option<&String>) {
+    let Some(filename) = filename else {
+        eprintln!("Usage: indexium upgrade <file>");
+        process::exit(2);
+    };
+
+    match upgrade_file::<i32, String>(filename, 4096) {
+        Ok(Upgrade::Current) => {
+            println!("{filename} already uses snapshot format version {FORMAT_VERSION}")
+        }
+        Ok(Upgrade::Upgraded {
+            from,
+            items,
+            backup,
+        }) => println!(
+            "Upgraded {filename} from format version {from} to {FORMAT_VERSION}, {items} item(s) copied; the original is kept in {}",
+            backup.display()
+        ),
+        Err(e) => {
+            eprintln!("Failed to upgrade {filename}: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// `indexium latch-experiment [threads] [ops] [degree]` runs a mixed workload
+/// against the in-memory `InMemoryLatchedBtree` from several threads and
+/// checks the tree afterwards. No snapshot file is involved.
+fn latch_experiment(args: &[String]) {
+    let (Ok(threads), Ok(ops), Ok(degree)) = (
+        args.first().map_or(Ok(8), |t| t.parse::<u64>()),
+        args.get(1).map_or(Ok(100_000), |o| o.parse::<u64>()),
+        args.get(2).map_or(Ok(DEGREE), |d| d.parse::<i32>()),
+    ) else {
+        eprintln!("Usage: indexium latch-experiment [threads] [ops per thread] [degree]");
+        process::exit(2);
+    };
+
+    let tree: InMemoryLatchedBtree = match InMemoryLatchedBtree::with_degree(degree) {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(2);
+        }
+    };
+    let started = Instant::now();
+    let found: u64 = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut state = t + 1;
+                    let mut found = 0;
+                    for _ in 0..ops {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        let key = ((state >> 32) % 10_000) as i32;
+                        match state % 10 {
+                            0..=5 => found += u64::from(tree.search(&key).is_ok()),
+                            6..=8 => {
+                                let val = format!("value-{key}");
+                                let _ = tree.insert(Item { key, val });
+                            }
+                            _ => {
+                                let _ = tree.delete(&key);
+                            }
+                        }
+                    }
+                    found
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap_or(0)).sum()
+    });
+    let elapsed = started.elapsed();
+
+    let total = threads * ops;
+    println!(
+        "{total} operation(s) on {threads} thread(s) in {elapsed:.2?} ({:.0} ops/s); {found} search hit(s), {} item(s) left",
+        total as f64 / elapsed.as_secs_f64(),
+        tree.range(..).len()
+    );
+    let problems = tree.check();
+    if !problems.is_empty() {
+        for problem in problems {
+            eprintln!("{problem}");
+        }
+        process::exit(1);
+    }
+}
+
+/// `indexium stress [threads] [ops]` runs a mixed workload of lookups,
+/// scans, writes and snapshots against one persistent tree shared by
+/// several threads, then checks the tree and reloads the last snapshot.
+fn stress(args: &[String]) {
+    let (Ok(threads), Ok(ops)) = (
+        args.first().map_or(Ok(8), |t| t.parse::<u64>()),
+        args.get(1).map_or(Ok(20_000), |o| o.parse::<u64>()),
+    ) else {
+        eprintln!("Usage: indexium stress [threads] [ops per thread]");
+        process::exit(2);
+    };
+
+    let path = env::temp_dir().join(format!("indexium-stress-{}.snap", process::id()));
+    let filename = path.to_string_lossy().into_owned();
+    let page_size = 4096;
+    let tree: SharedBtree = match Btree::new(&filename, page_size) {
+        Ok(tree) => tree.into(),
+        Err(e) => {
+            eprintln!("Failed to create {filename}: {e}");
+            process::exit(1);
+        }
+    };
+    for key in (0..10_000).step_by(2) {
+        let val = format!("value-{key}");
+        if let Err(e) = tree.insert(Item { key, val }) {
+            eprintln!("Failed to fill the tree: {e}");
+            process::exit(1);
+        }
+    }
+
+    let view = match tree.read_view() {
+        Ok(view) => view,
+        Err(e) => {
+            eprintln!("Failed to open a read view: {e}");
+            process::exit(1);
+        }
+    };
+
+    // Even keys stay put while the threads churn the odd ones, so every
+    // lookup and scan knows what it must find.
+    let started = Instant::now();
+    let failures: u64 = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut state = t + 1;
+                    let mut failures = 0;
+                    for op in 0..ops {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        let key = ((state >> 32) % 10_000) as i32;
+                        let val = format!("value-{key}");
+                        let ok = match state % 10 {
+                            _ if t == 0 && op % 1000 == 999 => tree.snapshot().is_ok(),
+                            0..=4 if key % 2 == 0 => tree.search(&key).is_ok_and(|v| v == val),
+                            0..=4 => !matches!(
+                                tree.search(&key),
+                                Err(e) if !matches!(e, IndexError::NotFound(_))
+                            ),
+                            5 => {
+                                let end = key.saturating_add(200);
+                                let stable = tree
+                                    .range(key..end)
+                                    .filter_map(Result::ok)
+                                    .filter(|item| item.key % 2 == 0)
+                                    .count();
+                                stable == (key..end.min(10_000)).filter(|k| k % 2 == 0).count()
+                            }
+                            _ if key % 2 == 0 => true,
+                            6..=7 => tree.upsert(Item { key, val }).is_ok(),
+                            _ => !matches!(
+                                tree.delete(&key),
+                                Err(e) if !matches!(e, IndexError::NotFound(_))
+                            ),
+                        };
+                        failures += u64::from(!ok);
                     }
-                }
-            } else {
-                Btree::new(filename, page_size).expect("Failed to create Btree")
-            };
-        IndexSession { btree }
+                    failures
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap_or(1)).sum()
+    });
+    let elapsed = started.elapsed();
+
+    let total = threads * ops;
+    println!(
+        "{total} operation(s) on {threads} thread(s) in {elapsed:.2?} ({:.0} ops/s); {} item(s) left",
+        total as f64 / elapsed.as_secs_f64(),
+        tree.count(..).unwrap_or(0)
+    );
+    let problems = tree.check();
+    let snapshot = tree.snapshot().and_then(|_| {
+        let reloaded: Btree = Btree::load_snapshot(&filename, page_size)?;
+        Ok(reloaded.count(..)? == tree.count(..)?)
+    });
+    let _ = fs::remove_file(&path);
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+    if failures > 0 {
+        eprintln!("{failures} operation(s) failed or saw the wrong result");
+    }
+    let view_intact = view.count(..).is_ok_and(|count| count == 5000);
+    if !view_intact {
+        eprintln!("The read view opened before the workload changed");
+    }
+    match &snapshot {
+        Ok(true) => {}
+        Ok(false) => eprintln!("The reloaded snapshot holds a different number of items"),
+        Err(e) => eprintln!("Failed to snapshot and reload the tree: {e}"),
+    }
+    if !problems.is_empty() || failures > 0 || !view_intact || !matches!(snapshot, Ok(true)) {
+        process::exit(1);
     }
 }
+
 fn main() {
-    let mut index_session = IndexSession::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("upgrade") => {
+            upgrade(args.get(1));
+            return;
+        }
+        Some("stress") => {
+            stress(&args[1..]);
+            return;
+        }
+        Some("latch-experiment") => {
+            latch_experiment(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
 
     let data_dir = PathBuf::from("data");
-    if !data_dir.exists() {
-        fs::create_dir(&data_dir).expect("Failed to create data directory");
+    if !data_dir.exists()
+        && let Err(e) = fs::create_dir(&data_dir)
+    {
+        eprintln!("Failed to create data directory: {e}");
+        process::exit(1);
     }
-    let history_file = data_dir.join("history.txt");
 
-    let mut input_handler =
-        InputHandler::with_history_file(history_file).expect("Failed to initialize input handler");
+    let mut index_session = match IndexSession::new() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Failed to open indexes: {e}");
+            process::exit(1);
+        }
+    };
+
+    let history_file = data_dir.join("history.txt");
+    let mut input_handler = match InputHandler::with_history_file(history_file) {
+        Ok(handler) => handler,
+        Err(e) => {
+            eprintln!("Failed to initialize input handler: {e}");
+            process::exit(1);
+        }
+    };
 
     while let Ok(line) = input_handler.readline("indexium> ") {
         if line.eq_ignore_ascii_case("exit") {
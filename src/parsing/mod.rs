@@ -1,14 +1,28 @@
 use crate::{
     IndexSession,
-    btree::{Item, utils::Visualizer},
+    btree::{
+        Btree, Item,
+        buffer_pool::{Clock, EvictionPolicy, Lru},
+        dump::DumpFormat,
+        error::IndexError,
+        events::{Logger, Observer, Verbosity},
+        utils::Visualizer,
+        wal::SyncPolicy,
+    },
+};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    ops::Bound,
+    path::PathBuf,
 };
-use std::path::PathBuf;
 
 struct Command<KeyType, ValType> {
     index_type: String,
     index_function: String,
     key: Option<KeyType>,
     value: Option<ValType>,
+    args: Vec<String>,
 }
 
 impl<KeyType, ValType> Command<KeyType, ValType>
@@ -39,15 +53,114 @@ where
             None
         };
 
+        let args = tokens[2..].iter().map(|t| t.to_string()).collect();
+
         Some(Command {
             index_type,
             index_function,
             key,
             value,
+            args,
         })
     }
 }
 
+/// Parses an inclusive range bound, where `*` leaves that end open.
+fn parse_bound<T: std::str::FromStr>(token: Option<&String>) -> Option<Bound<T>> {
+    match token.map(|t| t.as_str()) {
+        None | Some("*") => Some(Bound::Unbounded),
+        Some(t) => t.parse::<T>().ok().map(Bound::Included),
+    }
+}
+
+/// Parses a `log` level: `off` detaches the observer, `structure` and
+/// `detail` attach a `Logger` at that verbosity.
+fn parse_observer(token: Option<&String>) -> Option<Option<Box<dyn Observer>>> {
+    let verbosity = match token.map(|t| t.to_ascii_lowercase()).as_deref() {
+        Some("off") => return Some(None),
+        Some("structure") => Verbosity::Structure,
+        Some("detail") => Verbosity::Detail,
+        _ => return None,
+    };
+    Some(Some(Box::new(Logger::new(verbosity))))
+}
+
+/// `btree view` opens a read view of the tree, replacing any open one;
+/// `btree view search|range|count ..` query it and `btree view close` drops it.
+fn run_view(index_session: &mut IndexSession, args: &[String]) {
+    let subcommand = args.first().map(|a| a.to_ascii_lowercase());
+    if subcommand.is_none() {
+        index_session.view = None;
+        match index_session.btree.read_view() {
+            Ok(view) => {
+                match view.count(..) {
+                    Ok(count) => println!("Opened a read view of {count} item(s)"),
+                    Err(e) => eprintln!("Opened a read view, but failed to count it: {e}"),
+                }
+                index_session.view = Some(view);
+            }
+            Err(e) => eprintln!("Failed to open a read view: {e}"),
+        }
+        return;
+    }
+    if subcommand.as_deref() == Some("close") {
+        match index_session.view.take() {
+            Some(_) => println!("Closed the read view"),
+            None => println!("No read view is open"),
+        }
+        return;
+    }
+
+    let Some(view) = &index_session.view else {
+        eprintln!("Error: No read view is open (open one with `btree view`)");
+        return;
+    };
+    match subcommand.as_deref() {
+        Some("search") => {
+            let Some(key) = args.get(1).and_then(|k| k.parse::<i32>().ok()) else {
+                eprintln!("Error: Missing key for VIEW SEARCH (usage: btree view search <key>)");
+                return;
+            };
+            match view.search(&key) {
+                Ok(val) => println!("Value {val}"),
+                Err(IndexError::NotFound(_)) => println!("Key not found"),
+                Err(e) => eprintln!("Failed to search key {key}: {e}"),
+            }
+        }
+        Some("range") | Some("count") => {
+            let (Some(lo), Some(hi)) = (
+                parse_bound::<i32>(args.get(1)),
+                parse_bound::<i32>(args.get(2)),
+            ) else {
+                eprintln!("Error: Invalid bounds (usage: btree view range|count <lo|*> <hi|*>)");
+                return;
+            };
+            if subcommand.as_deref() == Some("count") {
+                match view.count((lo, hi)) {
+                    Ok(count) => println!("{count} item(s) in range"),
+                    Err(e) => eprintln!("Failed to count range: {e}"),
+                }
+                return;
+            }
+
+            let mut count = 0;
+            for item in view.range((lo, hi)) {
+                match item {
+                    Ok(item) => println!("{item}"),
+                    Err(e) => {
+                        eprintln!("Failed to scan range: {e}");
+                        return;
+                    }
+                }
+                count += 1;
+            }
+            println!("{count} item(s) in range");
+        }
+        Some(other) => eprintln!("Error: Unknown VIEW command {other}"),
+        None => {}
+    }
+}
+
 pub fn parse_command(index_session: &mut IndexSession, command: &str) {
     let trimmed_command = command.trim();
     if trimmed_command.is_empty() {
@@ -60,7 +173,7 @@ pub fn parse_command(index_session: &mut IndexSession, command: &str) {
     viz_path.push("btree");
     viz_path.push("tests");
     viz_path.push("visualizer.md");
-    let visualizer = Visualizer::new(viz_path.to_str().unwrap());
+    let visualizer = Visualizer::new(&viz_path.to_string_lossy());
 
     let cmd = match Command::<i32, String>::new(trimmed_command) {
         Some(cmd) => cmd,
@@ -80,15 +193,47 @@ pub fn parse_command(index_session: &mut IndexSession, command: &str) {
                 let val = match cmd.value.clone() {
                     Some(v) => v,
                     None => {
-                        eprintln!(
-                            "Error: Missing value for INSERT (key = {})",
-                            cmd.key.unwrap()
-                        );
+                        eprintln!("Error: Missing value for INSERT (key = {key})");
                         return;
                     }
                 };
 
-                index_session.btree.insert(Item { key, val });
+                if let Err(e) = index_session.btree.insert(Item { key, val }) {
+                    println!("Failed to insert key {key}: {e}");
+                    return;
+                }
+
+                if let Err(e) = visualizer.update(&index_session.btree) {
+                    eprintln!("Failed to update visualization: {e}");
+                }
+            }
+            "UPSERT" | "upsert" | "UPDATE" | "update" => {
+                let upsert = cmd.index_function.eq_ignore_ascii_case("upsert");
+                let (Some(key), Some(val)) = (cmd.key, cmd.value.clone()) else {
+                    eprintln!(
+                        "Error: Missing key or value for {} (usage: btree {} <key> <value>)",
+                        cmd.index_function.to_ascii_uppercase(),
+                        cmd.index_function.to_ascii_lowercase()
+                    );
+                    return;
+                };
+
+                let result = if upsert {
+                    index_session.btree.upsert(Item { key, val })
+                } else {
+                    index_session.btree.update(Item { key, val }).map(Some)
+                };
+                match result {
+                    Ok(Some(old)) => println!("Replaced value {old} of key {key}"),
+                    Ok(None) => println!("Inserted key {key}"),
+                    Err(e) => {
+                        println!(
+                            "Failed to {} key {key}: {e}",
+                            cmd.index_function.to_ascii_lowercase()
+                        );
+                        return;
+                    }
+                }
 
                 if let Err(e) = visualizer.update(&index_session.btree) {
                     eprintln!("Failed to update visualization: {e}");
@@ -103,9 +248,10 @@ pub fn parse_command(index_session: &mut IndexSession, command: &str) {
                     }
                 };
 
-                match index_session.btree.search(key) {
+                match index_session.btree.search(&key) {
                     Ok(val) => println!("Value {val}"),
-                    Err(_) => println!("Key not found"),
+                    Err(IndexError::NotFound(_)) => println!("Key not found"),
+                    Err(e) => eprintln!("Failed to search key {key}: {e}"),
                 }
             }
             "DELETE" | "delete" => {
@@ -116,7 +262,7 @@ pub fn parse_command(index_session: &mut IndexSession, command: &str) {
                         return;
                     }
                 };
-                match index_session.btree.delete(key) {
+                match index_session.btree.delete(&key) {
                     Ok(_) => {
                         println!("Successfully deleted key {key}");
 
@@ -127,10 +273,396 @@ pub fn parse_command(index_session: &mut IndexSession, command: &str) {
                     Err(e) => println!("Failed to delete key {key}: {e}"),
                 }
             }
-            "SNAPSHOT" | "snapshot" => {
-                index_session.btree.snapshot().expect("Failed to snapshot");
+            "RANGE" | "range" => {
+                let (Some(lo), Some(hi)) = (
+                    parse_bound::<i32>(cmd.args.first()),
+                    parse_bound::<i32>(cmd.args.get(1)),
+                ) else {
+                    eprintln!("Error: Invalid bounds for RANGE (usage: btree range <lo|*> <hi|*>)");
+                    return;
+                };
+
+                let mut count = 0;
+                for item in index_session.btree.range((lo, hi)) {
+                    match item {
+                        Ok(item) => println!("{item}"),
+                        Err(e) => {
+                            eprintln!("Failed to scan range: {e}");
+                            return;
+                        }
+                    }
+                    count += 1;
+                }
+                println!("{count} item(s) in range");
+            }
+            "VIEW" | "view" => run_view(index_session, &cmd.args),
+            "RANK" | "rank" => {
+                let Some(key) = cmd.key else {
+                    eprintln!("Error: Missing key for RANK (usage: btree rank <key>)");
+                    return;
+                };
+                match index_session.btree.rank(&key) {
+                    Ok(rank) => println!("{rank} item(s) below key {key}"),
+                    Err(e) => eprintln!("Failed to rank key {key}: {e}"),
+                }
+            }
+            "SELECT" | "select" => {
+                let Some(k) = cmd.args.first().and_then(|k| k.parse::<u64>().ok()) else {
+                    eprintln!("Error: Missing position for SELECT (usage: btree select <k>)");
+                    return;
+                };
+                match index_session.btree.select(k) {
+                    Ok(item) => println!("{item}"),
+                    Err(IndexError::NotFound(_)) => println!("No item at position {k}"),
+                    Err(e) => eprintln!("Failed to select position {k}: {e}"),
+                }
+            }
+            "COUNT" | "count" => {
+                let (Some(lo), Some(hi)) = (
+                    parse_bound::<i32>(cmd.args.first()),
+                    parse_bound::<i32>(cmd.args.get(1)),
+                ) else {
+                    eprintln!("Error: Invalid bounds for COUNT (usage: btree count <lo|*> <hi|*>)");
+                    return;
+                };
+                match index_session.btree.count((lo, hi)) {
+                    Ok(count) => println!("{count} item(s) in range"),
+                    Err(e) => eprintln!("Failed to count range: {e}"),
+                }
+            }
+            "POOL" | "pool" => {
+                let Some(capacity) = cmd.args.first().and_then(|c| c.parse::<usize>().ok()) else {
+                    eprintln!(
+                        "Error: Missing capacity for POOL (usage: btree pool <frames> [lru|clock])"
+                    );
+                    return;
+                };
+                let policy: Box<dyn EvictionPolicy> =
+                    match cmd.args.get(1).map(|p| p.to_ascii_lowercase()).as_deref() {
+                        None | Some("lru") => Box::new(Lru::default()),
+                        Some("clock") => Box::new(Clock::default()),
+                        Some(other) => {
+                            eprintln!("Error: Unknown eviction policy {other}");
+                            return;
+                        }
+                    };
+
+                match index_session.btree.configure_buffer_pool(capacity, policy) {
+                    Ok(()) => println!("Buffer pool resized to {capacity} frames"),
+                    Err(e) => println!("Failed to configure buffer pool: {e}"),
+                }
+            }
+            "BULK" | "bulk" => {
+                let Some(path) = cmd.args.first() else {
+                    eprintln!("Error: Missing file for BULK (usage: btree bulk <file> [fill])");
+                    return;
+                };
+                let fill = match cmd.args.get(1).map(|f| f.parse::<f64>()) {
+                    None => 1.0,
+                    Some(Ok(fill)) => fill,
+                    Some(Err(_)) => {
+                        eprintln!("Error: Invalid fill factor {}", cmd.args[1]);
+                        return;
+                    }
+                };
+
+                let contents = match fs::read_to_string(path) {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        eprintln!("Failed to read {path}: {e}");
+                        return;
+                    }
+                };
+                let mut items = Vec::new();
+                for (number, line) in contents.lines().enumerate() {
+                    let mut parts = line.split_whitespace();
+                    match (parts.next().map(str::parse::<i32>), parts.next()) {
+                        (None, _) => continue,
+                        (Some(Ok(key)), Some(val)) => items.push(Item {
+                            key,
+                            val: val.to_string(),
+                        }),
+                        _ => {
+                            eprintln!("Error: Expected `<key> <value>` on line {}", number + 1);
+                            return;
+                        }
+                    }
+                }
+
+                match index_session.btree.bulk_load(items, fill) {
+                    Ok(count) => {
+                        println!("Bulk loaded {count} item(s)");
+                        match index_session.btree.snapshot() {
+                            Ok(flushed) => println!("Snapshot written, {flushed} page(s) flushed"),
+                            Err(e) => eprintln!("Failed to snapshot: {e}"),
+                        }
+                        if let Err(e) = visualizer.update(&index_session.btree) {
+                            eprintln!("Failed to update visualization: {e}");
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to bulk load: {e}"),
+                }
+            }
+            "WAL" | "wal" => {
+                let policy = match cmd.args.first().map(|p| p.to_ascii_lowercase()).as_deref() {
+                    Some("always") => SyncPolicy::Always,
+                    Some("never") => SyncPolicy::Never,
+                    Some(n) => match n.parse::<u32>() {
+                        Ok(n) if n > 0 => SyncPolicy::EveryN(n),
+                        _ => {
+                            eprintln!("Error: Invalid sync policy {n}");
+                            return;
+                        }
+                    },
+                    None => {
+                        eprintln!(
+                            "Error: Missing sync policy for WAL (usage: btree wal <always|never|N>)"
+                        );
+                        return;
+                    }
+                };
+
+                match index_session.btree.set_wal_sync_policy(policy) {
+                    Ok(()) => println!("WAL sync policy set to {policy:?}"),
+                    Err(e) => println!("Failed to set WAL sync policy: {e}"),
+                }
+            }
+            "STATS" | "stats" => {
+                println!("Buffer pool: {}", index_session.btree.buffer_pool_stats());
+                println!("Free pages: {}", index_session.btree.free_pages());
+            }
+            "CHECK" | "check" => {
+                let violations = match cmd.args.first() {
+                    None => index_session.btree.check(),
+                    Some(path) => match Btree::<i32, String>::check_file(path, 4096) {
+                        Ok(violations) => violations,
+                        Err(e) => {
+                            eprintln!("Failed to open {path}: {e}");
+                            return;
+                        }
+                    },
+                };
+                if violations.is_empty() {
+                    println!("No violations found");
+                }
+                for violation in &violations {
+                    println!("{violation}");
+                }
+            }
+            "EXPORT" | "export" | "IMPORT" | "import" => {
+                let export = cmd.index_function.eq_ignore_ascii_case("export");
+                let Some(path) = cmd.args.first() else {
+                    eprintln!(
+                        "Error: Missing file for {} (usage: btree {} <file> [json|csv])",
+                        cmd.index_function.to_ascii_uppercase(),
+                        cmd.index_function.to_ascii_lowercase()
+                    );
+                    return;
+                };
+                let format = match cmd.args.get(1) {
+                    Some(format) => match format.parse::<DumpFormat>() {
+                        Ok(format) => format,
+                        Err(e) => {
+                            eprintln!("Error: {e}");
+                            return;
+                        }
+                    },
+                    None if path.ends_with(".csv") => DumpFormat::Csv,
+                    None => DumpFormat::JsonLines,
+                };
+
+                if export {
+                    let result = File::create(path)
+                        .map_err(IndexError::from)
+                        .and_then(|file| index_session.btree.export(BufWriter::new(file), format));
+                    match result {
+                        Ok(count) => println!("Exported {count} item(s) to {path}"),
+                        Err(e) => eprintln!("Failed to export to {path}: {e}"),
+                    }
+                    return;
+                }
+
+                let result = File::open(path)
+                    .map_err(IndexError::from)
+                    .and_then(|file| index_session.btree.import(BufReader::new(file), format));
+                match result {
+                    Ok(count) => println!("Imported {count} item(s) from {path}"),
+                    Err(e) => eprintln!("Failed to import {path}: {e}"),
+                }
+                if let Err(e) = visualizer.update(&index_session.btree) {
+                    eprintln!("Failed to update visualization: {e}");
+                }
             }
+            "LOG" | "log" => match parse_observer(cmd.args.first()) {
+                Some(observer) => {
+                    index_session.btree.set_observer(observer);
+                    println!("Logging set to {}", cmd.args[0]);
+                }
+                None => eprintln!(
+                    "Error: Invalid level for LOG (usage: btree log <off|structure|detail>)"
+                ),
+            },
+            "SNAPSHOT" | "snapshot" => match index_session.btree.snapshot() {
+                Ok(flushed) => println!("Snapshot written, {flushed} page(s) flushed"),
+                Err(e) => eprintln!("Failed to snapshot: {e}"),
+            },
             _ => {}
         }
+    } else if cmd.index_type.as_str() == "BPLUS" || cmd.index_type.as_str() == "bplus" {
+        run_bplus(index_session, cmd);
+    } else if cmd.index_type.as_str() == "MULTI" || cmd.index_type.as_str() == "multi" {
+        run_multimap(index_session, cmd);
+    }
+}
+
+fn run_multimap(index_session: &mut IndexSession, cmd: Command<i32, String>) {
+    let tree = &mut index_session.multimap;
+    match cmd.index_function.as_str() {
+        "INSERT" | "insert" => {
+            let (Some(key), Some(val)) = (cmd.key, cmd.value) else {
+                eprintln!(
+                    "Error: Missing key or value for INSERT (usage: multi insert <key> <value>)"
+                );
+                return;
+            };
+            if let Err(e) = tree.insert(Item { key, val }) {
+                println!("Failed to insert key {key}: {e}");
+            }
+        }
+        "SEARCH" | "search" => {
+            let Some(key) = cmd.key else {
+                eprintln!("Error: Missing key for SEARCH");
+                return;
+            };
+            match tree.search_all(&key) {
+                Ok(vals) if vals.is_empty() => println!("Key not found"),
+                Ok(vals) => println!("Values {}", vals.join(", ")),
+                Err(e) => eprintln!("Failed to search key {key}: {e}"),
+            }
+        }
+        "DELETE" | "delete" => {
+            let Some(key) = cmd.key else {
+                eprintln!("Error: Missing key for DELETE (usage: multi delete <key> [value])");
+                return;
+            };
+            let result = match &cmd.value {
+                Some(val) => tree.delete_pair(&key, val),
+                None => tree.delete(&key),
+            };
+            match result {
+                Ok(()) => println!("Successfully deleted key {key}"),
+                Err(e) => println!("Failed to delete key {key}: {e}"),
+            }
+        }
+        "RANGE" | "range" => {
+            let (Some(lo), Some(hi)) = (
+                parse_bound::<i32>(cmd.args.first()),
+                parse_bound::<i32>(cmd.args.get(1)),
+            ) else {
+                eprintln!("Error: Invalid bounds for RANGE (usage: multi range <lo|*> <hi|*>)");
+                return;
+            };
+
+            let mut count = 0;
+            for item in tree.range((lo, hi)) {
+                match item {
+                    Ok(item) => println!("{item}"),
+                    Err(e) => {
+                        eprintln!("Failed to scan range: {e}");
+                        return;
+                    }
+                }
+                count += 1;
+            }
+            println!("{count} item(s) in range");
+        }
+        "LOG" | "log" => match parse_observer(cmd.args.first()) {
+            Some(observer) => {
+                tree.set_observer(observer);
+                println!("Logging set to {}", cmd.args[0]);
+            }
+            None => {
+                eprintln!("Error: Invalid level for LOG (usage: multi log <off|structure|detail>)")
+            }
+        },
+        "SNAPSHOT" | "snapshot" => match tree.snapshot() {
+            Ok(flushed) => println!("Snapshot written, {flushed} page(s) flushed"),
+            Err(e) => eprintln!("Failed to snapshot: {e}"),
+        },
+        _ => {}
+    }
+}
+
+fn run_bplus(index_session: &mut IndexSession, cmd: Command<i32, String>) {
+    let tree = &mut index_session.bplus;
+    match cmd.index_function.as_str() {
+        "INSERT" | "insert" => {
+            let (Some(key), Some(val)) = (cmd.key, cmd.value) else {
+                eprintln!(
+                    "Error: Missing key or value for INSERT (usage: bplus insert <key> <value>)"
+                );
+                return;
+            };
+            if let Err(e) = tree.insert(Item { key, val }) {
+                println!("Failed to insert key {key}: {e}");
+            }
+        }
+        "SEARCH" | "search" => {
+            let Some(key) = cmd.key else {
+                eprintln!("Error: Missing key for SEARCH");
+                return;
+            };
+            match tree.search(&key) {
+                Ok(val) => println!("Value {val}"),
+                Err(IndexError::NotFound(_)) => println!("Key not found"),
+                Err(e) => eprintln!("Failed to search key {key}: {e}"),
+            }
+        }
+        "DELETE" | "delete" => {
+            let Some(key) = cmd.key else {
+                eprintln!("Error: Missing key for DELETE");
+                return;
+            };
+            match tree.delete(&key) {
+                Ok(()) => println!("Successfully deleted key {key}"),
+                Err(e) => println!("Failed to delete key {key}: {e}"),
+            }
+        }
+        "RANGE" | "range" => {
+            let (Some(lo), Some(hi)) = (
+                parse_bound::<i32>(cmd.args.first()),
+                parse_bound::<i32>(cmd.args.get(1)),
+            ) else {
+                eprintln!("Error: Invalid bounds for RANGE (usage: bplus range <lo|*> <hi|*>)");
+                return;
+            };
+
+            let mut count = 0;
+            for item in tree.range((lo, hi)) {
+                match item {
+                    Ok(item) => println!("{item}"),
+                    Err(e) => {
+                        eprintln!("Failed to scan range: {e}");
+                        return;
+                    }
+                }
+                count += 1;
+            }
+            println!("{count} item(s) in range");
+        }
+        "LOG" | "log" => match parse_observer(cmd.args.first()) {
+            Some(observer) => {
+                tree.set_observer(observer);
+                println!("Logging set to {}", cmd.args[0]);
+            }
+            None => {
+                eprintln!("Error: Invalid level for LOG (usage: bplus log <off|structure|detail>)")
+            }
+        },
+        "SNAPSHOT" | "snapshot" => match tree.snapshot() {
+            Ok(flushed) => println!("Snapshot written, {flushed} page(s) flushed"),
+            Err(e) => eprintln!("Failed to snapshot: {e}"),
+        },
+        _ => {}
     }
 }